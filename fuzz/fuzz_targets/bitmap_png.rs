@@ -0,0 +1,18 @@
+#![no_main]
+
+use galaxy_server_rust::export::bitmap_from_png;
+use libfuzzer_sys::fuzz_target;
+
+// Decodes the input as image for a pictorial puzzle, whose board size is
+// taken from the first two bytes. Bitmaps have to have the size of the board.
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 2 {
+        return;
+    }
+    let width = usize::from(data[0] % 30) + 1;
+    let height = usize::from(data[1] % 30) + 1;
+    if let Ok(bitmap) = bitmap_from_png(&data[2..], width, height) {
+        assert_eq!(bitmap.len(), width);
+        assert!(bitmap.iter().all(|column| column.len() == height));
+    }
+});
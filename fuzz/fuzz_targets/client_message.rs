@@ -0,0 +1,19 @@
+#![no_main]
+
+use galaxy_server_rust::protocol::ClientMessage;
+use libfuzzer_sys::fuzz_target;
+
+// Decodes text frames like the network does. Messages which decode have to
+// decode to the same message again after encoding them.
+fuzz_target!(|data: &[u8]| {
+    let text = match std::str::from_utf8(data) {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+    if let Ok(message) = serde_json::from_str::<ClientMessage>(text) {
+        let json = serde_json::to_string(&message).expect("Encoding a message failed");
+        let decoded: ClientMessage =
+            serde_json::from_str(&json).expect("Decoding an encoded message failed");
+        assert_eq!(decoded, message);
+    }
+});
@@ -0,0 +1,40 @@
+#![no_main]
+
+use galaxy_server_rust::galaxy::Galaxy;
+use galaxy_server_rust::gamegen::{Difficulty, GenerationConfig};
+use galaxy_server_rust::http::{self, Incoming};
+use libfuzzer_sys::fuzz_target;
+use std::sync::OnceLock;
+use tokio::runtime::{Builder, Runtime};
+
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+static GALAXY: OnceLock<Galaxy> = OnceLock::new();
+
+// Reads the first bytes of a connection like the network does and answers
+// HTTP requests, except those which generate boards because they are slow
+fuzz_target!(|data: &[u8]| {
+    let runtime = RUNTIME.get_or_init(|| {
+        Builder::new_current_thread()
+            .build()
+            .expect("Starting runtime failed")
+    });
+    let mut stream = data;
+    let request = match runtime.block_on(http::read_request(&mut stream)) {
+        Ok(Incoming::Http(request)) => request,
+        _ => return,
+    };
+    if request.generates() {
+        return;
+    }
+    let galaxy = GALAXY.get_or_init(|| {
+        Galaxy::new(&GenerationConfig {
+            width: 3,
+            height: 3,
+            difficulty: Difficulty::Easy,
+            seed: 1,
+        })
+        .expect("Generating the board failed")
+    });
+    let response = http::respond(galaxy, &request);
+    assert_ne!(response.status, 500, "{}", response.body);
+});
@@ -0,0 +1,37 @@
+#![no_main]
+
+use galaxy_server_rust::galaxy::Galaxy;
+use galaxy_server_rust::gamegen::{Difficulty, GenerationConfig};
+use galaxy_server_rust::http::{self, Request};
+use libfuzzer_sys::fuzz_target;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+static GALAXY: OnceLock<Galaxy> = OnceLock::new();
+
+// Submits the input as puzzle with a solution to the validation endpoint,
+// which parses stored and inline puzzles
+fuzz_target!(|data: &[u8]| {
+    let galaxy = GALAXY.get_or_init(|| {
+        Galaxy::new(&GenerationConfig {
+            width: 3,
+            height: 3,
+            difficulty: Difficulty::Easy,
+            seed: 1,
+        })
+        .expect("Generating the board failed")
+    });
+    let request = Request {
+        method: "POST".to_owned(),
+        path: "/validate".to_owned(),
+        query: HashMap::new(),
+        body: data.to_vec(),
+    };
+    let response = http::respond(galaxy, &request);
+    assert!(
+        matches!(response.status, 200 | 400 | 404),
+        "Unexpected status {}: {}",
+        response.status,
+        response.body
+    );
+});
@@ -0,0 +1,24 @@
+#![no_main]
+
+use galaxy_server_rust::export::Style;
+use galaxy_server_rust::replay::Replay;
+use libfuzzer_sys::fuzz_target;
+
+// Reads a replay file like the replay command does. Replays which pass the
+// check have to render every frame.
+fuzz_target!(|data: &[u8]| {
+    let replay = match serde_json::from_slice::<Replay>(data) {
+        Ok(replay) => replay,
+        Err(_) => return,
+    };
+    if replay.check().is_err() {
+        return;
+    }
+    // The first, a middle and the last frame, moves may be many
+    for moves in [0, replay.moves.len() / 2, replay.moves.len()] {
+        replay.text(moves);
+        replay
+            .svg(moves, &Style::default())
+            .expect("Rendering a checked replay failed");
+    }
+});
@@ -0,0 +1,58 @@
+#![no_main]
+
+use galaxy_server_rust::gamegen::{self, Difficulty, GenerationConfig};
+use galaxy_server_rust::solver::{self, Assignment, Puzzle};
+use libfuzzer_sys::fuzz_target;
+use std::convert::TryInto;
+
+const DIFFICULTIES: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+// Generates a board from the first bytes and checks that the solver agrees
+// with the validator. The solutions of the generator and the solver have to
+// be valid. The remaining bytes reassign fields of the generated solution,
+// which the solver has to accept exactly if the validator does. Configs the
+// generator fails for are skipped.
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 11 {
+        return;
+    }
+    let width = usize::from(data[0] % 7) + 1;
+    let height = usize::from(data[1] % 7) + 1;
+    let config = GenerationConfig {
+        width,
+        height,
+        difficulty: DIFFICULTIES[usize::from(data[2]) % DIFFICULTIES.len()],
+        seed: u64::from_le_bytes(data[3..11].try_into().unwrap()),
+    };
+    let board = match gamegen::try_generate(&config) {
+        Ok(board) => board,
+        Err(_) => return,
+    };
+    let puzzle = Puzzle {
+        width,
+        height,
+        dots: &board.dots,
+    };
+    let complete = |galaxies: &[Vec<usize>]| -> Assignment {
+        let columns = galaxies.iter();
+        columns
+            .map(|column| column.iter().copied().map(Some).collect())
+            .collect()
+    };
+
+    let generated = complete(&board.galaxies);
+    assert_eq!(solver::validate(&puzzle, &generated), Ok(()));
+    let solution = puzzle
+        .solve(&puzzle.empty_assignment())
+        .expect("Generated board has no solution");
+    assert_eq!(solver::validate(&puzzle, &complete(&solution)), Ok(()));
+
+    let mut changed = generated;
+    for pair in data[11..].chunks_exact(2) {
+        let field = usize::from(pair[0]) % (width * height);
+        let dot = usize::from(pair[1]) % board.dots.len();
+        changed[field % width][field / width] = Some(dot);
+    }
+    let valid = solver::validate(&puzzle, &changed).is_ok();
+    assert_eq!(puzzle.solve(&changed).is_some(), valid, "{:?}", changed);
+});
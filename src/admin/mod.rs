@@ -0,0 +1,273 @@
+use crate::galaxy::Galaxy;
+use crate::protocol::{AdminCommand, AdminRequest, AdminResponse};
+use log::{info, warn};
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::{task, time};
+
+/// Requests with a wrong token are answered this late, which slows down
+/// guessing it
+const FAILURE_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest line of a request
+const MAX_REQUEST: u64 = 64 * 1024;
+
+/// Compares the tokens in constant time, so timing doesn't reveal how much
+/// of the token was right
+fn authorized(token: &str, expected: &str) -> bool {
+    let difference = token
+        .bytes()
+        .zip(expected.bytes())
+        .fold(0, |difference, (a, b)| difference | (a ^ b));
+    token.len() == expected.len() && difference == 0
+}
+
+async fn send(
+    stream: &mut (impl AsyncWriteExt + Unpin),
+    response: &AdminResponse,
+) -> io::Result<()> {
+    let mut line = serde_json::to_string(response).expect("Serializing response failed");
+    line.push('\n');
+    stream.write_all(line.as_bytes()).await
+}
+
+/// Executes the commands of an admin connection, which sends one JSON
+/// request per line and gets one response per line. The connection is
+/// closed after a request with a wrong token.
+async fn handle(
+    galaxy: Arc<Galaxy>,
+    stream: TcpStream,
+    address: SocketAddr,
+    token: Arc<String>,
+) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    loop {
+        let mut line = String::new();
+        let read = (&mut reader).take(MAX_REQUEST).read_line(&mut line).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        if !line.ends_with('\n') && read as u64 == MAX_REQUEST {
+            let message = "Request too long".to_owned();
+            return send(&mut writer, &AdminResponse::Error { message }).await;
+        }
+
+        let request: AdminRequest = match serde_json::from_str(&line) {
+            Ok(request) => request,
+            Err(error) => {
+                let message = format!("Invalid request: {}", error);
+                send(&mut writer, &AdminResponse::Error { message }).await?;
+                continue;
+            }
+        };
+        if !authorized(&request.token, &token) {
+            warn!("Rejected admin request with a wrong token from {}", address);
+            time::sleep(FAILURE_DELAY).await;
+            let message = "Wrong token".to_owned();
+            return send(&mut writer, &AdminResponse::Error { message }).await;
+        }
+
+        info!("Admin at {} sent {:?}", address, request.command);
+        let galaxy = Arc::clone(&galaxy);
+        let response = task::spawn_blocking(move || galaxy.administrate(request.command))
+            .await
+            .unwrap_or_else(|error| AdminResponse::Error {
+                message: error.to_string(),
+            });
+        send(&mut writer, &response).await?;
+    }
+}
+
+/// Serves the admin channel for clients which know the token. Runs until
+/// the runtime shuts down.
+pub async fn serve(galaxy: Arc<Galaxy>, listener: TcpListener, token: String) {
+    let token = Arc::new(token);
+    loop {
+        match listener.accept().await {
+            Ok((stream, address)) => {
+                let connection = handle(Arc::clone(&galaxy), stream, address, Arc::clone(&token));
+                task::spawn(async move {
+                    if let Err(error) = connection.await {
+                        warn!("Admin connection from {} failed: {}", address, error);
+                    }
+                });
+            }
+            Err(error) => {
+                warn!("Accepting an admin connection failed: {}", error);
+                time::sleep(FAILURE_DELAY).await;
+            }
+        }
+    }
+}
+
+/// Sends a single command to the admin channel at the address
+pub async fn request(
+    address: &str,
+    token: &str,
+    command: AdminCommand,
+) -> io::Result<AdminResponse> {
+    let mut stream = TcpStream::connect(address).await?;
+    let request = AdminRequest {
+        token: token.to_owned(),
+        command,
+    };
+    let mut line = serde_json::to_string(&request).expect("Serializing request failed");
+    line.push('\n');
+    stream.write_all(line.as_bytes()).await?;
+
+    let mut answer = String::new();
+    BufReader::new(stream).read_line(&mut answer).await?;
+    serde_json::from_str(&answer).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+impl fmt::Display for AdminResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AdminResponse::Rooms { rooms } => {
+                for room in rooms {
+                    let capacity = room
+                        .capacity
+                        .map_or_else(|| "-".to_owned(), |capacity| capacity.to_string());
+                    writeln!(
+                        f,
+                        "{:>4}  {:<20} {:?} {}x{}, {}/{} players{}",
+                        room.room,
+                        room.name,
+                        room.mode,
+                        room.width,
+                        room.height,
+                        room.players,
+                        capacity,
+                        if room.locked { ", locked" } else { "" }
+                    )?;
+                }
+                Ok(())
+            }
+            AdminResponse::Players { players } => {
+                for player in players {
+                    writeln!(
+                        f,
+                        "{:>4}  {:<20} {} connections in rooms {:?}",
+                        player.player, player.name, player.connections, player.rooms
+                    )?;
+                }
+                Ok(())
+            }
+            AdminResponse::Done { message } => writeln!(f, "{}", message),
+            AdminResponse::Error { message } => writeln!(f, "Error: {}", message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::galaxy::{outbox, Mode, Settings, MAIN_ROOM};
+    use crate::gamegen::Difficulty;
+    use crate::protocol::{ClientMessage, ServerMessage};
+    use crate::secret::Secret;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn admins_kick_ban_and_announce() {
+        let galaxy = Arc::new(Galaxy::for_tests());
+        let (sender, mut inbox) = outbox(64);
+        let client = galaxy.connect(sender);
+        let login = |galaxy: &Galaxy| {
+            galaxy.handle(
+                client,
+                ClientMessage::Login {
+                    name: "troll".to_owned(),
+                    passphrase: Secret::default(),
+                },
+            )
+        };
+        login(&galaxy);
+        let player = galaxy.player_of(client).unwrap();
+        inbox.try_iter().count();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(serve(Arc::clone(&galaxy), listener, "secret".to_owned()));
+        let admin = |token: &'static str, command| request(&address, token, command);
+
+        let wrong = admin("guess", AdminCommand::Players).await.unwrap();
+        assert!(matches!(wrong, AdminResponse::Error { .. }));
+        match admin("secret", AdminCommand::Players).await.unwrap() {
+            AdminResponse::Players { players } => {
+                assert_eq!(players.len(), 1);
+                assert_eq!(
+                    (players[0].player, players[0].rooms.clone()),
+                    (player, vec![MAIN_ROOM])
+                );
+            }
+            response => panic!("Unexpected response {:?}", response),
+        }
+
+        let message = "Restart soon".to_owned();
+        admin("secret", AdminCommand::Announce { message })
+            .await
+            .unwrap();
+        assert!(matches!(
+            inbox.try_recv(),
+            Ok(ServerMessage::Announcement { .. })
+        ));
+
+        let minutes = Some(u64::MAX);
+        let overlong = admin("secret", AdminCommand::Ban { player, minutes })
+            .await
+            .unwrap();
+        assert!(matches!(overlong, AdminResponse::Error { .. }));
+        assert_eq!(galaxy.player_of(client), Some(player));
+
+        let minutes = Some(5);
+        admin("secret", AdminCommand::Ban { player, minutes })
+            .await
+            .unwrap();
+        assert!(matches!(inbox.try_recv(), Ok(ServerMessage::Kicked { .. })));
+        assert_eq!(galaxy.player_of(client), None);
+        login(&galaxy);
+        assert!(matches!(inbox.try_recv(), Ok(ServerMessage::Error { .. })));
+
+        admin("secret", AdminCommand::Unban { player })
+            .await
+            .unwrap();
+        login(&galaxy);
+        assert!(matches!(
+            inbox.try_recv(),
+            Ok(ServerMessage::Welcome { .. })
+        ));
+
+        let create = ClientMessage::CreateRoom {
+            name: "Hideout".to_owned(),
+            width: 3,
+            height: 3,
+            difficulty: Difficulty::Easy,
+            seed: Some(1),
+            mode: Mode::Coop,
+            settings: Settings::default(),
+            password: None,
+            capacity: None,
+        };
+        galaxy.handle(client, create);
+        let room = galaxy.room_of(client).unwrap();
+        inbox.try_iter().count();
+        admin("secret", AdminCommand::CloseRoom { room })
+            .await
+            .unwrap();
+        assert_eq!(inbox.try_recv(), Ok(ServerMessage::Left { room }));
+        assert_eq!(galaxy.room_of(client), None);
+
+        let main = admin("secret", AdminCommand::CloseRoom { room: MAIN_ROOM })
+            .await
+            .unwrap();
+        assert!(matches!(main, AdminResponse::Error { .. }));
+        let save = admin("secret", AdminCommand::Save).await.unwrap();
+        assert!(matches!(save, AdminResponse::Error { .. }));
+    }
+}
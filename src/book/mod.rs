@@ -0,0 +1,113 @@
+use crate::export::{pdf, Picture, Style};
+use crate::gamegen::{self, Difficulty, GenerationConfig};
+
+/// Margin of the pages in points
+const PAGE_MARGIN: f64 = 50.0;
+/// Space below a grid for its caption in points
+const CAPTION_HEIGHT: f64 = 60.0;
+
+/// Describes a batch of puzzles which are printed together
+pub struct BookConfig {
+    pub count: usize,
+    pub width: usize,
+    pub height: usize,
+    pub difficulty: Difficulty,
+    /// Seed of the first puzzle, the following puzzles use the next seeds
+    pub seed: u64,
+}
+
+/// Draws a single board with its caption on a new page
+fn board_page(picture: &Picture, caption: &str, details: &str) -> Result<pdf::Page, String> {
+    let mut page = pdf::Page::new(pdf::A4);
+    let width = pdf::A4.0 - 2.0 * PAGE_MARGIN;
+    let height = pdf::A4.1 - 2.0 * PAGE_MARGIN - CAPTION_HEIGHT;
+    page.picture(
+        picture,
+        &Style::default(),
+        (PAGE_MARGIN, PAGE_MARGIN, width, height),
+    )?;
+
+    let center = page.width() / 2.0;
+    let top = pdf::A4.1 - PAGE_MARGIN - CAPTION_HEIGHT + 15.0;
+    page.centered_text(center, top, 14.0, caption);
+    page.centered_text(center, top + 22.0, 10.0, details);
+    Ok(page)
+}
+
+/// Generates a batch of puzzles and lays them out as book. Every puzzle gets
+/// its own page, the solutions follow after all puzzles. A puzzle whose seed
+/// the generator fails for is replaced by one of the following seeds.
+///
+/// @return PDF file content, an error naming the seed the generator failed for
+pub fn generate(config: &BookConfig) -> Result<Vec<u8>, String> {
+    let mut puzzle_pages = Vec::new();
+    let mut solution_pages = Vec::new();
+    for i in 0..config.count {
+        let seed = config.seed.wrapping_add(i as u64);
+        let (board, generated) = gamegen::generate_reliably(&GenerationConfig {
+            width: config.width,
+            height: config.height,
+            difficulty: config.difficulty,
+            seed,
+        })
+        .ok_or_else(|| format!("Generating the puzzle with seed {} failed", seed))?;
+        let number = i + 1;
+        // The id looks the puzzle up at /puzzle/<id> and on the leaderboards
+        let details = format!(
+            "{}x{}, {}, seed {}, id {}",
+            config.width,
+            config.height,
+            config.difficulty,
+            generated.seed,
+            board.id()
+        );
+
+        puzzle_pages.push(board_page(
+            &Picture::puzzle(&board),
+            &format!("Puzzle {}", number),
+            &details,
+        )?);
+        solution_pages.push(board_page(
+            &Picture::solution(&board),
+            &format!("Solution of puzzle {}", number),
+            &details,
+        )?);
+    }
+
+    let mut document = pdf::Document::new();
+    for page in puzzle_pages.into_iter().chain(solution_pages) {
+        document.add_page(page);
+    }
+    Ok(document.write())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn books_have_a_page_per_puzzle_and_solution() {
+        let config = BookConfig {
+            count: 2,
+            width: 5,
+            height: 4,
+            difficulty: Difficulty::Normal,
+            seed: 7,
+        };
+        let file = String::from_utf8(generate(&config).unwrap()).unwrap();
+
+        assert!(file.contains("/Count 4 >>"));
+        assert_eq!(file.matches("/Type /Page ").count(), 4);
+        for seed in 7..9 {
+            let (board, _) = gamegen::generate_reliably(&GenerationConfig {
+                width: 5,
+                height: 4,
+                difficulty: Difficulty::Normal,
+                seed,
+            })
+            .unwrap();
+            let caption = format!("seed {}, id {}", seed, board.id());
+            assert_eq!(file.matches(&caption).count(), 2);
+        }
+    }
+}
@@ -0,0 +1,354 @@
+use crate::galaxy::MAX_BOARD_SIZE;
+use crate::gamegen::Difficulty;
+use crate::limits::LimitConfig;
+use crate::logger;
+use crate::tls::TlsConfig;
+use log::LevelFilter;
+use serde::Deserialize;
+use std::fs;
+use std::net::ToSocketAddrs;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// Environment variables with this prefix override keys of the config file.
+/// Nested keys are separated by `__`, so `GALAXY__LIMITS__MOVES__BURST`
+/// overrides `limits.moves.burst`.
+pub const ENV_PREFIX: &str = "GALAXY__";
+
+/// Settings of the server, read from a TOML file. Missing keys have their
+/// defaults and unknown ones are rejected.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Address of the WebSockets and the HTTP API
+    pub address: String,
+    /// Log file which keeps players and games across restarts
+    pub storage: Option<PathBuf>,
+    /// Seconds clients get to disconnect when the server shuts down
+    pub shutdown_deadline: u64,
+    pub generation: GenerationSettings,
+    pub tls: Option<TlsConfig>,
+    pub admin: Option<AdminConfig>,
+    pub metrics: Option<MetricsConfig>,
+    pub limits: LimitConfig,
+    pub log: LogConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            address: "0.0.0.0:9001".to_owned(),
+            storage: None,
+            shutdown_deadline: 10,
+            generation: GenerationSettings::default(),
+            tls: None,
+            admin: None,
+            metrics: None,
+            limits: LimitConfig::default(),
+            log: LogConfig::default(),
+        }
+    }
+}
+
+/// Board of the main room and the boards clients may generate
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GenerationSettings {
+    /// Width of the first board
+    pub width: usize,
+    /// Height of the first board
+    pub height: usize,
+    pub difficulty: Difficulty,
+    /// Seed of the first board, which is random if missing
+    pub seed: Option<u64>,
+    /// Biggest width and height clients may ask for, at most MAX_BOARD_SIZE
+    pub max_size: usize,
+}
+
+impl Default for GenerationSettings {
+    fn default() -> Self {
+        GenerationSettings {
+            width: 10,
+            height: 10,
+            difficulty: Difficulty::Normal,
+            seed: None,
+            max_size: MAX_BOARD_SIZE,
+        }
+    }
+}
+
+/// Admin channel, see the admin module
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdminConfig {
+    pub address: String,
+    pub token: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetricsConfig {
+    pub address: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub format: logger::Format,
+    pub level: LevelFilter,
+    /// Logs the grids of the generator at trace level
+    pub grids: bool,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            format: logger::Format::Text,
+            level: LevelFilter::Info,
+            grids: false,
+        }
+    }
+}
+
+/// Parses the value of an environment variable like a TOML value, so numbers
+/// and booleans work. Anything else is taken as string.
+fn parse_env(raw: &str) -> Value {
+    format!("value = {}", raw)
+        .parse::<Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(raw.to_owned()))
+}
+
+/// Sets the key of the table, creating the tables on its way
+///
+/// @return Error message if a key on the way isn't a table
+fn set(table: &mut Table, path: &[String], value: Value) -> Result<(), String> {
+    let (last, parents) = path.split_last().expect("Empty key");
+    let mut table = table;
+    for (index, key) in parents.iter().enumerate() {
+        let entry = table
+            .entry(key.clone())
+            .or_insert_with(|| Value::Table(Table::new()));
+        table = entry
+            .as_table_mut()
+            .ok_or_else(|| format!("{}: is no table", path[..=index].join(".")))?;
+    }
+    table.insert(last.clone(), value);
+    Ok(())
+}
+
+impl Config {
+    /// Reads the config file if there is one and applies the overrides of
+    /// the environment, see ENV_PREFIX
+    ///
+    /// @return Config, which still has to be validated, or an error which
+    /// names the offending key
+    pub fn load<I>(path: Option<&Path>, variables: I) -> Result<Config, String>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut table = match path {
+            Some(path) => {
+                let content = fs::read_to_string(path)
+                    .map_err(|error| format!("Reading {} failed: {}", path.display(), error))?;
+                content
+                    .parse::<Table>()
+                    .map_err(|error| format!("{}: {}", path.display(), error))?
+            }
+            None => Table::new(),
+        };
+
+        // Strings which look like numbers are retried as strings if the key
+        // needs one, an admin token may consist of digits for example
+        let mut raw_values = Vec::new();
+        for (name, raw) in variables {
+            let key = match name.strip_prefix(ENV_PREFIX) {
+                Some(key) => key.to_lowercase(),
+                None => continue,
+            };
+            let path: Vec<String> = key.split("__").map(str::to_owned).collect();
+            if path.iter().any(String::is_empty) {
+                return Err(format!("{}: invalid key", name));
+            }
+            set(&mut table, &path, parse_env(&raw))
+                .map_err(|error| format!("{}: {}", name, error))?;
+            raw_values.push((path, raw));
+        }
+
+        loop {
+            match serde_path_to_error::deserialize(Value::Table(table.clone())) {
+                Ok(config) => return Ok(config),
+                Err(error) => {
+                    let key = error.path().to_string();
+                    let retried = raw_values
+                        .iter()
+                        .position(|(path, _)| path.join(".") == key)
+                        .map(|index| raw_values.swap_remove(index));
+                    match retried {
+                        Some((path, raw)) => set(&mut table, &path, Value::String(raw))?,
+                        None => {
+                            // The message repeats the key on a line of its own
+                            let message = error.inner().message();
+                            let message = message.lines().next().unwrap_or_default();
+                            return Err(format!("{}: {}", key, message));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Checks the values which deserialize but make no sense
+    pub fn validate(&self) -> Result<(), String> {
+        let generation = &self.generation;
+        if generation.max_size == 0 || generation.max_size > MAX_BOARD_SIZE {
+            return Err(format!(
+                "generation.max_size: has to be between 1 and {}",
+                MAX_BOARD_SIZE
+            ));
+        }
+        for (key, size) in [("width", generation.width), ("height", generation.height)] {
+            if size == 0 || size > generation.max_size {
+                return Err(format!(
+                    "generation.{}: has to be between 1 and generation.max_size",
+                    key
+                ));
+            }
+        }
+        let limits = &self.limits;
+        let rates = [
+            ("connections", limits.connections),
+            ("messages", limits.messages),
+            ("moves", limits.moves),
+            ("generations", limits.generations),
+            ("credentials", limits.credentials),
+            ("strikes", limits.strikes),
+        ];
+        for (key, rate) in rates {
+            if rate.burst.is_nan() || rate.burst < 1.0 {
+                return Err(format!("limits.{}.burst: has to be at least 1", key));
+            }
+            if rate.per_second.is_nan() || rate.per_second <= 0.0 {
+                return Err(format!("limits.{}.per_second: has to be positive", key));
+            }
+        }
+        let mut addresses = vec![("address", &self.address)];
+        if let Some(admin) = &self.admin {
+            if admin.token.is_empty() {
+                return Err("admin.token: must not be empty".to_owned());
+            }
+            addresses.push(("admin.address", &admin.address));
+        }
+        if let Some(metrics) = &self.metrics {
+            addresses.push(("metrics.address", &metrics.address));
+        }
+        for (key, address) in addresses {
+            if let Err(error) = address.to_socket_addrs() {
+                return Err(format!("{}: {}", key, error));
+            }
+        }
+        if let Some(tls) = &self.tls {
+            tls.check()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::Rate;
+    use std::time::Duration;
+
+    fn load(content: &str, variables: &[(&str, &str)]) -> Result<Config, String> {
+        let path =
+            std::env::temp_dir().join(format!("galaxy-config-{}.toml", rand::random::<u64>()));
+        fs::write(&path, content).unwrap();
+        let variables = variables
+            .iter()
+            .map(|&(name, value)| (name.to_owned(), value.to_owned()));
+        let config = Config::load(Some(&path), variables);
+        fs::remove_file(&path).unwrap();
+        let config = config?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    #[test]
+    fn loads_files_with_overrides_and_names_offending_keys() {
+        let content = r#"
+            address = "127.0.0.1:9001"
+            storage = "galaxy.log"
+
+            [generation]
+            width = 12
+            difficulty = "hard"
+
+            [admin]
+            address = "127.0.0.1:9002"
+            token = "secret"
+
+            [limits]
+            moves = { burst = 5, per_second = 2.5 }
+            ban = 30
+
+            [log]
+            format = "json"
+            level = "debug"
+        "#;
+        let config = load(
+            content,
+            &[
+                ("GALAXY__GENERATION__HEIGHT", "8"),
+                ("GALAXY__ADMIN__TOKEN", "1234"),
+                ("GALAXY_ADMIN_TOKEN", "ignored"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.address, "127.0.0.1:9001");
+        assert_eq!(config.storage, Some(PathBuf::from("galaxy.log")));
+        assert_eq!((config.generation.width, config.generation.height), (12, 8));
+        assert_eq!(config.generation.difficulty, Difficulty::Hard);
+        assert_eq!(config.admin.unwrap().token, "1234");
+        assert_eq!(
+            config.limits.moves,
+            Rate {
+                burst: 5.0,
+                per_second: 2.5
+            }
+        );
+        assert_eq!(config.limits.ban, Duration::from_secs(30));
+        assert_eq!(config.limits.messages, LimitConfig::default().messages);
+        assert_eq!(config.log.format, logger::Format::Json);
+        assert_eq!(config.log.level, LevelFilter::Debug);
+
+        let error = load("[limits.moves]\nburts = 5\n", &[]).unwrap_err();
+        assert!(
+            error.starts_with("limits.moves.burts: unknown field"),
+            "{}",
+            error
+        );
+        let error = load("", &[("GALAXY__GENERATION__WIDTH", "wide")]).unwrap_err();
+        assert!(
+            error.starts_with("generation.width: invalid type"),
+            "{}",
+            error
+        );
+        let error = load("[generation]\nwidth = 31\n", &[]).unwrap_err();
+        assert!(error.starts_with("generation.width: "), "{}", error);
+        let error = load("[generation]\nmax_size = 100\n", &[]).unwrap_err();
+        assert!(error.starts_with("generation.max_size: "), "{}", error);
+        let error = load("address = [", &[]).unwrap_err();
+        assert!(error.contains("line 1"), "{}", error);
+        let error = load("[metrics]\naddress = \"9003\"\n", &[]).unwrap_err();
+        assert!(error.starts_with("metrics.address: "), "{}", error);
+        let missing = r#"
+            [tls]
+            certificate = "/nonexistent/cert.pem"
+            key = "/nonexistent/key.pem"
+        "#;
+        let error = load(missing, &[]).unwrap_err();
+        assert!(error.starts_with("tls.certificate: "), "{}", error);
+    }
+}
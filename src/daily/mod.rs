@@ -0,0 +1,154 @@
+use crate::gamegen::{self, Board, Difficulty, GenerationConfig};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// There is a daily puzzle for every difficulty
+const DIFFICULTIES: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// @return Days since the Unix epoch in UTC
+pub fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() / SECONDS_PER_DAY)
+        .unwrap_or(0)
+}
+
+/// Formats days since the Unix epoch as ISO date
+///
+/// @return Date like 2021-10-24
+pub fn date(day: u64) -> String {
+    // Civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = day + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day_of_month = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day_of_month)
+}
+
+/// Mixes the bits of a number, see splitmix64
+fn mix(mut value: u64) -> u64 {
+    value = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+}
+
+/// Derives the board of a day. It only depends on the date and difficulty,
+/// so everybody gets the same board on the same day.
+pub fn config(day: u64, difficulty: Difficulty) -> GenerationConfig {
+    let size = match difficulty {
+        Difficulty::Easy => 7,
+        Difficulty::Normal => 10,
+        Difficulty::Hard => 15,
+    };
+    GenerationConfig {
+        width: size,
+        height: size,
+        difficulty,
+        seed: mix(mix(day) ^ difficulty as u64),
+    }
+}
+
+/// Cache of the daily puzzles, which are generated in advance
+#[derive(Default)]
+pub struct Daily {
+    boards: Mutex<HashMap<(u64, Difficulty), Board>>,
+}
+
+impl Daily {
+    /// @return Board of the day, which is generated if it isn't cached,
+    /// None if generating it failed
+    pub fn board(&self, day: u64, difficulty: Difficulty) -> Option<Board> {
+        if let Some(board) = self.boards.lock().unwrap().get(&(day, difficulty)) {
+            return Some(board.clone());
+        }
+
+        // The lock isn't held during generation, so cached boards stay
+        // available
+        let (board, _) = gamegen::generate_reliably(&config(day, difficulty))?;
+        self.boards
+            .lock()
+            .unwrap()
+            .insert((day, difficulty), board.clone());
+        Some(board)
+    }
+
+    /// @return Cached board with the puzzle id, see Board::id
+    pub fn find(&self, puzzle: u64) -> Option<Board> {
+        let boards = self.boards.lock().unwrap();
+        boards.values().find(|board| board.id() == puzzle).cloned()
+    }
+
+    /// Generates all puzzles of the day and forgets older ones
+    pub fn prepare(&self, day: u64) {
+        for &difficulty in &DIFFICULTIES {
+            if self.board(day, difficulty).is_none() {
+                warn!(
+                    "Generating the {} daily puzzle of {} failed",
+                    difficulty,
+                    date(day)
+                );
+            }
+        }
+        self.boards
+            .lock()
+            .unwrap()
+            .retain(|&(board_day, _), _| board_day >= day);
+        info!("Prepared the daily puzzles of {}", date(day));
+    }
+
+    /// Prepares the puzzles of today in the background and then those of
+    /// every following day when it starts.
+    pub fn schedule(self: Arc<Self>) {
+        thread::spawn(move || loop {
+            self.prepare(today());
+
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            let next_day = (now / SECONDS_PER_DAY + 1) * SECONDS_PER_DAY;
+            thread::sleep(Duration::from_secs(next_day - now));
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_dates() {
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(11_016), "2000-02-29");
+        assert_eq!(date(20_745), "2026-10-19");
+    }
+
+    #[test]
+    fn boards_depend_on_the_day() {
+        let first = config(20_745, Difficulty::Easy);
+        assert_eq!(config(20_745, Difficulty::Easy), first);
+        assert_ne!(config(20_746, Difficulty::Easy).seed, first.seed);
+        assert_ne!(config(20_745, Difficulty::Hard).seed, first.seed);
+
+        let daily = Daily::default();
+        let board = daily.board(20_745, Difficulty::Easy).unwrap();
+        assert_eq!((board.width, board.height), (7, 7));
+        assert_eq!(daily.board(20_745, Difficulty::Easy), Some(board));
+    }
+}
@@ -0,0 +1,113 @@
+use crate::gamegen::Bitmap;
+use std::ops::Range;
+
+/// Reads a PNG image and scales it to a bitmap of the given size. Every
+/// field becomes black, if the average of the pixels it covers is rather dark.
+///
+/// @return Bitmap with true for black fields
+pub fn bitmap_from_png(
+    data: &[u8],
+    width: usize,
+    height: usize,
+) -> Result<Bitmap, png::DecodingError> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+
+    let channels = info.color_type.samples();
+    let image_width = info.width as usize;
+    let image_height = info.height as usize;
+
+    let mut luminances = Vec::with_capacity(image_width * image_height);
+    for py in 0..image_height {
+        let row = &buffer[py * info.line_size..];
+        for px in 0..image_width {
+            let pixel = &row[px * channels..(px + 1) * channels];
+            let (luminance, alpha) = match pixel {
+                [gray] => (f64::from(*gray), 255.0),
+                [gray, alpha] => (f64::from(*gray), f64::from(*alpha)),
+                [r, g, b] => (luma(*r, *g, *b), 255.0),
+                [r, g, b, alpha] => (luma(*r, *g, *b), f64::from(*alpha)),
+                _ => unreachable!("Unexpected amount of channels"),
+            };
+            // Transparent pixels count as white
+            luminances.push(luminance * alpha / 255.0 + 255.0 * (1.0 - alpha / 255.0));
+        }
+    }
+
+    // Average the pixels every field covers
+    Ok((0..width)
+        .map(|x| {
+            let columns = covered(x, width, image_width);
+            (0..height)
+                .map(|y| {
+                    let rows = covered(y, height, image_height);
+                    let mut sum = 0.0;
+                    for py in rows.clone() {
+                        for px in columns.clone() {
+                            sum += luminances[py * image_width + px];
+                        }
+                    }
+                    let count = (columns.len() * rows.len()) as f64;
+                    sum / count < 128.0
+                })
+                .collect()
+        })
+        .collect())
+}
+
+/// Maps a field to the pixels it covers along one axis. Fields of images
+/// smaller than the board share pixels, so every field covers at least one.
+///
+/// @return Range of pixel indices
+fn covered(field: usize, fields: usize, pixels: usize) -> Range<usize> {
+    let start = (field * pixels / fields).min(pixels - 1);
+    let end = ((field + 1) * pixels / fields).max(start + 1);
+    start..end
+}
+
+fn luma(r: u8, g: u8, b: u8) -> f64 {
+    0.299 * f64::from(r) + 0.587 * f64::from(g) + 0.114 * f64::from(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// @return PNG file of a grayscale image given by its rows
+    fn encode(rows: &[&[u8]]) -> Vec<u8> {
+        let mut file = Vec::new();
+        let mut encoder = png::Encoder::new(&mut file, rows[0].len() as u32, rows.len() as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&rows.concat()).unwrap();
+        writer.finish().unwrap();
+        file
+    }
+
+    #[test]
+    fn small_images_are_scaled_up() {
+        let bitmap = bitmap_from_png(&encode(&[&[0]]), 3, 2).unwrap();
+        assert_eq!(bitmap, vec![vec![true; 2]; 3]);
+
+        let bitmap = bitmap_from_png(&encode(&[&[0, 255]]), 4, 2).unwrap();
+        assert_eq!(
+            bitmap,
+            vec![
+                vec![true, true],
+                vec![true, true],
+                vec![false, false],
+                vec![false, false]
+            ]
+        );
+    }
+
+    #[test]
+    fn large_images_are_averaged() {
+        let image: &[&[u8]] = &[&[0, 0, 255, 255], &[0, 255, 255, 255]];
+        let bitmap = bitmap_from_png(&encode(image), 2, 1).unwrap();
+        assert_eq!(bitmap, vec![vec![true], vec![false]]);
+    }
+}
@@ -0,0 +1,305 @@
+use crate::gamegen::{Board, DotColor, DotPos, Galaxies};
+use crate::solver::Assignment;
+
+mod bitmap;
+pub mod pdf;
+mod raster;
+mod svg;
+
+pub use bitmap::bitmap_from_png;
+pub use raster::png;
+pub use svg::svg;
+
+/// Longest side of a field in pixels the command line accepts
+pub const MAX_FIELD_SIZE: u32 = 1000;
+
+/// Error of images whose size doesn't fit into memory
+const TOO_LARGE: &str = "The image is too large";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Color(pub u8, pub u8, pub u8);
+
+/// Describes how a board is drawn
+pub struct Style {
+    /// Length of the side of a single field in pixels
+    pub field_size: u32,
+    /// Space around the board in pixels
+    pub margin: u32,
+    pub background: Color,
+    pub grid: Color,
+    /// Color of the outline of the board and the borders between galaxies
+    pub border: Color,
+    pub white_dot: Color,
+    pub black_dot: Color,
+    /// Shading of galaxies with a white dot, only used for solutions
+    pub white_galaxy: Color,
+    /// Shading of galaxies with a black dot, only used for solutions
+    pub black_galaxy: Color,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            field_size: 40,
+            margin: 20,
+            background: Color(255, 255, 255),
+            grid: Color(190, 190, 190),
+            border: Color(0, 0, 0),
+            white_dot: Color(255, 255, 255),
+            black_dot: Color(0, 0, 0),
+            white_galaxy: Color(255, 255, 255),
+            black_galaxy: Color(110, 110, 110),
+        }
+    }
+}
+
+/// Everything about a board which ends up in an image
+pub struct Picture<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub dots: &'a [DotPos],
+    /// Color of every dot, all dots are white if there are none
+    pub colors: Option<&'a [DotColor]>,
+    /// Solution of the board, only the dots are drawn if there is none
+    pub galaxies: Option<&'a Galaxies>,
+    /// Partially solved board, which is drawn like the solution without the
+    /// free fields. Only used if there are no galaxies.
+    pub assignment: Option<&'a Assignment>,
+}
+
+impl<'a> Picture<'a> {
+    /// Picture of the puzzle, which only shows the dots
+    pub fn puzzle(board: &'a Board) -> Self {
+        Picture {
+            width: board.width,
+            height: board.height,
+            dots: &board.dots,
+            colors: Some(&board.colors),
+            galaxies: None,
+            assignment: None,
+        }
+    }
+
+    /// Picture of the solved puzzle, which shows dots and galaxies
+    pub fn solution(board: &'a Board) -> Self {
+        Picture {
+            galaxies: Some(&board.galaxies),
+            ..Picture::puzzle(board)
+        }
+    }
+
+    /// @return Dot whose galaxy the field belongs to, if it is known
+    fn galaxy(&self, x: usize, y: usize) -> Option<usize> {
+        match (self.galaxies, self.assignment) {
+            (Some(galaxies), _) => Some(galaxies[x][y]),
+            (None, Some(assignment)) => assignment[x][y],
+            (None, None) => None,
+        }
+    }
+
+    fn dot_color(&self, index: usize) -> DotColor {
+        self.colors
+            .and_then(|colors| colors.get(index).copied())
+            .unwrap_or(DotColor::White)
+    }
+}
+
+/// Basic shapes a picture is made of. Coordinates are in pixels.
+enum Shape {
+    Rect {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        fill: Color,
+    },
+    Circle {
+        x: f64,
+        y: f64,
+        radius: f64,
+        fill: Color,
+        stroke: Color,
+        stroke_width: f64,
+    },
+}
+
+/// Size of the whole image in pixels
+///
+/// @return Width and height, an error if they overflow
+fn image_size(picture: &Picture, style: &Style) -> Result<(usize, usize), &'static str> {
+    let field = style.field_size as usize;
+    let margin = 2 * style.margin as usize;
+    let side = |fields: usize| fields.checked_mul(field)?.checked_add(margin);
+    match (side(picture.width), side(picture.height)) {
+        (Some(width), Some(height)) => Ok((width, height)),
+        _ => Err(TOO_LARGE),
+    }
+}
+
+/// Translates a picture of the given image size into shapes, which are
+/// ordered from back to front
+fn shapes(picture: &Picture, style: &Style, size: (usize, usize)) -> Vec<Shape> {
+    let (image_width, image_height) = size;
+    let field = f64::from(style.field_size);
+    let margin = f64::from(style.margin);
+    let thin = (field / 40.0).max(1.0);
+    let thick = (field / 10.0).max(2.0);
+    let board_width = picture.width as f64 * field;
+    let board_height = picture.height as f64 * field;
+
+    let mut shapes = vec![Shape::Rect {
+        x: 0.0,
+        y: 0.0,
+        width: image_width as f64,
+        height: image_height as f64,
+        fill: style.background,
+    }];
+
+    // Shade galaxies
+    for x in 0..picture.width {
+        for y in 0..picture.height {
+            if let Some(galaxy) = picture.galaxy(x, y) {
+                let fill = match picture.dot_color(galaxy) {
+                    DotColor::White => style.white_galaxy,
+                    DotColor::Black => style.black_galaxy,
+                };
+                shapes.push(Shape::Rect {
+                    x: margin + x as f64 * field,
+                    y: margin + y as f64 * field,
+                    width: field,
+                    height: field,
+                    fill,
+                });
+            }
+        }
+    }
+
+    // Grid
+    for x in 1..picture.width {
+        shapes.push(Shape::Rect {
+            x: margin + x as f64 * field - thin / 2.0,
+            y: margin,
+            width: thin,
+            height: board_height,
+            fill: style.grid,
+        });
+    }
+    for y in 1..picture.height {
+        shapes.push(Shape::Rect {
+            x: margin,
+            y: margin + y as f64 * field - thin / 2.0,
+            width: board_width,
+            height: thin,
+            fill: style.grid,
+        });
+    }
+
+    // Borders between galaxies, which are extended to cover the corners. Free
+    // fields have no borders.
+    for x in 0..picture.width {
+        for y in 0..picture.height {
+            let galaxy = match picture.galaxy(x, y) {
+                Some(galaxy) => galaxy,
+                None => continue,
+            };
+            let differs = |other: Option<usize>| other.is_some_and(|other| other != galaxy);
+            if x > 0 && differs(picture.galaxy(x - 1, y)) {
+                shapes.push(Shape::Rect {
+                    x: margin + x as f64 * field - thick / 2.0,
+                    y: margin + y as f64 * field - thick / 2.0,
+                    width: thick,
+                    height: field + thick,
+                    fill: style.border,
+                });
+            }
+            if y > 0 && differs(picture.galaxy(x, y - 1)) {
+                shapes.push(Shape::Rect {
+                    x: margin + x as f64 * field - thick / 2.0,
+                    y: margin + y as f64 * field - thick / 2.0,
+                    width: field + thick,
+                    height: thick,
+                    fill: style.border,
+                });
+            }
+        }
+    }
+
+    // Outline
+    let outline = [
+        (0.0, 0.0, board_width, 0.0),
+        (0.0, board_height, board_width, 0.0),
+        (0.0, 0.0, 0.0, board_height),
+        (board_width, 0.0, 0.0, board_height),
+    ];
+    for &(x, y, width, height) in outline.iter() {
+        shapes.push(Shape::Rect {
+            x: margin + x - thick / 2.0,
+            y: margin + y - thick / 2.0,
+            width: width + thick,
+            height: height + thick,
+            fill: style.border,
+        });
+    }
+
+    // Dots, which use dot grid coordinates
+    for (index, dot) in picture.dots.iter().enumerate() {
+        let fill = match picture.dot_color(index) {
+            DotColor::White => style.white_dot,
+            DotColor::Black => style.black_dot,
+        };
+        shapes.push(Shape::Circle {
+            x: margin + (dot.0 + 1) as f64 * field / 2.0,
+            y: margin + (dot.1 + 1) as f64 * field / 2.0,
+            radius: field * 0.18,
+            fill,
+            stroke: style.border,
+            stroke_width: thin * 1.5,
+        });
+    }
+
+    shapes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gamegen;
+
+    #[test]
+    fn svg_contains_all_dots() {
+        let board = gamegen::generate_board(7, 5).unwrap();
+        let style = Style::default();
+        let document = svg(&Picture::solution(&board), &style).unwrap();
+
+        assert!(document.starts_with("<svg"));
+        assert_eq!(document.matches("<circle").count(), board.dots.len());
+    }
+
+    #[test]
+    fn png_has_image_size() {
+        let board = gamegen::generate_board(4, 6).unwrap();
+        let style = Style::default();
+        let file = png(&Picture::puzzle(&board), &style).unwrap();
+
+        let decoder = ::png::Decoder::new(file.as_slice());
+        let reader = decoder.read_info().unwrap();
+        let info = reader.info();
+        let size = image_size(&Picture::puzzle(&board), &style).unwrap();
+        assert_eq!((info.width as usize, info.height as usize), size);
+    }
+
+    #[test]
+    fn oversized_images_are_refused() {
+        let board = gamegen::generate_board(4, 6).unwrap();
+        let style = Style {
+            field_size: u32::MAX,
+            ..Style::default()
+        };
+        let picture = Picture {
+            width: usize::MAX / 2,
+            ..Picture::puzzle(&board)
+        };
+        assert_eq!(svg(&picture, &style), Err(TOO_LARGE));
+        assert!(png(&Picture::puzzle(&board), &style).is_err());
+    }
+}
@@ -0,0 +1,244 @@
+use super::{image_size, shapes, Color, Picture, Shape, Style};
+use std::fmt::Write;
+
+/// Size of an A4 page in points
+pub const A4: (f64, f64) = (595.0, 842.0);
+
+/// Factor to approximate a quarter circle with a bezier curve
+const KAPPA: f64 = 0.552_284_75;
+
+/// A single page of a PDF document, which is drawn on in points starting at
+/// the top left corner
+pub struct Page {
+    width: f64,
+    height: f64,
+    content: String,
+}
+
+impl Page {
+    pub fn new(size: (f64, f64)) -> Self {
+        Page {
+            width: size.0,
+            height: size.1,
+            content: String::new(),
+        }
+    }
+
+    fn fill_color(&mut self, color: Color) {
+        writeln!(
+            self.content,
+            "{:.3} {:.3} {:.3} rg",
+            f64::from(color.0) / 255.0,
+            f64::from(color.1) / 255.0,
+            f64::from(color.2) / 255.0
+        )
+        .unwrap();
+    }
+
+    fn stroke_color(&mut self, color: Color) {
+        writeln!(
+            self.content,
+            "{:.3} {:.3} {:.3} RG",
+            f64::from(color.0) / 255.0,
+            f64::from(color.1) / 255.0,
+            f64::from(color.2) / 255.0
+        )
+        .unwrap();
+    }
+
+    /// Draws a picture of a board into the given box, scaled down to fit and
+    /// centered horizontally.
+    ///
+    /// @return Nothing, an error if the picture is too large
+    pub fn picture(
+        &mut self,
+        picture: &Picture,
+        style: &Style,
+        area: (f64, f64, f64, f64),
+    ) -> Result<(), &'static str> {
+        let (left, top, width, height) = area;
+        let size = image_size(picture, style)?;
+        let (image_width, image_height) = (size.0 as f64, size.1 as f64);
+        let scale = (width / image_width).min(height / image_height);
+        let left = left + (width - image_width * scale) / 2.0;
+
+        // Flip the y axis, so the picture can be drawn in pixel coordinates
+        writeln!(self.content, "q").unwrap();
+        writeln!(
+            self.content,
+            "{:.3} 0 0 {:.3} {:.3} {:.3} cm",
+            scale,
+            -scale,
+            left,
+            self.height - top
+        )
+        .unwrap();
+        for shape in shapes(picture, style, size) {
+            match shape {
+                Shape::Rect {
+                    x,
+                    y,
+                    width,
+                    height,
+                    fill,
+                } => {
+                    self.fill_color(fill);
+                    writeln!(
+                        self.content,
+                        "{:.3} {:.3} {:.3} {:.3} re f",
+                        x, y, width, height
+                    )
+                    .unwrap();
+                }
+                Shape::Circle {
+                    x,
+                    y,
+                    radius,
+                    fill,
+                    stroke,
+                    stroke_width,
+                } => {
+                    self.fill_color(fill);
+                    self.stroke_color(stroke);
+                    writeln!(self.content, "{:.3} w", stroke_width).unwrap();
+                    let k = radius * KAPPA;
+                    writeln!(self.content, "{:.3} {:.3} m", x + radius, y).unwrap();
+                    let quarters = [
+                        (x + radius, y + k, x + k, y + radius, x, y + radius),
+                        (x - k, y + radius, x - radius, y + k, x - radius, y),
+                        (x - radius, y - k, x - k, y - radius, x, y - radius),
+                        (x + k, y - radius, x + radius, y - k, x + radius, y),
+                    ];
+                    for (x1, y1, x2, y2, x3, y3) in quarters.iter() {
+                        writeln!(
+                            self.content,
+                            "{:.3} {:.3} {:.3} {:.3} {:.3} {:.3} c",
+                            x1, y1, x2, y2, x3, y3
+                        )
+                        .unwrap();
+                    }
+                    writeln!(self.content, "b").unwrap();
+                }
+            }
+        }
+        writeln!(self.content, "Q").unwrap();
+        Ok(())
+    }
+
+    /// Writes a line of text in Helvetica, centered horizontally around x.
+    /// The width of the text is only estimated.
+    pub fn centered_text(&mut self, x: f64, top: f64, size: f64, text: &str) {
+        let estimated_width = text.chars().count() as f64 * size * 0.5;
+        let escaped = text
+            .replace('\\', "\\\\")
+            .replace('(', "\\(")
+            .replace(')', "\\)");
+        self.fill_color(Color(0, 0, 0));
+        writeln!(
+            self.content,
+            "BT /F1 {:.1} Tf {:.3} {:.3} Td ({}) Tj ET",
+            size,
+            x - estimated_width / 2.0,
+            self.height - top - size,
+            escaped
+        )
+        .unwrap();
+    }
+
+    pub fn width(&self) -> f64 {
+        self.width
+    }
+}
+
+/// Minimal PDF document consisting of pages with vector graphics and text.
+#[derive(Default)]
+pub struct Document {
+    pages: Vec<Page>,
+}
+
+impl Document {
+    pub fn new() -> Self {
+        Document::default()
+    }
+
+    pub fn add_page(&mut self, page: Page) {
+        self.pages.push(page);
+    }
+
+    /// Serializes the document.
+    ///
+    /// @return PDF file content
+    pub fn write(&self) -> Vec<u8> {
+        // Objects 1 to 3 are catalog, page tree and font, afterwards every
+        // page is followed by its content stream
+        let mut objects = Vec::new();
+        let kids: Vec<String> = (0..self.pages.len())
+            .map(|i| format!("{} 0 R", 4 + 2 * i))
+            .collect();
+        objects.push("<< /Type /Catalog /Pages 2 0 R >>".to_owned());
+        objects.push(format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "),
+            self.pages.len()
+        ));
+        objects.push("<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>".to_owned());
+        for (i, page) in self.pages.iter().enumerate() {
+            objects.push(format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.0} {:.0}] \
+                 /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+                page.width,
+                page.height,
+                5 + 2 * i
+            ));
+            objects.push(format!(
+                "<< /Length {} >>\nstream\n{}endstream",
+                page.content.len(),
+                page.content
+            ));
+        }
+
+        let mut file = String::from("%PDF-1.4\n");
+        let mut offsets = Vec::new();
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(file.len());
+            write!(file, "{} 0 obj\n{}\nendobj\n", i + 1, object).unwrap();
+        }
+        let xref = file.len();
+        write!(file, "xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).unwrap();
+        for offset in offsets {
+            writeln!(file, "{:010} 00000 n ", offset).unwrap();
+        }
+        write!(
+            file,
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref
+        )
+        .unwrap();
+        file.into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xref_points_to_objects() {
+        let mut document = Document::new();
+        for i in 0..3 {
+            let mut page = Page::new(A4);
+            page.centered_text(100.0, 100.0, 12.0, &format!("Page (no. {})", i));
+            document.add_page(page);
+        }
+        let file = String::from_utf8(document.write()).unwrap();
+
+        let xref = file.rfind("startxref\n").unwrap() + "startxref\n".len();
+        let xref: usize = file[xref..].lines().next().unwrap().parse().unwrap();
+        let entries: Vec<&str> = file[xref..].lines().skip(3).take(9).collect();
+        for (i, entry) in entries.iter().enumerate() {
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(file[offset..].starts_with(&format!("{} 0 obj", i + 1)));
+        }
+    }
+}
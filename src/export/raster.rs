@@ -0,0 +1,125 @@
+use super::{image_size, shapes, Color, Picture, Shape, Style};
+use std::convert::TryFrom;
+
+/// Amount of samples per pixel and axis which are used to smooth edges
+const SUBSAMPLES: u32 = 4;
+
+/// RGB image which shapes are drawn on
+struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    /// @return White canvas, None if PNG images can't be that large
+    fn new(width: usize, height: usize) -> Option<Self> {
+        let pixels = width.checked_mul(height)?.checked_mul(3)?;
+        Some(Canvas {
+            width: u32::try_from(width).ok()?,
+            height: u32::try_from(height).ok()?,
+            pixels: vec![0; pixels],
+        })
+    }
+
+    /// Mixes the color into the pixel, coverage is between 0 and 1
+    fn blend(&mut self, x: u32, y: u32, color: Color, coverage: f64) {
+        let index = (y as usize * self.width as usize + x as usize) * 3;
+        let channels = [color.0, color.1, color.2];
+        for (pixel, channel) in self.pixels[index..index + 3].iter_mut().zip(&channels) {
+            let mixed = f64::from(*pixel) * (1.0 - coverage) + f64::from(*channel) * coverage;
+            *pixel = mixed.round() as u8;
+        }
+    }
+
+    /// Calls the function for every pixel inside the bounding box with the
+    /// share of the pixel for which the predicate holds
+    fn fill<F>(&mut self, left: f64, top: f64, right: f64, bottom: f64, color: Color, inside: F)
+    where
+        F: Fn(f64, f64) -> bool,
+    {
+        let x_start = left.floor().max(0.0) as u32;
+        let y_start = top.floor().max(0.0) as u32;
+        let x_end = (right.ceil().max(0.0) as u32).min(self.width);
+        let y_end = (bottom.ceil().max(0.0) as u32).min(self.height);
+
+        let step = 1.0 / f64::from(SUBSAMPLES);
+        for x in x_start..x_end {
+            for y in y_start..y_end {
+                let mut hits = 0;
+                for i in 0..SUBSAMPLES {
+                    for j in 0..SUBSAMPLES {
+                        let sx = f64::from(x) + (f64::from(i) + 0.5) * step;
+                        let sy = f64::from(y) + (f64::from(j) + 0.5) * step;
+                        if inside(sx, sy) {
+                            hits += 1;
+                        }
+                    }
+                }
+                if hits > 0 {
+                    let coverage = f64::from(hits) / f64::from(SUBSAMPLES * SUBSAMPLES);
+                    self.blend(x, y, color, coverage);
+                }
+            }
+        }
+    }
+
+    fn rect(&mut self, x: f64, y: f64, width: f64, height: f64, color: Color) {
+        let (right, bottom) = (x + width, y + height);
+        self.fill(x, y, right, bottom, color, |sx, sy| {
+            sx >= x && sx < right && sy >= y && sy < bottom
+        });
+    }
+
+    fn circle(&mut self, x: f64, y: f64, radius: f64, color: Color) {
+        self.fill(
+            x - radius,
+            y - radius,
+            x + radius,
+            y + radius,
+            color,
+            |sx, sy| (sx - x).powi(2) + (sy - y).powi(2) <= radius * radius,
+        );
+    }
+}
+
+/// Rasterizes a picture of a board and encodes it as PNG.
+///
+/// @return PNG file content
+pub fn png(picture: &Picture, style: &Style) -> Result<Vec<u8>, png::EncodingError> {
+    let size = image_size(picture, style).map_err(|_| png::EncodingError::LimitsExceeded)?;
+    let mut canvas = Canvas::new(size.0, size.1).ok_or(png::EncodingError::LimitsExceeded)?;
+
+    for shape in shapes(picture, style, size) {
+        match shape {
+            Shape::Rect {
+                x,
+                y,
+                width,
+                height,
+                fill,
+            } => canvas.rect(x, y, width, height, fill),
+            Shape::Circle {
+                x,
+                y,
+                radius,
+                fill,
+                stroke,
+                stroke_width,
+            } => {
+                canvas.circle(x, y, radius + stroke_width / 2.0, stroke);
+                canvas.circle(x, y, radius - stroke_width / 2.0, fill);
+            }
+        }
+    }
+
+    let mut file = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut file, canvas.width, canvas.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&canvas.pixels)?;
+    }
+    Ok(file)
+}
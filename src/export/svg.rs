@@ -0,0 +1,61 @@
+use super::{image_size, shapes, Color, Picture, Shape, Style};
+use std::fmt::Write;
+
+fn hex(color: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.0, color.1, color.2)
+}
+
+/// Renders a picture of a board as SVG document.
+///
+/// @return SVG document, an error if the image is too large
+pub fn svg(picture: &Picture, style: &Style) -> Result<String, &'static str> {
+    let (width, height) = image_size(picture, style)?;
+
+    let mut s = String::new();
+    writeln!(
+        s,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+        w = width,
+        h = height
+    )
+    .unwrap();
+    for shape in shapes(picture, style, (width, height)) {
+        match shape {
+            Shape::Rect {
+                x,
+                y,
+                width,
+                height,
+                fill,
+            } => writeln!(
+                s,
+                r#"  <rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+                x,
+                y,
+                width,
+                height,
+                hex(fill)
+            ),
+            Shape::Circle {
+                x,
+                y,
+                radius,
+                fill,
+                stroke,
+                stroke_width,
+            } => writeln!(
+                s,
+                r#"  <circle cx="{}" cy="{}" r="{}" fill="{}" stroke="{}" stroke-width="{}"/>"#,
+                x,
+                y,
+                radius,
+                hex(fill),
+                hex(stroke),
+                stroke_width
+            ),
+        }
+        .unwrap();
+    }
+    s.push_str("</svg>\n");
+    Ok(s)
+}
@@ -0,0 +1,136 @@
+use super::{Outbox, RoomId};
+use crate::protocol::ServerMessage;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Instant;
+
+struct Delayed {
+    due: Instant,
+    /// Keeps messages which are due at the same time in order
+    sequence: u64,
+    /// Room whose game the message is about
+    room: RoomId,
+    outbox: Outbox,
+    message: ServerMessage,
+}
+
+enum Command {
+    Send(Delayed),
+    /// Drops the messages of the room, because its game was replaced
+    Cancel(RoomId),
+}
+
+impl Delayed {
+    fn key(&self) -> (Instant, u64) {
+        (self.due, self.sequence)
+    }
+}
+
+impl PartialEq for Delayed {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Delayed {}
+
+impl Ord for Delayed {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl PartialOrd for Delayed {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Delivers messages to clients at a later time, which lets spectators see
+/// competitive games with a delay
+pub struct Delay {
+    sender: Sender<Command>,
+}
+
+/// Waits for the earliest message and delivers it until the delay is dropped
+fn deliver(receiver: Receiver<Command>) {
+    let mut queue = BinaryHeap::new();
+    let mut sequence = 0;
+    loop {
+        let received = match queue.peek() {
+            Some(Reverse(next)) => {
+                let next: &Delayed = next;
+                receiver.recv_timeout(next.due.saturating_duration_since(Instant::now()))
+            }
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok(Command::Send(mut delayed)) => {
+                delayed.sequence = sequence;
+                sequence += 1;
+                queue.push(Reverse(delayed));
+            }
+            Ok(Command::Cancel(room)) => queue.retain(|Reverse(delayed)| delayed.room != room),
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+
+        let now = Instant::now();
+        while queue.peek().is_some_and(|Reverse(next)| next.due <= now) {
+            if let Some(Reverse(delayed)) = queue.pop() {
+                delayed.outbox.send(delayed.message);
+            }
+        }
+    }
+}
+
+impl Delay {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || deliver(receiver));
+        Delay { sender }
+    }
+
+    /// Sends the message about the game of the room at the given time, or
+    /// right away if it is due already
+    pub fn send(&self, due: Instant, room: RoomId, outbox: &Outbox, message: ServerMessage) {
+        if due <= Instant::now() {
+            outbox.send(message);
+            return;
+        }
+        let _ = self.sender.send(Command::Send(Delayed {
+            due,
+            sequence: 0,
+            room,
+            outbox: outbox.clone(),
+            message,
+        }));
+    }
+
+    /// Drops the messages about the game of the room which aren't sent yet
+    pub fn cancel(&self, room: RoomId) {
+        let _ = self.sender.send(Command::Cancel(room));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{outbox, OUTBOX_CAPACITY};
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn drops_messages_of_replaced_games() {
+        let delay = Delay::new();
+        let (outbox, mut inbox) = outbox(OUTBOX_CAPACITY);
+        let due = Instant::now() + Duration::from_millis(50);
+        delay.send(due, 1, &outbox, ServerMessage::Solved);
+        delay.send(due, 2, &outbox, ServerMessage::ShuttingDown);
+        delay.cancel(1);
+        thread::sleep(Duration::from_millis(200));
+        let messages: Vec<ServerMessage> = inbox.try_iter().collect();
+        assert_eq!(messages, vec![ServerMessage::ShuttingDown]);
+    }
+}
@@ -0,0 +1,79 @@
+use super::room::Room;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Identifies a room
+pub type RoomId = u64;
+
+/// Room which clients join when they log in. It is never closed.
+pub const MAIN_ROOM: RoomId = 0;
+
+/// Rooms without clients are closed after this time, which gives players
+/// the chance to reconnect
+pub const EMPTY_ROOM_LIFETIME: Duration = Duration::from_secs(5 * 60);
+
+/// All open rooms. Every room has a lock of its own, so games in different
+/// rooms go on at the same time.
+pub struct Lobby {
+    rooms: BTreeMap<RoomId, Arc<Mutex<Room>>>,
+    next_room: RoomId,
+}
+
+impl Lobby {
+    /// @return Lobby with the rooms, which have to include the main room
+    pub fn new(rooms: BTreeMap<RoomId, Room>) -> Self {
+        assert!(rooms.contains_key(&MAIN_ROOM), "The main room is missing");
+        let next_room = rooms.keys().next_back().map_or(MAIN_ROOM, |&id| id) + 1;
+        let rooms = rooms
+            .into_iter()
+            .map(|(id, room)| (id, Arc::new(Mutex::new(room))))
+            .collect();
+        Lobby { rooms, next_room }
+    }
+
+    pub fn get(&self, room: RoomId) -> Option<&Arc<Mutex<Room>>> {
+        self.rooms.get(&room)
+    }
+
+    /// @return Rooms ordered by their ids, which is the order to lock them in
+    pub fn rooms(&self) -> impl Iterator<Item = (RoomId, &Arc<Mutex<Room>>)> {
+        self.rooms.iter().map(|(&id, room)| (id, room))
+    }
+
+    /// @return Id of the new room
+    pub fn open(&mut self, room: Room) -> RoomId {
+        let id = self.next_room;
+        self.next_room += 1;
+        self.rooms.insert(id, Arc::new(Mutex::new(room)));
+        id
+    }
+
+    /// @return Closed room if it was open
+    pub fn close(&mut self, room: RoomId) -> Option<Arc<Mutex<Room>>> {
+        self.rooms.remove(&room)
+    }
+
+    /// Closes the rooms which are empty for longer than their lifetime
+    ///
+    /// @return Ids of the closed rooms
+    pub fn cleanup(&mut self, now: Instant) -> Vec<RoomId> {
+        let expired: Vec<RoomId> = self
+            .rooms
+            .iter()
+            .filter(|&(&id, room)| {
+                id != MAIN_ROOM
+                    && room
+                        .lock()
+                        .unwrap()
+                        .empty_since
+                        .is_some_and(|since| now.duration_since(since) >= EMPTY_ROOM_LIFETIME)
+            })
+            .map(|(&id, _)| id)
+            .collect();
+        for id in &expired {
+            self.rooms.remove(id);
+        }
+        expired
+    }
+}
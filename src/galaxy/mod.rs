@@ -0,0 +1,2106 @@
+use crate::daily::{self, Daily};
+use crate::game::{Game, GameError};
+use crate::gamegen::{self, Board, Difficulty, DotPos, Galaxies, GenerationConfig};
+use crate::leaderboard::{Leaderboard, Period};
+use crate::logger;
+use crate::metrics;
+use crate::protocol::{
+    AdminCommand, AdminResponse, ClientMessage, HintReason, PlayerInfo, Rank, RoomInfo,
+    ServerMessage,
+};
+use crate::replay::Replay;
+use crate::secret::{Secret, SecretHash};
+use crate::solver::{hint, Assignment, Exhausted, Puzzle, Reason};
+use crate::storage::{Record, Storage};
+use crate::types::{GameChange, Id, Offset, Player, Position};
+use log::{info, warn};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+mod delay;
+mod lobby;
+mod outbox;
+mod room;
+
+use delay::Delay;
+use lobby::Lobby;
+pub use lobby::{RoomId, MAIN_ROOM};
+pub use outbox::{outbox, Inbox, Outbox, OUTBOX_CAPACITY};
+use room::{Member, Room};
+pub use room::{Mode, Settings};
+
+/// Identifies a single connection
+pub type ClientId = u64;
+
+/// Biggest width and height of generated boards. The config may lower it
+/// for clients.
+pub const MAX_BOARD_SIZE: usize = 30;
+
+/// Logs are compacted once at least this many records were appended
+const MIN_COMPACTION: usize = 1000;
+
+/// Longest room name in characters
+const MAX_ROOM_NAME: usize = 40;
+
+/// Longest player name in characters
+const MAX_PLAYER_NAME: usize = 32;
+
+const MAIN_ROOM_NAME: &str = "Main";
+
+/// Error for spectators of a race who don't choose a player, since every
+/// player has a game of their own
+const RACE_WITHOUT_PLAYER: &str = "Spectators of a race have to choose a player";
+
+/// Small main room for the galaxies of tests
+#[cfg(test)]
+pub const TEST_CONFIG: GenerationConfig = GenerationConfig {
+    width: 3,
+    height: 3,
+    difficulty: Difficulty::Normal,
+    seed: 1,
+};
+
+/// What a client does in its room
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Role {
+    Player,
+    /// Watches the game, in race mode the game of the player if there is one
+    Spectator(Option<Id>),
+}
+
+/// Outcome of checking a passphrase, which happens before locking because
+/// hashing is slow on purpose
+enum Credentials {
+    /// The passphrase is the one of the player
+    Verified(Id),
+    /// The passphrase is the one of nobody using the name
+    Wrong,
+    /// Nobody uses the name yet, so it is registered with the hash
+    New(SecretHash),
+}
+
+struct Client {
+    outbox: Outbox,
+    /// Player the client logged in as
+    player: Option<Id>,
+    room: Option<(RoomId, Role)>,
+}
+
+/// The storage if there is one and how much of it is obsolete
+struct Log {
+    storage: Option<Storage>,
+    /// Records which the last compaction wrote
+    compacted: usize,
+    /// Records which were appended since then
+    appended: usize,
+}
+
+impl Log {
+    /// Writes the record to the storage if there is one. Failures are only
+    /// logged, so the game goes on without being persisted.
+    fn store(&mut self, record: Record) {
+        if let Some(storage) = &mut self.storage {
+            match storage.append(&record) {
+                Ok(()) => self.appended += 1,
+                Err(error) => warn!("Storing {:?} failed: {}", record, error),
+            }
+        }
+    }
+
+    /// Whether more records were appended than the last compaction wrote,
+    /// so compacting again takes less time than it saves when restoring
+    fn bloated(&self) -> bool {
+        self.storage.is_some() && self.appended >= self.compacted.max(MIN_COMPACTION)
+    }
+
+    fn rewrite(&mut self, records: &[Record]) -> io::Result<()> {
+        if let Some(storage) = &mut self.storage {
+            storage.rewrite(records)?;
+        }
+        self.compacted = records.len();
+        self.appended = 0;
+        Ok(())
+    }
+}
+
+/// Parts of the state which rooms use without locking the lobby. Locks are
+/// taken in this order: state, rooms by their ids, leaderboard and log.
+struct Shared {
+    leaderboard: Mutex<Leaderboard>,
+    log: Mutex<Log>,
+    delay: Delay,
+}
+
+/// Players, clients and the lobby
+struct State {
+    lobby: Lobby,
+    players: Vec<Player>,
+    clients: HashMap<ClientId, Client>,
+    next_client: ClientId,
+    shared: Arc<Shared>,
+    /// Banned players and until when in milliseconds since the Unix epoch,
+    /// forever if there is no end
+    bans: HashMap<Id, Option<u64>>,
+    /// The server shuts down, so messages are ignored
+    closing: bool,
+}
+
+/// How much is going on right now
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Census {
+    pub rooms: usize,
+    /// Connected clients, whether logged in or not
+    pub clients: usize,
+    /// Players with at least one connected client
+    pub players: usize,
+}
+
+/// Main class of this project. Manages all ressources
+pub struct Galaxy {
+    state: Mutex<State>,
+    shared: Arc<Shared>,
+    daily: Arc<Daily>,
+    max_board_size: usize,
+}
+
+/// @return Message with the whole game, which is the given one of its room
+fn board_message(game: &Game, number: u64) -> ServerMessage {
+    ServerMessage::Board {
+        puzzle: game.puzzle,
+        game: number,
+        width: game.width,
+        height: game.height,
+        dots: game.dots.clone(),
+        fields: game.fields.clone(),
+        sequence: game.changes().len(),
+    }
+}
+
+/// @return Message with the change at the index of the game
+fn change_message(game: &Game, index: usize) -> ServerMessage {
+    ServerMessage::Change {
+        sequence: index + 1,
+        change: game.changes()[index].change.clone(),
+    }
+}
+
+/// @return Milliseconds since the Unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64)
+}
+
+/// Name, password and capacity of a room which was opened but has no game
+/// yet during restoring
+type Opening = (String, Option<SecretHash>, Option<usize>);
+
+/// Starts the game of a restored room. Rooms except the main room have to
+/// be opened before.
+fn restore_game(
+    rooms: &mut BTreeMap<RoomId, Room>,
+    opened: &mut HashMap<RoomId, Opening>,
+    id: RoomId,
+    mode: Mode,
+    settings: Settings,
+    board: Board,
+) -> io::Result<()> {
+    if let Some((name, password, capacity)) = opened.remove(&id) {
+        let mut room = Room::new(name, mode, settings, board);
+        room.password = password;
+        room.capacity = capacity;
+        rooms.insert(id, room);
+        return Ok(());
+    }
+    match rooms.get_mut(&id) {
+        Some(room) => room.start(mode, settings, board),
+        None if id == MAIN_ROOM => {
+            let room = Room::new(MAIN_ROOM_NAME.to_owned(), mode, settings, board);
+            rooms.insert(id, room);
+        }
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Game of an unknown room",
+            ))
+        }
+    }
+    Ok(())
+}
+
+impl State {
+    fn new(lobby: Lobby, shared: Arc<Shared>) -> Self {
+        State {
+            lobby,
+            players: Vec::new(),
+            clients: HashMap::new(),
+            next_client: 0,
+            shared,
+            bans: HashMap::new(),
+            closing: false,
+        }
+    }
+
+    /// @return Room a client is in, which is never closed
+    fn room(&self, room: RoomId) -> Arc<Mutex<Room>> {
+        let room = self.lobby.get(room).expect("Rooms with clients stay open");
+        Arc::clone(room)
+    }
+
+    fn store(&self, record: Record) {
+        self.shared.log.lock().unwrap().store(record);
+    }
+
+    /// Rewrites the log with only the records which are needed to restore
+    /// the current state. All rooms are locked meanwhile, so no change gets
+    /// lost.
+    fn compact(&self) -> io::Result<()> {
+        let rooms: Vec<_> = self
+            .lobby
+            .rooms()
+            .map(|(id, room)| (id, room.lock().unwrap()))
+            .collect();
+        let leaderboard = self.shared.leaderboard.lock().unwrap();
+        let mut records: Vec<Record> = self.players.iter().cloned().map(Record::Player).collect();
+        let solves = leaderboard.solves().iter().cloned();
+        records.extend(solves.map(Record::Solve));
+        let now = now();
+        for (&player, &until) in &self.bans {
+            if until.is_none_or(|until| until > now) {
+                records.push(Record::Ban { player, until });
+            }
+        }
+        for (id, room) in &rooms {
+            let id = *id;
+            if id != MAIN_ROOM {
+                records.push(Record::Open {
+                    room: id,
+                    name: room.name.clone(),
+                    password: room.password.clone(),
+                    capacity: room.capacity,
+                });
+            }
+            records.push(Record::Room {
+                room: id,
+                mode: room.mode(),
+                settings: room.settings,
+                board: room.board().clone(),
+            });
+            for game in room.games() {
+                let changes = game.changes().iter().cloned();
+                records.extend(changes.map(|change| Record::Change { room: id, change }));
+            }
+        }
+        self.shared.log.lock().unwrap().rewrite(&records)
+    }
+
+    /// Replaces the game of the room and sends its clients the new board
+    fn start_game(&mut self, room: RoomId, mode: Mode, settings: Settings, board: Board) {
+        let started = self.room(room);
+        let mut started = started.lock().unwrap();
+        started.start(mode, settings, board);
+        self.store(Record::Room {
+            room,
+            mode,
+            settings,
+            board: started.board().clone(),
+        });
+        // Spectators shouldn't see moves of the old game on the new board
+        self.shared.delay.cancel(room);
+
+        let clients: Vec<ClientId> = started.members.keys().copied().collect();
+        let mut excluded = Vec::new();
+        for client in clients {
+            let role = started.members[&client].role;
+            if started.watching(client) && !settings.spectators {
+                excluded.push((client, "The new game doesn't allow spectators"));
+            } else if role == Role::Spectator(None) && mode == Mode::Race {
+                excluded.push((client, RACE_WITHOUT_PLAYER));
+            } else {
+                started.send_board(room, &self.shared.delay, client);
+            }
+        }
+        drop(started);
+        for (client, reason) in excluded {
+            self.leave(client);
+            self.error(client, reason);
+        }
+    }
+
+    /// Adds the room to the lobby
+    ///
+    /// @return Id of the room
+    fn open_room(&mut self, room: Room) -> RoomId {
+        let records = vec![
+            Record::Open {
+                room: 0,
+                name: room.name.clone(),
+                password: room.password.clone(),
+                capacity: room.capacity,
+            },
+            Record::Room {
+                room: 0,
+                mode: room.mode(),
+                settings: room.settings,
+                board: room.board().clone(),
+            },
+        ];
+        let id = self.lobby.open(room);
+        for mut record in records {
+            if let Record::Open { room, .. } | Record::Room { room, .. } = &mut record {
+                *room = id;
+            }
+            self.store(record);
+        }
+        id
+    }
+
+    /// Closes rooms which are empty for too long at the given time
+    fn cleanup(&mut self, now: Instant) {
+        for room in self.lobby.cleanup(now) {
+            info!("Closed room {} because it is empty", room);
+            self.store(Record::Close { room });
+        }
+    }
+
+    /// Puts the client into the room after it left its current one
+    fn enter(&mut self, client: ClientId, room: RoomId, role: Role) {
+        self.leave(client);
+        let (outbox, player) = match self.clients.get_mut(&client) {
+            Some(connection) => {
+                connection.room = Some((room, role));
+                (connection.outbox.clone(), connection.player)
+            }
+            None => return,
+        };
+        let name = player.map_or_else(String::new, |player| {
+            self.players[usize::from(player)].name.clone()
+        });
+        let entered = self.room(room);
+        let mut entered = entered.lock().unwrap();
+        let member = Member {
+            outbox,
+            player,
+            name,
+            role,
+        };
+        entered.members.insert(client, member);
+        entered.empty_since = None;
+        let name = entered.name.clone();
+        entered.send(client, ServerMessage::Joined { room, name });
+        entered.send_board(room, &self.shared.delay, client);
+    }
+
+    /// Takes the client out of its room
+    ///
+    /// @return Room the client was in
+    fn leave(&mut self, client: ClientId) -> Option<RoomId> {
+        let (room, _) = self.clients.get_mut(&client)?.room.take()?;
+        let left = self.room(room);
+        let mut left = left.lock().unwrap();
+        left.members.remove(&client);
+        if left.members.is_empty() {
+            left.empty_since = Some(Instant::now());
+        }
+        Some(room)
+    }
+
+    fn room_infos(&self) -> Vec<RoomInfo> {
+        self.lobby
+            .rooms()
+            .map(|(id, room)| {
+                let room = room.lock().unwrap();
+                RoomInfo {
+                    room: id,
+                    name: room.name.clone(),
+                    mode: room.mode(),
+                    width: room.board().width,
+                    height: room.board().height,
+                    players: room.players().len(),
+                    capacity: room.capacity,
+                    locked: room.password.is_some(),
+                    spectators: room.settings.spectators,
+                }
+            })
+            .collect()
+    }
+
+    fn rooms(&self, client: ClientId) {
+        let rooms = self.room_infos();
+        self.send(client, ServerMessage::Rooms { rooms });
+    }
+
+    /// @return Players who are online, ordered by their ids
+    fn player_infos(&self) -> Vec<PlayerInfo> {
+        let mut players: BTreeMap<Id, PlayerInfo> = BTreeMap::new();
+        for client in self.clients.values() {
+            let player = match client.player {
+                Some(player) => player,
+                None => continue,
+            };
+            let info = players.entry(player).or_insert_with(|| PlayerInfo {
+                player,
+                name: self.players[usize::from(player)].name.clone(),
+                connections: 0,
+                rooms: Vec::new(),
+            });
+            info.connections += 1;
+            if let Some((room, _)) = client.room {
+                info.rooms.push(room);
+            }
+        }
+        players.into_values().collect()
+    }
+
+    fn banned(&self, player: Id) -> bool {
+        self.bans
+            .get(&player)
+            .is_some_and(|until| until.is_none_or(|until| until > now()))
+    }
+
+    /// Takes all clients of the player out of their rooms and tells them to
+    /// disconnect
+    ///
+    /// @return Amount of kicked clients
+    fn kick(&mut self, player: Id, reason: &str) -> usize {
+        let clients: Vec<ClientId> = self
+            .clients
+            .iter()
+            .filter(|(_, client)| client.player == Some(player))
+            .map(|(&id, _)| id)
+            .collect();
+        for &client in &clients {
+            self.leave(client);
+            if let Some(connection) = self.clients.get_mut(&client) {
+                connection.player = None;
+            }
+            let reason = reason.to_owned();
+            self.send(client, ServerMessage::Kicked { reason });
+        }
+        clients.len()
+    }
+
+    /// Closes the room after its clients left it
+    fn close_room(&mut self, room: RoomId) -> Result<(), &'static str> {
+        if room == MAIN_ROOM {
+            return Err("The main room can't be closed");
+        }
+        if self.lobby.get(room).is_none() {
+            return Err("There is no such room");
+        }
+        let clients: Vec<ClientId> = self
+            .clients
+            .iter()
+            .filter(|(_, client)| client.room.is_some_and(|(id, _)| id == room))
+            .map(|(&id, _)| id)
+            .collect();
+        for client in clients {
+            self.leave(client);
+            self.send(client, ServerMessage::Left { room });
+        }
+        self.lobby.close(room);
+        self.shared.delay.cancel(room);
+        self.store(Record::Close { room });
+        Ok(())
+    }
+
+    /// Executes a command of the admin console
+    fn administrate(&mut self, command: AdminCommand) -> Result<AdminResponse, String> {
+        let done = |message: String| Ok(AdminResponse::Done { message });
+        let known = |player: Id| {
+            if usize::from(player) < self.players.len() {
+                Ok(())
+            } else {
+                Err(format!("There is no player {}", player))
+            }
+        };
+        match command {
+            AdminCommand::Rooms => Ok(AdminResponse::Rooms {
+                rooms: self.room_infos(),
+            }),
+            AdminCommand::Players => Ok(AdminResponse::Players {
+                players: self.player_infos(),
+            }),
+            AdminCommand::Kick { player } => {
+                known(player)?;
+                let kicked = self.kick(player, "Kicked by an admin");
+                info!("Kicked player {} from {} connections", player, kicked);
+                done(format!("Kicked {} connections", kicked))
+            }
+            AdminCommand::Ban { player, minutes } => {
+                known(player)?;
+                let until = match minutes {
+                    Some(minutes) => Some(
+                        minutes
+                            .checked_mul(60 * 1000)
+                            .and_then(|millis| now().checked_add(millis))
+                            .ok_or("The ban is too long")?,
+                    ),
+                    None => None,
+                };
+                self.bans.insert(player, until);
+                self.store(Record::Ban { player, until });
+                let kicked = self.kick(player, "Banned by an admin");
+                info!("Banned player {} for {:?} minutes", player, minutes);
+                done(format!(
+                    "Banned player {}, kicked {} connections",
+                    player, kicked
+                ))
+            }
+            AdminCommand::Unban { player } => {
+                if self.bans.remove(&player).is_none() {
+                    return Err(format!("Player {} isn't banned", player));
+                }
+                self.store(Record::Unban { player });
+                info!("Unbanned player {}", player);
+                done(format!("Unbanned player {}", player))
+            }
+            AdminCommand::CloseRoom { room } => {
+                self.close_room(room)?;
+                info!("Closed room {} on behalf of an admin", room);
+                done(format!("Closed room {}", room))
+            }
+            AdminCommand::Announce { message } => {
+                let message = ServerMessage::Announcement { message };
+                for client in self.clients.values() {
+                    client.outbox.send(message.clone());
+                }
+                done(format!("Announced to {} clients", self.clients.len()))
+            }
+            AdminCommand::Save => {
+                if self.shared.log.lock().unwrap().storage.is_none() {
+                    return Err("The server runs without storage".to_owned());
+                }
+                self.compact()
+                    .map_err(|error| format!("Saving failed: {}", error))?;
+                done("Saved the state".to_owned())
+            }
+        }
+    }
+
+    /// Checks whether the client may enter the room, which needs the right
+    /// password if it has one
+    ///
+    /// @return Capacity of the room and whether it allows spectators
+    fn admit(&self, room: RoomId, unlocked: bool) -> Result<(Option<usize>, bool), &'static str> {
+        let room = self.lobby.get(room).ok_or("There is no such room")?;
+        let room = room.lock().unwrap();
+        if room.password.is_some() && !unlocked {
+            return Err("Wrong password");
+        }
+        Ok((room.capacity, room.settings.spectators))
+    }
+
+    fn join(&mut self, client: ClientId, room: RoomId, unlocked: bool) {
+        let capacity = match self.admit(room, unlocked) {
+            Ok((capacity, _)) => capacity,
+            Err(reason) => return self.error(client, reason),
+        };
+        // Players who are in the room with another connection already don't
+        // take more space
+        let players = self.room(room).lock().unwrap().players();
+        let player = self.clients.get(&client).and_then(|client| client.player);
+        let present = player.is_some_and(|player| players.contains(&player));
+        if !present && capacity.is_some_and(|capacity| players.len() >= capacity) {
+            return self.error(client, "The room is full");
+        }
+        self.enter(client, room, Role::Player);
+    }
+
+    fn send(&self, client: ClientId, message: ServerMessage) {
+        if let Some(client) = self.clients.get(&client) {
+            client.outbox.send(message);
+        }
+    }
+
+    /// Sends the client the board of its room
+    fn send_board(&self, client: ClientId) {
+        if let Some((room, _)) = self.clients.get(&client).and_then(|client| client.room) {
+            let delay = &self.shared.delay;
+            self.room(room)
+                .lock()
+                .unwrap()
+                .send_board(room, delay, client);
+        }
+    }
+
+    /// Lets the client watch the game of the room, in race mode the game of
+    /// the player
+    fn spectate(&mut self, client: ClientId, room: RoomId, player: Option<Id>, unlocked: bool) {
+        match self.admit(room, unlocked) {
+            Ok((_, false)) => return self.error(client, "This game doesn't allow spectators"),
+            Ok(_) => (),
+            Err(reason) => return self.error(client, reason),
+        }
+        match player {
+            Some(player) if usize::from(player) >= self.players.len() => {
+                return self.error(client, "There is no such player");
+            }
+            Some(_) => (),
+            None if self.room(room).lock().unwrap().mode() == Mode::Race => {
+                return self.error(client, RACE_WITHOUT_PLAYER);
+            }
+            None => (),
+        }
+        self.enter(client, room, Role::Spectator(player));
+    }
+
+    fn error(&self, client: ClientId, message: &str) {
+        self.send(
+            client,
+            ServerMessage::Error {
+                message: message.to_owned(),
+            },
+        );
+    }
+
+    /// @return Ranks of the period with the names of the players
+    fn ranks(&self, period: Period, puzzle: Option<u64>) -> Vec<Rank> {
+        let leaderboard = self.shared.leaderboard.lock().unwrap();
+        leaderboard
+            .ranking(period, puzzle, now())
+            .into_iter()
+            .map(|entry| Rank {
+                player: entry.player,
+                name: self.players[usize::from(entry.player)].name.clone(),
+                solves: entry.solves,
+                duration: entry.duration,
+                moves: entry.moves,
+            })
+            .collect()
+    }
+
+    fn leaderboard(&self, client: ClientId, period: Period, puzzle: Option<u64>) {
+        let ranks = self.ranks(period, puzzle);
+        self.send(
+            client,
+            ServerMessage::Leaderboard {
+                period,
+                puzzle,
+                ranks,
+            },
+        );
+    }
+
+    /// Logs the client in and puts it into the main room unless it is in a
+    /// room already
+    fn login(&mut self, client: ClientId, name: String, credentials: Credentials) {
+        let existing = self.players.iter().find(|player| player.name == name);
+        let id = match (existing.map(|player| player.id), credentials) {
+            (Some(id), _) if self.banned(id) => return self.error(client, "You are banned"),
+            (Some(id), Credentials::Verified(verified)) if id == verified => id,
+            (None, Credentials::New(passphrase_hash)) => {
+                let id = match Id::try_from(self.players.len()) {
+                    Ok(id) => id,
+                    Err(_) => return self.error(client, "No more players can register"),
+                };
+                info!("Player {} registered as '{}'", id, name);
+                let player = Player {
+                    id,
+                    name,
+                    passphrase_hash,
+                };
+                self.store(Record::Player(player.clone()));
+                self.players.push(player);
+                id
+            }
+            // Somebody else may have registered the name after the check
+            _ => return self.error(client, "Wrong passphrase"),
+        };
+
+        let room = match self.clients.get_mut(&client) {
+            Some(connection) => {
+                connection.player = Some(id);
+                connection.room
+            }
+            None => return,
+        };
+        self.send(client, ServerMessage::Welcome { player: id });
+        match room {
+            Some((room, _)) => {
+                let name = self.players[usize::from(id)].name.clone();
+                let entered = self.room(room);
+                let mut entered = entered.lock().unwrap();
+                if let Some(member) = entered.members.get_mut(&client) {
+                    member.player = Some(id);
+                    member.name = name;
+                }
+                entered.send_board(room, &self.shared.delay, client);
+            }
+            None => self.enter(client, MAIN_ROOM, Role::Player),
+        }
+    }
+}
+
+impl Shared {
+    /// Notifies the players of the game about a change or the client about
+    /// the error. In race mode the other players only learn the progress.
+    fn publish(
+        &self,
+        room: &mut Room,
+        id: RoomId,
+        client: ClientId,
+        player: Id,
+        result: Result<GameChange, GameError>,
+    ) {
+        let change = match result {
+            Ok(change) => change,
+            Err(error) => return room.error(client, &error.to_string()),
+        };
+        metrics::moved();
+        let mode = room.mode();
+        let session = room.session(player);
+        let timed = session.game.changes().last().cloned();
+        let sequence = session.game.changes().len();
+        let percent = session.game.progress();
+        let solved = session.game.is_solved();
+        if let Some(change) = timed {
+            let record = Record::Change { room: id, change };
+            self.log.lock().unwrap().store(record);
+        }
+
+        let delay = &self.delay;
+        let change = ServerMessage::Change { sequence, change };
+        match mode {
+            Mode::Coop | Mode::Solo => {
+                room.broadcast(change.clone());
+                room.send_spectators(id, delay, None, change);
+                if solved {
+                    room.broadcast(ServerMessage::Solved);
+                    room.send_spectators(id, delay, None, ServerMessage::Solved);
+                }
+            }
+            Mode::Race => {
+                room.send_player(player, change.clone());
+                room.send_spectators(id, delay, Some(player), change);
+                let progress = ServerMessage::RaceProgress { player, percent };
+                room.broadcast(progress.clone());
+                room.send_spectators(id, delay, None, progress);
+                if solved {
+                    room.send_player(player, ServerMessage::Solved);
+                    room.send_spectators(id, delay, Some(player), ServerMessage::Solved);
+                    if room.winner.is_none() {
+                        room.winner = Some(player);
+                        info!("Player {} won the race in room {}", player, id);
+                        let won = ServerMessage::RaceWon {
+                            player,
+                            name: room.members[&client].name.clone(),
+                        };
+                        room.broadcast(won.clone());
+                        room.send_spectators(id, delay, None, won);
+                    }
+                }
+            }
+        }
+        if solved {
+            self.record_solves(room, player);
+        }
+    }
+
+    /// Adds the solves of the game of the player to the leaderboard, if that
+    /// didn't happen yet
+    fn record_solves(&self, room: &mut Room, player: Id) {
+        let session = room.session(player);
+        if session.recorded {
+            return;
+        }
+        session.recorded = true;
+        let mut leaderboard = self.leaderboard.lock().unwrap();
+        for solve in session.game.solves() {
+            match leaderboard.record(solve.clone()) {
+                Ok(()) => {
+                    metrics::solved(true);
+                    self.log.lock().unwrap().store(Record::Solve(solve));
+                }
+                Err(reason) => {
+                    metrics::solved(false);
+                    warn!("Rejected solve of player {}: {}", solve.player, reason);
+                }
+            }
+        }
+    }
+}
+
+/// Copies the game of the player for a hint to the client
+fn hint_task(room: &mut Room, client: ClientId, player: Id) -> Result<HintTask, &'static str> {
+    if room.mode() == Mode::Race {
+        return Err("There are no hints in race mode");
+    }
+    let outbox = room.members[&client].outbox.clone();
+    let game = &room.session(player).game;
+    Ok(HintTask {
+        outbox,
+        width: game.width,
+        height: game.height,
+        dots: game.puzzle().dots.to_vec(),
+        assignment: game.assignment(),
+        solution: game.solution().clone(),
+    })
+}
+
+/// Copy of a game, so its hint can be searched without holding the lock
+/// or blocking the room
+pub struct HintTask {
+    /// Outbox of the client who asked for the hint
+    outbox: Outbox,
+    width: usize,
+    height: usize,
+    dots: Vec<DotPos>,
+    assignment: Assignment,
+    solution: Galaxies,
+}
+
+impl HintTask {
+    /// Searches the hint and sends it to the client
+    pub fn run(self) {
+        let message = self.hint();
+        self.outbox.send(message);
+    }
+
+    /// @return Hint for the player or an error if there is none
+    fn hint(&self) -> ServerMessage {
+        let puzzle = Puzzle {
+            width: self.width,
+            height: self.height,
+            dots: &self.dots,
+        };
+        let error = |message: &str| ServerMessage::Error {
+            message: message.to_owned(),
+        };
+        let found = match hint::hint(&puzzle, &self.assignment, &self.solution) {
+            Ok(Some(found)) => found,
+            Ok(None) => return error("The puzzle is solved already"),
+            Err(Exhausted) => return error("There is no hint for the current state"),
+        };
+
+        let to_position = |(x, y): (usize, usize)| Position(x as Offset, y as Offset);
+        let reason = match found.kind {
+            hint::HintKind::Deduction(Reason::UnderDot) => HintReason::UnderDot,
+            hint::HintKind::Deduction(Reason::Mirror) => HintReason::Mirror,
+            hint::HintKind::Deduction(Reason::OnlyCandidate) => HintReason::OnlyCandidate,
+            hint::HintKind::Mistake => HintReason::Mistake,
+            hint::HintKind::Reveal => HintReason::Reveal,
+        };
+        ServerMessage::Hint {
+            reason,
+            field: to_position(found.field),
+            dot: found.dot.map(|dot| dot as Id),
+            involved: found.involved.iter().copied().map(to_position).collect(),
+            text: found.describe(&puzzle),
+        }
+    }
+}
+
+/// @return Main room with a board generated with the config, an error if the
+/// generator fails for it
+fn main_room(config: &GenerationConfig) -> io::Result<Room> {
+    let (board, _) = gamegen::generate_reliably(config)
+        .ok_or_else(|| io::Error::other("Generating the board of the main room failed"))?;
+    Ok(Room::new(
+        MAIN_ROOM_NAME.to_owned(),
+        Mode::Coop,
+        Settings::default(),
+        board,
+    ))
+}
+
+impl Galaxy {
+    /// Starts with a main room whose board is generated with the config
+    ///
+    /// @return Galaxy, an error if the generator fails for the config
+    pub fn new(config: &GenerationConfig) -> io::Result<Self> {
+        let main = main_room(config)?;
+        let lobby = Lobby::new(vec![(MAIN_ROOM, main)].into_iter().collect());
+        Ok(Galaxy::with(lobby, Leaderboard::default(), None, 0))
+    }
+
+    /// @return Galaxy without storage whose main room uses TEST_CONFIG
+    #[cfg(test)]
+    pub fn for_tests() -> Self {
+        Galaxy::new(&TEST_CONFIG).expect("Generating the test board failed")
+    }
+
+    /// @return Galaxy with the lobby, the leaderboard and the storage, which
+    /// contains the amount of records
+    fn with(
+        lobby: Lobby,
+        leaderboard: Leaderboard,
+        storage: Option<Storage>,
+        records: usize,
+    ) -> Self {
+        let shared = Arc::new(Shared {
+            leaderboard: Mutex::new(leaderboard),
+            log: Mutex::new(Log {
+                storage,
+                compacted: records,
+                appended: 0,
+            }),
+            delay: Delay::new(),
+        });
+        Galaxy {
+            state: Mutex::new(State::new(lobby, Arc::clone(&shared))),
+            shared,
+            daily: Arc::default(),
+            max_board_size: MAX_BOARD_SIZE,
+        }
+    }
+
+    /// Restores players and the rooms from the log at the path. The game of
+    /// the main room is generated with the config if the log doesn't contain
+    /// one yet.
+    pub fn open(config: &GenerationConfig, path: &Path) -> io::Result<Self> {
+        let (storage, records) = Storage::open(path)?;
+        let stored = records.len();
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let mut players = Vec::new();
+        let mut leaderboard = Leaderboard::default();
+        let mut rooms = BTreeMap::new();
+        let mut opened = HashMap::new();
+        let mut bans = HashMap::new();
+        let mut changes = 0;
+        for record in records {
+            match record {
+                Record::Player(player) => {
+                    if usize::from(player.id) != players.len() {
+                        return Err(invalid("Players are stored out of order"));
+                    }
+                    players.push(player);
+                }
+                Record::Open {
+                    room,
+                    name,
+                    password,
+                    capacity,
+                } => {
+                    opened.insert(room, (name, password, capacity));
+                }
+                Record::Room {
+                    room,
+                    mode,
+                    settings,
+                    board,
+                } => restore_game(&mut rooms, &mut opened, room, mode, settings, board)?,
+                Record::Change { room, change } => {
+                    let room = rooms
+                        .get_mut(&room)
+                        .ok_or_else(|| invalid("Change of a room without game"))?;
+                    let game = &mut room.session(change.change.player).game;
+                    if usize::from(change.change.affected_field) >= game.fields.len() {
+                        return Err(invalid("Change of an unknown field"));
+                    }
+                    let dots = [change.change.new_association, change.change.old_association];
+                    if dots
+                        .iter()
+                        .flatten()
+                        .any(|&dot| usize::from(dot) >= game.dots.len())
+                    {
+                        return Err(invalid("Change of an unknown dot"));
+                    }
+                    game.apply(change);
+                    changes += 1;
+                }
+                Record::Solve(solve) => {
+                    if usize::from(solve.player) >= players.len() {
+                        return Err(invalid("Solve of an unknown player"));
+                    }
+                    leaderboard
+                        .record(solve)
+                        .map_err(|reason| invalid(&reason))?;
+                }
+                Record::Close { room } => {
+                    rooms.remove(&room);
+                    opened.remove(&room);
+                }
+                Record::Ban { player, until } => {
+                    bans.insert(player, until);
+                }
+                Record::Unban { player } => {
+                    bans.remove(&player);
+                }
+            }
+        }
+        info!(
+            "Restored {} players, {} rooms and {} changes from {:?}",
+            players.len(),
+            rooms.len(),
+            changes,
+            path
+        );
+
+        let restored_main = rooms.contains_key(&MAIN_ROOM);
+        if !restored_main {
+            rooms.insert(MAIN_ROOM, main_room(config)?);
+        }
+        // Players get the chance to come back before the rooms are closed
+        let now = Instant::now();
+        for room in rooms.values_mut() {
+            room.restored();
+            room.empty_since = Some(now);
+        }
+
+        let galaxy = Galaxy::with(Lobby::new(rooms), leaderboard, Some(storage), stored);
+        {
+            let mut state = galaxy.state.lock().unwrap();
+            state.players = players;
+            state.bans = bans;
+            if !restored_main {
+                let board = state.room(MAIN_ROOM).lock().unwrap().board().clone();
+                state.store(Record::Room {
+                    room: MAIN_ROOM,
+                    mode: Mode::Coop,
+                    settings: Settings::default(),
+                    board,
+                });
+            }
+        }
+        Ok(galaxy)
+    }
+
+    /// Sets the biggest width and height of boards clients may ask for
+    pub fn max_board_size(mut self, size: usize) -> Self {
+        self.max_board_size = size;
+        self
+    }
+
+    /// @return Whether clients may use boards of the size
+    pub fn supports(&self, width: usize, height: usize) -> bool {
+        (1..=self.max_board_size).contains(&width) && (1..=self.max_board_size).contains(&height)
+    }
+
+    /// Checks the board size clients asked for
+    pub fn generation_config(
+        &self,
+        width: usize,
+        height: usize,
+        difficulty: Difficulty,
+        seed: Option<u64>,
+    ) -> Result<GenerationConfig, &'static str> {
+        if !self.supports(width, height) {
+            return Err("Unsupported board size");
+        }
+        Ok(GenerationConfig {
+            width,
+            height,
+            difficulty,
+            seed: seed.unwrap_or_else(rand::random),
+        })
+    }
+
+    /// Generates the daily puzzles in the background from now on
+    pub fn schedule_daily(&self) {
+        Arc::clone(&self.daily).schedule();
+    }
+
+    /// Registers a new connection, which receives messages over the outbox.
+    pub fn connect(&self, outbox: Outbox) -> ClientId {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_client;
+        state.next_client += 1;
+        state.clients.insert(
+            id,
+            Client {
+                outbox,
+                player: None,
+                room: None,
+            },
+        );
+        id
+    }
+
+    pub fn disconnect(&self, client: ClientId) {
+        let mut state = self.state.lock().unwrap();
+        state.leave(client);
+        state.clients.remove(&client);
+        state.cleanup(Instant::now());
+    }
+
+    /// Looks for a puzzle in the rooms and the daily puzzles
+    ///
+    /// @return Board with the puzzle id, see Board::id
+    pub fn puzzle(&self, puzzle: u64) -> Option<Board> {
+        let stored = {
+            let state = self.state.lock().unwrap();
+            let found = state.lobby.rooms().find_map(|(_, room)| {
+                let room = room.lock().unwrap();
+                (room.board().id() == puzzle).then(|| room.board().clone())
+            });
+            found
+        };
+        stored.or_else(|| self.daily.find(puzzle))
+    }
+
+    /// @return Ranks of the period, limited to the puzzle if there is one
+    pub fn ranking(&self, period: Period, puzzle: Option<u64>) -> Vec<Rank> {
+        self.state.lock().unwrap().ranks(period, puzzle)
+    }
+
+    /// Executes a command of the admin console
+    pub fn administrate(&self, command: AdminCommand) -> AdminResponse {
+        let mut state = self.state.lock().unwrap();
+        state
+            .administrate(command)
+            .unwrap_or_else(|message| AdminResponse::Error { message })
+    }
+
+    /// Tells every client that the server shuts down and saves the state.
+    /// Messages which arrive afterwards are ignored.
+    pub fn shut_down(&self) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.closing = true;
+        let clients: Vec<ClientId> = state.clients.keys().copied().collect();
+        for client in clients {
+            state.send(client, ServerMessage::ShuttingDown);
+        }
+        state.compact()
+    }
+
+    /// @return Numbers of rooms, clients and players online
+    pub fn census(&self) -> Census {
+        let state = self.state.lock().unwrap();
+        let players: HashSet<Id> = state
+            .clients
+            .values()
+            .filter_map(|client| client.player)
+            .collect();
+        Census {
+            rooms: state.lobby.rooms().count(),
+            clients: state.clients.len(),
+            players: players.len(),
+        }
+    }
+
+    /// @return Player the client is logged in as
+    pub fn player_of(&self, client: ClientId) -> Option<Id> {
+        let state = self.state.lock().unwrap();
+        state.clients.get(&client)?.player
+    }
+
+    /// @return Room the client is in
+    pub fn room_of(&self, client: ClientId) -> Option<RoomId> {
+        let state = self.state.lock().unwrap();
+        let (room, _) = state.clients.get(&client)?.room?;
+        Some(room)
+    }
+
+    /// Checks the passphrase of the player with the name without holding the
+    /// lock
+    fn check(&self, name: &str, passphrase: &Secret) -> Credentials {
+        let existing = {
+            let state = self.state.lock().unwrap();
+            let existing = state.players.iter().find(|player| player.name == name);
+            existing.map(|player| (player.id, player.passphrase_hash.clone()))
+        };
+        match existing {
+            Some((id, hash)) if hash.matches(passphrase) => Credentials::Verified(id),
+            Some(_) => Credentials::Wrong,
+            None => Credentials::New(SecretHash::new(passphrase)),
+        }
+    }
+
+    /// Checks the password of the room without holding the lock
+    ///
+    /// @return Whether the room has a password and it is the given one
+    fn unlocks(&self, room: RoomId, password: Option<&Secret>) -> bool {
+        let password = match password {
+            Some(password) => password,
+            None => return false,
+        };
+        let room = self.state.lock().unwrap().lobby.get(room).map(Arc::clone);
+        let hash = room.and_then(|room| room.lock().unwrap().password.clone());
+        hash.is_some_and(|hash| hash.matches(password))
+    }
+
+    /// Processes a message of a client and answers or notifies all clients
+    ///
+    /// @return Search for the hint the client asked for, which is left to
+    /// the caller because it may take a while
+    pub fn handle(&self, client: ClientId, message: ClientMessage) -> Option<HintTask> {
+        let message = match message {
+            ClientMessage::Login { name, passphrase } => ClientMessage::Login {
+                name: name.trim().to_owned(),
+                passphrase,
+            },
+            message => message,
+        };
+        // Boards are generated before locking, because big boards take a
+        // while and other rooms shouldn't wait for them
+        let (logged_in, _context) = {
+            let state = self.state.lock().unwrap();
+            let mut fields = vec![("client", Value::from(client))];
+            if let Some(known) = state.clients.get(&client) {
+                fields.extend(known.player.map(|player| ("player", Value::from(player))));
+                fields.extend(known.room.map(|(room, _)| ("room", Value::from(room))));
+            }
+            let logged_in = state
+                .clients
+                .get(&client)
+                .is_some_and(|client| client.player.is_some());
+            (logged_in, logger::context(fields))
+        };
+        let generated = match &message {
+            ClientMessage::NewGame {
+                width,
+                height,
+                difficulty,
+                seed,
+                ..
+            }
+            | ClientMessage::CreateRoom {
+                width,
+                height,
+                difficulty,
+                seed,
+                ..
+            } if logged_in => Some(
+                self.generation_config(*width, *height, *difficulty, *seed)
+                    .and_then(|config| {
+                        gamegen::generate_reliably(&config).ok_or("Generating the board failed")
+                    }),
+            ),
+            _ => None,
+        };
+        let daily = match &message {
+            ClientMessage::Daily { difficulty } if logged_in => {
+                let day = daily::today();
+                let board = self.daily.board(day, *difficulty);
+                Some((day, board.ok_or("Generating the daily puzzle failed")))
+            }
+            _ => None,
+        };
+        // Passwords and passphrases are hashed before locking as well,
+        // because hashing is slow on purpose
+        let credentials = match &message {
+            ClientMessage::Login { name, passphrase }
+                if !name.is_empty() && name.chars().count() <= MAX_PLAYER_NAME =>
+            {
+                Some(self.check(name, passphrase))
+            }
+            _ => None,
+        };
+        let password_hash = match &message {
+            ClientMessage::CreateRoom {
+                password: Some(password),
+                ..
+            } if logged_in => Some(SecretHash::new(password)),
+            _ => None,
+        };
+        let unlocked = match &message {
+            ClientMessage::Join { room, password }
+            | ClientMessage::Spectate { room, password, .. } => {
+                self.unlocks(*room, password.as_ref())
+            }
+            _ => false,
+        };
+
+        let mut state = self.state.lock().unwrap();
+        if state.closing {
+            return None;
+        }
+        let (player, place) = match state.clients.get(&client) {
+            Some(client) => (client.player, client.room),
+            None => return None,
+        };
+        let (message, player, room) = match (message, player, place) {
+            (ClientMessage::Login { name, .. }, _, _) => {
+                match credentials {
+                    Some(credentials) => state.login(client, name, credentials),
+                    None => state.error(client, "Unsupported player name"),
+                }
+                return None;
+            }
+            (ClientMessage::Rooms, _, _) => {
+                state.cleanup(Instant::now());
+                state.rooms(client);
+                return None;
+            }
+            (ClientMessage::Spectate { room, player, .. }, _, _) => {
+                state.cleanup(Instant::now());
+                state.spectate(client, room, player, unlocked);
+                return None;
+            }
+            (ClientMessage::Leave, _, _) => {
+                match state.leave(client) {
+                    Some(room) => state.send(client, ServerMessage::Left { room }),
+                    None => state.error(client, "You aren't in a room"),
+                }
+                return None;
+            }
+            (ClientMessage::Leaderboard { period, puzzle }, _, _) => {
+                state.leaderboard(client, period, puzzle);
+                return None;
+            }
+            (ClientMessage::Resync { .. }, _, Some((_, Role::Spectator(_)))) => {
+                state.send_board(client);
+                return None;
+            }
+            (_, None, _) => {
+                state.error(client, "Login first");
+                return None;
+            }
+            (
+                ClientMessage::CreateRoom {
+                    name,
+                    mode,
+                    settings,
+                    capacity,
+                    ..
+                },
+                Some(player),
+                _,
+            ) => {
+                let name = name.trim().to_owned();
+                if name.is_empty() || name.chars().count() > MAX_ROOM_NAME {
+                    state.error(client, "Unsupported room name");
+                    return None;
+                }
+                let capacity = if mode == Mode::Solo {
+                    Some(1)
+                } else {
+                    capacity
+                };
+                if capacity == Some(0) {
+                    state.error(client, "Rooms need space for a player");
+                    return None;
+                }
+                if let Err(reason) = settings.check() {
+                    state.error(client, reason);
+                    return None;
+                }
+                let (board, config) = match generated {
+                    Some(Ok(generated)) => generated,
+                    Some(Err(reason)) => {
+                        state.error(client, reason);
+                        return None;
+                    }
+                    None => unreachable!("Boards are generated for logged in players"),
+                };
+                let mut room = Room::new(name, mode, settings, board);
+                room.password = password_hash;
+                room.capacity = capacity;
+                state.cleanup(Instant::now());
+                let id = state.open_room(room);
+                info!(
+                    seed = config.seed;
+                    "Player {} opened room {} with a {:?} game with {:?}",
+                    player, id, mode, config
+                );
+                state.enter(client, id, Role::Player);
+                return None;
+            }
+            (ClientMessage::Join { room, .. }, Some(_), _) => {
+                state.cleanup(Instant::now());
+                state.join(client, room, unlocked);
+                return None;
+            }
+            (_, Some(_), Some((_, Role::Spectator(_)))) => {
+                state.error(client, "Spectators can't play");
+                return None;
+            }
+            (_, Some(_), None) => {
+                state.error(client, "Join a room first");
+                return None;
+            }
+            (
+                ClientMessage::NewGame { mode, settings, .. },
+                Some(player),
+                Some((room, Role::Player)),
+            ) => {
+                let (board, config) = match generated {
+                    Some(Ok(generated)) => generated,
+                    Some(Err(reason)) => {
+                        state.error(client, reason);
+                        return None;
+                    }
+                    None => unreachable!("Boards are generated for logged in players"),
+                };
+                let capacity = state.room(room).lock().unwrap().capacity;
+                if mode == Mode::Solo && capacity != Some(1) {
+                    state.error(client, "Solo games need a room of their own");
+                    return None;
+                }
+                if let Err(reason) = settings.check() {
+                    state.error(client, reason);
+                    return None;
+                }
+                info!(
+                    seed = config.seed;
+                    "Player {} started a new {:?} game in room {} with {:?}",
+                    player, mode, room, config
+                );
+                state.start_game(room, mode, settings, board);
+                return None;
+            }
+            (ClientMessage::Daily { difficulty }, Some(player), Some((room, Role::Player))) => {
+                let (day, board) = match daily {
+                    Some((day, Ok(board))) => (day, board),
+                    Some((_, Err(reason))) => {
+                        state.error(client, reason);
+                        return None;
+                    }
+                    None => unreachable!("Daily puzzles are fetched for logged in players"),
+                };
+                info!(
+                    "Player {} started the {} daily puzzle of {} in room {}",
+                    player,
+                    difficulty,
+                    daily::date(day),
+                    room
+                );
+                let (mode, settings) = {
+                    let current = state.room(room);
+                    let current = current.lock().unwrap();
+                    (current.mode(), current.settings)
+                };
+                state.start_game(room, mode, settings, board);
+                return None;
+            }
+            (message, Some(player), Some((room, Role::Player))) => (message, player, room),
+        };
+
+        // Moves only lock their room, so other rooms go on meanwhile
+        let locked = state.room(room);
+        drop(state);
+        let hint = self.play(locked.lock().unwrap(), room, client, player, message);
+        self.compact_if_bloated();
+        hint
+    }
+
+    /// Processes a message of a player in the room
+    ///
+    /// @return Search for the hint the player asked for
+    fn play(
+        &self,
+        mut room: MutexGuard<Room>,
+        id: RoomId,
+        client: ClientId,
+        player: Id,
+        message: ClientMessage,
+    ) -> Option<HintTask> {
+        // The client may have left the room after the lobby was unlocked
+        if room.playing(client) != Some(player) {
+            return None;
+        }
+        match message {
+            ClientMessage::Login { .. }
+            | ClientMessage::Rooms
+            | ClientMessage::CreateRoom { .. }
+            | ClientMessage::Join { .. }
+            | ClientMessage::Leave
+            | ClientMessage::Spectate { .. }
+            | ClientMessage::Leaderboard { .. }
+            | ClientMessage::NewGame { .. }
+            | ClientMessage::Daily { .. } => unreachable!(),
+            ClientMessage::Assign { field, dot } => {
+                let result = room.session(player).game.assign(player, field, dot);
+                self.shared.publish(&mut room, id, client, player, result);
+            }
+            ClientMessage::Hint => {
+                // The solver may take a while, so the room doesn't wait for it
+                match hint_task(&mut room, client, player) {
+                    Ok(task) => return Some(task),
+                    Err(reason) => room.error(client, reason),
+                }
+            }
+            ClientMessage::Undo => {
+                let result = room.session(player).game.undo(player);
+                self.shared.publish(&mut room, id, client, player, result);
+            }
+            ClientMessage::Redo => {
+                let result = room.session(player).game.redo(player);
+                self.shared.publish(&mut room, id, client, player, result);
+            }
+            ClientMessage::Progress => {
+                let game = &room.session(player).game;
+                let message = ServerMessage::Progress {
+                    percent: game.progress(),
+                    contributions: game.contributions(),
+                };
+                room.send(client, message);
+            }
+            ClientMessage::Snapshot { changes } => {
+                let game = &room.session(player).game;
+                let total = game.changes().len();
+                let changes = changes.unwrap_or(total).min(total);
+                let message = ServerMessage::Snapshot {
+                    changes,
+                    fields: game.state_at(changes),
+                };
+                room.send(client, message);
+            }
+            ClientMessage::Replay => {
+                let replay = Replay::new(&room.session(player).game);
+                room.send(client, ServerMessage::Replay(replay))
+            }
+            ClientMessage::Resync {
+                puzzle,
+                game,
+                sequence,
+            } => {
+                let delay = &self.shared.delay;
+                room.resync(id, delay, client, player, (puzzle, game), sequence)
+            }
+        }
+        None
+    }
+
+    /// Compacts the log once enough records were appended. Everything is
+    /// locked meanwhile, so this doesn't happen with every new game.
+    fn compact_if_bloated(&self) {
+        if !self.shared.log.lock().unwrap().bloated() {
+            return;
+        }
+        let state = self.state.lock().unwrap();
+        // Another thread may have compacted it while this one waited
+        if !self.shared.log.lock().unwrap().bloated() {
+            return;
+        }
+        if let Err(error) = state.compact() {
+            warn!("Compacting the log failed: {}", error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::lobby::EMPTY_ROOM_LIFETIME;
+    use super::*;
+    use crate::gamegen::Difficulty;
+    use std::fs;
+    use std::process;
+
+    fn login(galaxy: &Galaxy, name: &str) -> (ClientId, Inbox) {
+        let (outbox, inbox) = outbox(OUTBOX_CAPACITY);
+        let client = galaxy.connect(outbox);
+        galaxy.handle(
+            client,
+            ClientMessage::Login {
+                name: name.to_owned(),
+                passphrase: Secret::default(),
+            },
+        );
+        (client, inbox)
+    }
+
+    #[test]
+    fn race_shares_only_progress() {
+        let galaxy = Galaxy::for_tests();
+        let (first, mut first_inbox) = login(&galaxy, "first");
+        let (_, mut second_inbox) = login(&galaxy, "second");
+        galaxy.handle(
+            first,
+            ClientMessage::NewGame {
+                width: 4,
+                height: 4,
+                difficulty: Difficulty::Normal,
+                seed: Some(2),
+                mode: Mode::Race,
+                settings: Settings::default(),
+            },
+        );
+        first_inbox.try_iter().count();
+        second_inbox.try_iter().count();
+
+        let main = galaxy.state.lock().unwrap().room(MAIN_ROOM);
+        let solution = main.lock().unwrap().session(0).game.solution().clone();
+        for (x, column) in solution.iter().enumerate() {
+            for (y, &dot) in column.iter().enumerate() {
+                let field = Position(x as Offset, y as Offset);
+                let dot = Some(dot as Id);
+                galaxy.handle(first, ClientMessage::Assign { field, dot });
+            }
+        }
+
+        let first_messages: Vec<ServerMessage> = first_inbox.try_iter().collect();
+        let second_messages: Vec<ServerMessage> = second_inbox.try_iter().collect();
+        assert!(first_messages.contains(&ServerMessage::Solved));
+        assert!(!second_messages.iter().any(|message| matches!(
+            message,
+            ServerMessage::Change { .. } | ServerMessage::Solved
+        )));
+        assert!(second_messages.contains(&ServerMessage::RaceProgress {
+            player: 0,
+            percent: 100
+        }));
+        assert!(second_messages.contains(&ServerMessage::RaceWon {
+            player: 0,
+            name: "first".to_owned()
+        }));
+
+        let mut main = main.lock().unwrap();
+        let fields = &main.session(1).game.fields;
+        assert!(fields.iter().all(|field| field.assigned_dot.is_none()));
+    }
+
+    #[test]
+    fn spectators_watch_but_cannot_play() {
+        let galaxy = Galaxy::for_tests();
+        let (player, mut player_inbox) = login(&galaxy, "player");
+        let (outbox, mut inbox) = outbox(OUTBOX_CAPACITY);
+        let spectator = galaxy.connect(outbox);
+        let spectate = ClientMessage::Spectate {
+            room: MAIN_ROOM,
+            player: None,
+            password: None,
+        };
+        galaxy.handle(spectator, spectate.clone());
+        assert!(matches!(inbox.try_recv(), Ok(ServerMessage::Joined { .. })));
+        assert!(matches!(inbox.try_recv(), Ok(ServerMessage::Board { .. })));
+
+        let field = Position(0, 0);
+        galaxy.handle(
+            spectator,
+            ClientMessage::Assign {
+                field,
+                dot: Some(0),
+            },
+        );
+        assert!(matches!(inbox.try_recv(), Ok(ServerMessage::Error { .. })));
+        galaxy.handle(
+            player,
+            ClientMessage::Assign {
+                field,
+                dot: Some(0),
+            },
+        );
+        let change = inbox.try_recv().unwrap();
+        assert!(matches!(change, ServerMessage::Change { .. }));
+        assert!(player_inbox.try_iter().any(|message| message == change));
+
+        galaxy.handle(
+            player,
+            ClientMessage::NewGame {
+                width: 3,
+                height: 3,
+                difficulty: Difficulty::Normal,
+                seed: Some(2),
+                mode: Mode::Coop,
+                settings: Settings {
+                    spectators: false,
+                    spectator_delay: 0,
+                },
+            },
+        );
+        assert!(matches!(inbox.try_recv(), Ok(ServerMessage::Error { .. })));
+        galaxy.handle(spectator, spectate);
+        assert!(matches!(inbox.try_recv(), Ok(ServerMessage::Error { .. })));
+    }
+
+    #[test]
+    fn race_spectators_choose_a_player() {
+        let galaxy = Galaxy::for_tests();
+        let (player, _player_inbox) = login(&galaxy, "player");
+        let (outbox, mut inbox) = outbox(OUTBOX_CAPACITY);
+        let spectator = galaxy.connect(outbox);
+        let spectate = |player| ClientMessage::Spectate {
+            room: MAIN_ROOM,
+            player,
+            password: None,
+        };
+        galaxy.handle(spectator, spectate(None));
+        inbox.try_iter().count();
+
+        galaxy.handle(
+            player,
+            ClientMessage::NewGame {
+                width: 4,
+                height: 4,
+                difficulty: Difficulty::Normal,
+                seed: Some(2),
+                mode: Mode::Race,
+                settings: Settings::default(),
+            },
+        );
+        let error = ServerMessage::Error {
+            message: RACE_WITHOUT_PLAYER.to_owned(),
+        };
+        assert!(inbox.try_iter().any(|message| message == error));
+        galaxy.handle(spectator, spectate(None));
+        assert_eq!(inbox.try_recv().ok(), Some(error));
+
+        galaxy.handle(spectator, spectate(Some(0)));
+        assert!(matches!(inbox.try_recv(), Ok(ServerMessage::Joined { .. })));
+        assert!(matches!(inbox.try_recv(), Ok(ServerMessage::Board { .. })));
+    }
+
+    #[test]
+    fn spectator_delays_are_limited() {
+        let galaxy = Galaxy::for_tests();
+        let (client, mut inbox) = login(&galaxy, "player");
+        inbox.try_iter().count();
+        let settings = Settings {
+            spectators: true,
+            spectator_delay: u64::MAX,
+        };
+        galaxy.handle(
+            client,
+            ClientMessage::NewGame {
+                width: 4,
+                height: 4,
+                difficulty: Difficulty::Normal,
+                seed: Some(2),
+                mode: Mode::Coop,
+                settings,
+            },
+        );
+        galaxy.handle(
+            client,
+            ClientMessage::CreateRoom {
+                name: "Slow".to_owned(),
+                width: 4,
+                height: 4,
+                difficulty: Difficulty::Normal,
+                seed: Some(2),
+                mode: Mode::Coop,
+                settings,
+                password: None,
+                capacity: None,
+            },
+        );
+        let errors = inbox
+            .try_iter()
+            .filter(|message| matches!(message, ServerMessage::Error { .. }))
+            .count();
+        assert_eq!(errors, 2);
+    }
+
+    #[test]
+    fn rooms_progress_independently() {
+        let galaxy = Galaxy::for_tests();
+        let (client, mut inbox) = login(&galaxy, "player");
+        galaxy.handle(
+            client,
+            ClientMessage::CreateRoom {
+                name: "Other".to_owned(),
+                width: 4,
+                height: 4,
+                difficulty: Difficulty::Normal,
+                seed: Some(2),
+                mode: Mode::Coop,
+                settings: Settings::default(),
+                password: None,
+                capacity: None,
+            },
+        );
+        inbox.try_iter().count();
+
+        // Moves in the other room don't need the lock of the main room
+        let main = galaxy.state.lock().unwrap().room(MAIN_ROOM);
+        let _busy = main.lock().unwrap();
+        let field = Position(0, 0);
+        galaxy.handle(
+            client,
+            ClientMessage::Assign {
+                field,
+                dot: Some(0),
+            },
+        );
+        assert!(matches!(inbox.try_recv(), Ok(ServerMessage::Change { .. })));
+    }
+
+    #[test]
+    fn hints_are_searched_by_the_caller() {
+        let galaxy = Galaxy::for_tests();
+        let (client, mut inbox) = login(&galaxy, "player");
+        inbox.try_iter().count();
+
+        let task = galaxy.handle(client, ClientMessage::Hint).unwrap();
+        assert!(inbox.try_recv().is_err());
+        task.run();
+        assert!(matches!(inbox.try_recv(), Ok(ServerMessage::Hint { .. })));
+    }
+
+    #[test]
+    fn resync_sends_missed_changes() {
+        let galaxy = Galaxy::for_tests();
+        let (client, mut inbox) = login(&galaxy, "player");
+        for x in 0..3 {
+            let field = Position(x, 0);
+            galaxy.handle(
+                client,
+                ClientMessage::Assign {
+                    field,
+                    dot: Some(0),
+                },
+            );
+        }
+        let (puzzle, game) = inbox
+            .try_iter()
+            .filter_map(|message| match message {
+                ServerMessage::Board { puzzle, game, .. } => Some((puzzle, game)),
+                _ => None,
+            })
+            .next()
+            .unwrap();
+        inbox.try_iter().count();
+
+        galaxy.handle(
+            client,
+            ClientMessage::Resync {
+                puzzle,
+                game,
+                sequence: 1,
+            },
+        );
+        let sequences: Vec<usize> = inbox
+            .try_iter()
+            .map(|message| match message {
+                ServerMessage::Change { sequence, .. } => sequence,
+                other => panic!("Unexpected {:?}", other),
+            })
+            .collect();
+        assert_eq!(sequences, vec![2, 3]);
+
+        galaxy.handle(
+            client,
+            ClientMessage::Resync {
+                puzzle: puzzle + 1,
+                game,
+                sequence: 1,
+            },
+        );
+        assert!(matches!(
+            inbox.try_recv(),
+            Ok(ServerMessage::Board { sequence: 3, .. })
+        ));
+
+        // The same board started again is another game
+        let board = {
+            let main = galaxy.state.lock().unwrap().room(MAIN_ROOM);
+            let board = main.lock().unwrap().board().clone();
+            board
+        };
+        let mode = Mode::Coop;
+        galaxy
+            .state
+            .lock()
+            .unwrap()
+            .start_game(MAIN_ROOM, mode, Settings::default(), board);
+        inbox.try_iter().count();
+        galaxy.handle(
+            client,
+            ClientMessage::Resync {
+                puzzle,
+                game,
+                sequence: 0,
+            },
+        );
+        assert!(matches!(
+            inbox.try_recv(),
+            Ok(ServerMessage::Board { sequence: 0, game: next, .. }) if next == game + 1
+        ));
+    }
+
+    #[test]
+    fn rooms_are_locked_limited_restored_and_closed() {
+        let path = std::env::temp_dir().join(format!("galaxy-rooms-{}.log", process::id()));
+        let _ = fs::remove_file(&path);
+        let galaxy = Galaxy::open(&TEST_CONFIG, &path).unwrap();
+        let (owner, mut owner_inbox) = login(&galaxy, "owner");
+        let (guest, mut guest_inbox) = login(&galaxy, "guest");
+        galaxy.handle(
+            owner,
+            ClientMessage::CreateRoom {
+                name: "Secret".to_owned(),
+                width: 4,
+                height: 4,
+                difficulty: Difficulty::Easy,
+                seed: Some(3),
+                mode: Mode::Coop,
+                settings: Settings::default(),
+                password: Some(Secret("xyzzy".to_owned())),
+                capacity: Some(1),
+            },
+        );
+        let room = owner_inbox
+            .try_iter()
+            .filter_map(|message| match message {
+                ServerMessage::Joined { room, .. } => Some(room),
+                _ => None,
+            })
+            .last()
+            .unwrap();
+        let field = Position(0, 0);
+        galaxy.handle(
+            owner,
+            ClientMessage::Assign {
+                field,
+                dot: Some(0),
+            },
+        );
+        guest_inbox.try_iter().count();
+
+        let join = |password: &str| ClientMessage::Join {
+            room,
+            password: Some(Secret(password.to_owned())),
+        };
+        galaxy.handle(guest, join("plugh"));
+        galaxy.handle(guest, join("xyzzy"));
+        let errors: Vec<ServerMessage> = guest_inbox.try_iter().collect();
+        assert_eq!(
+            errors,
+            vec![
+                ServerMessage::Error {
+                    message: "Wrong password".to_owned()
+                },
+                ServerMessage::Error {
+                    message: "The room is full".to_owned()
+                }
+            ]
+        );
+
+        galaxy.handle(guest, ClientMessage::Rooms);
+        match guest_inbox.try_recv() {
+            Ok(ServerMessage::Rooms { rooms }) => {
+                let listed: Vec<(RoomId, usize, bool)> = rooms
+                    .iter()
+                    .map(|info| (info.room, info.players, info.locked))
+                    .collect();
+                assert_eq!(listed, vec![(MAIN_ROOM, 1, false), (room, 1, true)]);
+            }
+            other => panic!("Unexpected {:?}", other),
+        }
+        drop(galaxy);
+        assert!(!fs::read_to_string(&path).unwrap().contains("xyzzy"));
+
+        let galaxy = Galaxy::open(&TEST_CONFIG, &path).unwrap();
+        {
+            let mut state = galaxy.state.lock().unwrap();
+            let restored = state.room(room);
+            let mut restored = restored.lock().unwrap();
+            let password = Secret("xyzzy".to_owned());
+            assert!(restored.password.as_ref().unwrap().matches(&password));
+            assert_eq!(restored.session(0).game.changes().len(), 1);
+            drop(restored);
+            state.cleanup(Instant::now() + EMPTY_ROOM_LIFETIME);
+            assert!(state.lobby.get(room).is_none());
+            assert!(state.lobby.get(MAIN_ROOM).is_some());
+        }
+        drop(galaxy);
+
+        let galaxy = Galaxy::open(&TEST_CONFIG, &path).unwrap();
+        assert!(galaxy.state.lock().unwrap().lobby.get(room).is_none());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn new_games_are_restored_without_compacting() {
+        let path = std::env::temp_dir().join(format!("galaxy-games-{}.log", process::id()));
+        let _ = fs::remove_file(&path);
+        let galaxy = Galaxy::open(&TEST_CONFIG, &path).unwrap();
+        let (client, _inbox) = login(&galaxy, "player");
+        galaxy.handle(
+            client,
+            ClientMessage::NewGame {
+                width: 4,
+                height: 4,
+                difficulty: Difficulty::Normal,
+                seed: Some(2),
+                mode: Mode::Coop,
+                settings: Settings::default(),
+            },
+        );
+        let field = Position(0, 0);
+        galaxy.handle(
+            client,
+            ClientMessage::Assign {
+                field,
+                dot: Some(0),
+            },
+        );
+        let board = {
+            let main = galaxy.state.lock().unwrap().room(MAIN_ROOM);
+            let board = main.lock().unwrap().board().clone();
+            board
+        };
+        assert_eq!(galaxy.shared.log.lock().unwrap().compacted, 0);
+        drop(galaxy);
+
+        let galaxy = Galaxy::open(&TEST_CONFIG, &path).unwrap();
+        let main = galaxy.state.lock().unwrap().room(MAIN_ROOM);
+        let mut main = main.lock().unwrap();
+        assert_eq!(main.board().id(), board.id());
+        assert_eq!(main.session(0).game.changes().len(), 1);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn changes_of_unknown_dots_are_refused() {
+        let path = std::env::temp_dir().join(format!("galaxy-dots-{}.log", process::id()));
+        let _ = fs::remove_file(&path);
+        let galaxy = Galaxy::open(&TEST_CONFIG, &path).unwrap();
+        let (client, _inbox) = login(&galaxy, "player");
+        galaxy.handle(
+            client,
+            ClientMessage::Assign {
+                field: Position(0, 0),
+                dot: Some(0),
+            },
+        );
+        drop(galaxy);
+        let log = fs::read_to_string(&path).unwrap();
+        assert!(log.contains(r#""new_association":0"#));
+        fs::write(
+            &path,
+            log.replace(r#""new_association":0"#, r#""new_association":999"#),
+        )
+        .unwrap();
+
+        let error = Galaxy::open(&TEST_CONFIG, &path).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn player_names_are_trimmed_and_limited() {
+        let galaxy = Galaxy::for_tests();
+        let (_, mut inbox) = login(&galaxy, "  player ");
+        assert_eq!(
+            inbox.try_recv().ok(),
+            Some(ServerMessage::Welcome { player: 0 })
+        );
+        let (_, mut inbox) = login(&galaxy, "player");
+        assert_eq!(
+            inbox.try_recv().ok(),
+            Some(ServerMessage::Welcome { player: 0 })
+        );
+
+        for name in [" ".to_owned(), "x".repeat(MAX_PLAYER_NAME + 1)] {
+            let (_, mut inbox) = login(&galaxy, &name);
+            let message = "Unsupported player name".to_owned();
+            assert_eq!(
+                inbox.try_recv().ok(),
+                Some(ServerMessage::Error { message })
+            );
+        }
+        assert_eq!(galaxy.state.lock().unwrap().players.len(), 1);
+    }
+
+    #[test]
+    fn registration_stops_when_ids_run_out() {
+        let galaxy = Galaxy::for_tests();
+        let (_, mut inbox) = login(&galaxy, "last");
+        assert!(inbox
+            .try_iter()
+            .any(|message| message == ServerMessage::Welcome { player: 0 }));
+        {
+            let mut state = galaxy.state.lock().unwrap();
+            let last = state.players[0].clone();
+            state.players.resize(usize::from(Id::MAX) + 1, last);
+        }
+
+        let (_, mut inbox) = login(&galaxy, "overflow");
+        let message = "No more players can register".to_owned();
+        assert_eq!(
+            inbox.try_recv().ok(),
+            Some(ServerMessage::Error { message })
+        );
+        let state = galaxy.state.lock().unwrap();
+        assert_eq!(state.players.len(), usize::from(Id::MAX) + 1);
+    }
+}
@@ -0,0 +1,86 @@
+use crate::protocol::ServerMessage;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+#[cfg(test)]
+use tokio::sync::mpsc::error::TryRecvError;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::{self, Receiver, Sender};
+
+/// Messages a connection may have queued before it counts as lagging
+pub const OUTBOX_CAPACITY: usize = 1024;
+
+/// Sending side of the channel to the connection of a client. It never
+/// blocks, so a slow client can't stall the others.
+#[derive(Clone)]
+pub struct Outbox {
+    sender: Sender<ServerMessage>,
+    lagging: Arc<AtomicBool>,
+}
+
+/// Receiving side of the channel, which the connection forwards to the
+/// client
+pub struct Inbox {
+    receiver: Receiver<ServerMessage>,
+    lagging: Arc<AtomicBool>,
+}
+
+/// @return Connected outbox and inbox
+pub fn outbox(capacity: usize) -> (Outbox, Inbox) {
+    let (sender, receiver) = mpsc::channel(capacity);
+    let lagging = Arc::new(AtomicBool::new(false));
+    let outbox = Outbox {
+        sender,
+        lagging: Arc::clone(&lagging),
+    };
+    (outbox, Inbox { receiver, lagging })
+}
+
+impl Outbox {
+    /// Queues the message. If the queue is full, the message is dropped and
+    /// the inbox is marked as lagging instead.
+    pub fn send(&self, message: ServerMessage) {
+        // A closed channel means the client disconnected in the meantime
+        if let Err(TrySendError::Full(_)) = self.sender.try_send(message) {
+            self.lagging.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Inbox {
+    /// @return Next message, None if all outboxes are gone
+    pub async fn recv(&mut self) -> Option<ServerMessage> {
+        self.receiver.recv().await
+    }
+
+    #[cfg(test)]
+    pub fn try_recv(&mut self) -> Result<ServerMessage, TryRecvError> {
+        self.receiver.try_recv()
+    }
+
+    /// @return Iterator over the messages which are queued right now
+    #[cfg(test)]
+    pub fn try_iter(&mut self) -> impl Iterator<Item = ServerMessage> + '_ {
+        std::iter::from_fn(move || self.receiver.try_recv().ok())
+    }
+
+    /// Whether messages were dropped because the client didn't keep up. The
+    /// client has to resynchronize then.
+    pub fn lagging(&self) -> bool {
+        self.lagging.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_outboxes_mark_the_inbox_as_lagging() {
+        let (outbox, mut inbox) = outbox(2);
+        for _ in 0..3 {
+            outbox.send(ServerMessage::Solved);
+        }
+        assert!(inbox.lagging());
+        assert_eq!(inbox.try_iter().count(), 2);
+    }
+}
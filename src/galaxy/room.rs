@@ -0,0 +1,365 @@
+use super::delay::Delay;
+use super::{board_message, change_message, now, ClientId, Outbox, Role, RoomId};
+use crate::game::Game;
+use crate::gamegen::Board;
+use crate::protocol::ServerMessage;
+use crate::secret::SecretHash;
+use crate::types::Id;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// Clients who missed more changes get the whole board instead
+const MAX_RESYNC_CHANGES: usize = 256;
+
+/// Spectators are delayed by at most an hour
+pub const MAX_SPECTATOR_DELAY: u64 = 60 * 60;
+
+/// How the players of a room play together
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    /// All players solve a single board together
+    #[default]
+    Coop,
+    /// Every player solves the same board alone, the first one wins
+    Race,
+    /// A single player solves the board in a room of their own
+    Solo,
+}
+
+/// Options of a room which are chosen with the game
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Whether clients may watch without playing
+    pub spectators: bool,
+    /// Seconds spectators see the game later than the players
+    pub spectator_delay: u64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            spectators: true,
+            spectator_delay: 0,
+        }
+    }
+}
+
+impl Settings {
+    /// Rejects settings which clients may not choose
+    pub fn check(&self) -> Result<(), &'static str> {
+        if self.spectator_delay > MAX_SPECTATOR_DELAY {
+            return Err("Spectators can be delayed by an hour at most");
+        }
+        Ok(())
+    }
+
+    /// @return How much later spectators see the game
+    pub fn delay(&self) -> Duration {
+        Duration::from_secs(self.spectator_delay)
+    }
+}
+
+/// A game together with the bookkeeping of the room
+pub struct Session {
+    pub game: Game,
+    /// Whether the solves of the game are in the leaderboard already
+    pub recorded: bool,
+}
+
+impl Session {
+    fn new(board: Board) -> Self {
+        Session {
+            game: Game::new(board),
+            recorded: false,
+        }
+    }
+}
+
+/// A client in the room
+pub struct Member {
+    pub outbox: Outbox,
+    /// Player the client is logged in as
+    pub player: Option<Id>,
+    /// Name of the player, empty if the client isn't logged in
+    pub name: String,
+    pub role: Role,
+}
+
+impl Member {
+    /// @return Player the client plays as
+    fn playing(&self) -> Option<Id> {
+        match self.role {
+            Role::Player => self.player,
+            Role::Spectator(_) => None,
+        }
+    }
+
+    /// @return Player whose game the client watches, None inside if it
+    /// watches everybody
+    fn watching(&self) -> Option<Option<Id>> {
+        match self.role {
+            Role::Spectator(player) => Some(player),
+            Role::Player => None,
+        }
+    }
+}
+
+enum Sessions {
+    Shared(Session),
+    /// Sessions by player, which are created when the player starts playing
+    Separate(HashMap<Id, Session>),
+}
+
+/// Board which is played in a certain mode
+pub struct Room {
+    pub name: String,
+    /// Clients need it to join or watch if there is one
+    pub password: Option<SecretHash>,
+    /// Most players at the same time, unlimited if missing
+    pub capacity: Option<usize>,
+    /// Since when no client is in the room
+    pub empty_since: Option<Instant>,
+    pub settings: Settings,
+    mode: Mode,
+    board: Board,
+    sessions: Sessions,
+    /// Player who solved the board first in race mode
+    pub winner: Option<Id>,
+    /// Number of the current game, which increases whenever one starts
+    game: u64,
+    /// Clients in the room, so it can notify them without the lobby
+    pub members: HashMap<ClientId, Member>,
+}
+
+fn sessions(mode: Mode, board: &Board) -> Sessions {
+    match mode {
+        Mode::Coop | Mode::Solo => Sessions::Shared(Session::new(board.clone())),
+        Mode::Race => Sessions::Separate(HashMap::new()),
+    }
+}
+
+impl Room {
+    pub fn new(name: String, mode: Mode, settings: Settings, board: Board) -> Self {
+        Room {
+            name,
+            password: None,
+            capacity: None,
+            empty_since: None,
+            settings,
+            mode,
+            sessions: sessions(mode, &board),
+            board,
+            winner: None,
+            members: HashMap::new(),
+            game: 0,
+        }
+    }
+
+    /// Replaces the game of the room
+    pub fn start(&mut self, mode: Mode, settings: Settings, board: Board) {
+        self.settings = settings;
+        self.mode = mode;
+        self.sessions = sessions(mode, &board);
+        self.board = board;
+        self.winner = None;
+        self.game += 1;
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// @return Games of all sessions
+    pub fn games(&self) -> Vec<&Game> {
+        match &self.sessions {
+            Sessions::Shared(session) => vec![&session.game],
+            Sessions::Separate(sessions) => {
+                sessions.values().map(|session| &session.game).collect()
+            }
+        }
+    }
+
+    /// @return Session the player plays in
+    pub fn session(&mut self, player: Id) -> &mut Session {
+        match &mut self.sessions {
+            Sessions::Shared(session) => session,
+            Sessions::Separate(sessions) => {
+                let board = &self.board;
+                sessions
+                    .entry(player)
+                    .or_insert_with(|| Session::new(board.clone()))
+            }
+        }
+    }
+
+    /// Derives the bookkeeping of the sessions after their games were
+    /// restored. The winner is whoever made the last move of a solution
+    /// first.
+    pub fn restored(&mut self) {
+        let sessions: Vec<&mut Session> = match &mut self.sessions {
+            Sessions::Shared(session) => vec![session],
+            Sessions::Separate(sessions) => sessions.values_mut().collect(),
+        };
+        let mut first = None;
+        for session in sessions {
+            session.recorded = session.game.is_solved();
+            if session.recorded {
+                if let Some(last) = session.game.changes().last() {
+                    if first.is_none_or(|(time, _)| last.time < time) {
+                        first = Some((last.time, last.change.player));
+                    }
+                }
+            }
+        }
+        if self.mode() == Mode::Race {
+            self.winner = first.map(|(_, player)| player);
+        }
+    }
+
+    /// @return Players in the room
+    pub fn players(&self) -> HashSet<Id> {
+        self.members.values().filter_map(Member::playing).collect()
+    }
+
+    /// @return Player the client plays as in the room
+    pub fn playing(&self, client: ClientId) -> Option<Id> {
+        self.members.get(&client).and_then(Member::playing)
+    }
+
+    /// @return Whether the client watches the room
+    pub fn watching(&self, client: ClientId) -> bool {
+        self.members
+            .get(&client)
+            .is_some_and(|member| member.watching().is_some())
+    }
+
+    pub fn send(&self, client: ClientId, message: ServerMessage) {
+        if let Some(member) = self.members.get(&client) {
+            member.outbox.send(message);
+        }
+    }
+
+    pub fn error(&self, client: ClientId, message: &str) {
+        let message = message.to_owned();
+        self.send(client, ServerMessage::Error { message });
+    }
+
+    /// Sends the message to all players in the room
+    pub fn broadcast(&self, message: ServerMessage) {
+        for member in self.members.values() {
+            if member.playing().is_some() {
+                member.outbox.send(message.clone());
+            }
+        }
+    }
+
+    /// Sends the message to all connections of the player in the room
+    pub fn send_player(&self, player: Id, message: ServerMessage) {
+        for member in self.members.values() {
+            if member.playing() == Some(player) {
+                member.outbox.send(message.clone());
+            }
+        }
+    }
+
+    /// Sends the message with the delay of the room to the spectators who
+    /// watch the game of the player, or to all if there is no player.
+    pub fn send_spectators(
+        &self,
+        id: RoomId,
+        delay: &Delay,
+        player: Option<Id>,
+        message: ServerMessage,
+    ) {
+        let due = Instant::now() + self.settings.delay();
+        for member in self.members.values() {
+            if let Some(watched) = member.watching() {
+                if player.is_none() || watched == player {
+                    delay.send(due, id, &member.outbox, message.clone());
+                }
+            }
+        }
+    }
+
+    /// Sends the client the board it plays on or watches. Spectators see the
+    /// board as it was the delay of the room ago and receive the changes
+    /// since then later.
+    pub fn send_board(&mut self, id: RoomId, delay: &Delay, client: ClientId) {
+        let (player, role, outbox) = match self.members.get(&client) {
+            Some(member) => (member.player, member.role, member.outbox.clone()),
+            None => return,
+        };
+        let cutoff = now().saturating_sub(self.settings.delay().as_millis() as u64);
+        let number = self.game;
+        let (game, spectating) = match (role, player, self.mode()) {
+            (Role::Player, Some(player), _) => (&self.session(player).game, false),
+            (Role::Spectator(Some(player)), _, Mode::Race) => (&self.session(player).game, true),
+            (Role::Spectator(_), _, Mode::Coop) | (Role::Spectator(_), _, Mode::Solo) => {
+                (&self.session(0).game, true)
+            }
+            _ => return,
+        };
+        if !spectating {
+            outbox.send(board_message(game, number));
+            return;
+        }
+
+        let changes = game.changes();
+        let visible = changes
+            .iter()
+            .take_while(|timed| timed.time <= cutoff)
+            .count();
+        let state = game.state_at(visible);
+        let mut message = board_message(game, number);
+        if let ServerMessage::Board {
+            fields, sequence, ..
+        } = &mut message
+        {
+            for (field, dot) in fields.iter_mut().zip(state) {
+                field.assigned_dot = dot;
+            }
+            *sequence = visible;
+        }
+
+        outbox.send(message);
+        let start = Instant::now();
+        for (index, timed) in changes.iter().enumerate().skip(visible) {
+            let due = start + Duration::from_millis(timed.time.saturating_sub(cutoff));
+            delay.send(due, id, &outbox, change_message(game, index));
+        }
+    }
+
+    /// Sends the player the changes since the sequence number, or the whole
+    /// board if the puzzle and number of the game are another one or too
+    /// many changes are missing
+    pub fn resync(
+        &mut self,
+        id: RoomId,
+        delay: &Delay,
+        client: ClientId,
+        player: Id,
+        (puzzle, number): (u64, u64),
+        sequence: usize,
+    ) {
+        let current = self.game;
+        let game = &self.session(player).game;
+        let total = game.changes().len();
+        let other = game.puzzle != puzzle || current != number;
+        if other || sequence > total || total - sequence > MAX_RESYNC_CHANGES {
+            return self.send_board(id, delay, client);
+        }
+        let messages: Vec<ServerMessage> = (sequence..total)
+            .map(|index| change_message(game, index))
+            .collect();
+        for message in messages {
+            self.send(client, message);
+        }
+    }
+}
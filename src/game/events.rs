@@ -0,0 +1,83 @@
+use crate::types::{Id, TimedChange};
+
+/// Dot of every field by its id
+pub type Snapshot = Vec<Option<Id>>;
+
+/// Amount of changes between two snapshots
+const SNAPSHOT_INTERVAL: usize = 64;
+
+pub fn fold(state: &mut Snapshot, timed: &TimedChange) {
+    let change = &timed.change;
+    state[usize::from(change.affected_field)] = change.new_association;
+}
+
+/// Ordered stream of all changes of a game. The state at any point is the
+/// fold of the changes before it, snapshots keep replaying cheap.
+pub struct EventLog {
+    changes: Vec<TimedChange>,
+    /// The state after every SNAPSHOT_INTERVAL changes, starting with the
+    /// empty board
+    snapshots: Vec<Snapshot>,
+}
+
+impl EventLog {
+    pub fn new(fields: usize) -> Self {
+        EventLog {
+            changes: Vec::new(),
+            snapshots: vec![vec![None; fields]],
+        }
+    }
+
+    pub fn push(&mut self, change: TimedChange) {
+        self.changes.push(change);
+        if self.changes.len().is_multiple_of(SNAPSHOT_INTERVAL) {
+            let snapshot = self.state_at(self.changes.len());
+            self.snapshots.push(snapshot);
+        }
+    }
+
+    pub fn changes(&self) -> &[TimedChange] {
+        &self.changes
+    }
+
+    /// Replays the log up to a point in time
+    ///
+    /// @return State after the given amount of changes, or after all changes
+    /// if there are less
+    pub fn state_at(&self, changes: usize) -> Snapshot {
+        let changes = changes.min(self.changes.len());
+        let index = (changes / SNAPSHOT_INTERVAL).min(self.snapshots.len() - 1);
+        let mut state = self.snapshots[index].clone();
+        for change in &self.changes[index * SNAPSHOT_INTERVAL..changes] {
+            fold(&mut state, change);
+        }
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::GameChange;
+
+    #[test]
+    fn replays_to_any_point() {
+        let mut log = EventLog::new(3);
+        for i in 0..200 {
+            log.push(TimedChange {
+                time: 0,
+                change: GameChange {
+                    player: 0,
+                    affected_field: (i % 3) as Id,
+                    new_association: Some(i),
+                    old_association: None,
+                },
+            });
+        }
+
+        assert_eq!(log.state_at(0), vec![None, None, None]);
+        assert_eq!(log.state_at(2), vec![Some(0), Some(1), None]);
+        assert_eq!(log.state_at(130), vec![Some(129), Some(127), Some(128)]);
+        assert_eq!(log.state_at(1000), vec![Some(198), Some(199), Some(197)]);
+    }
+}
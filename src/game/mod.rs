@@ -0,0 +1,416 @@
+use crate::gamegen::{Board, DotPos, Galaxies};
+use crate::leaderboard::Solve;
+use crate::solver::{self, Assignment, Puzzle};
+use crate::types::{Contribution, Dot, Field, GameChange, Id, Offset, Position, TimedChange};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod events;
+
+use events::EventLog;
+pub use events::{fold, Snapshot};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GameError {
+    UnknownField(Position),
+    UnknownDot(Id),
+    NothingToUndo,
+    NothingToRedo,
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameError::UnknownField(position) => write!(f, "There is no field at {}", position),
+            GameError::UnknownDot(id) => write!(f, "There is no dot with id {}", id),
+            GameError::NothingToUndo => write!(f, "There is nothing to undo"),
+            GameError::NothingToRedo => write!(f, "There is nothing to redo"),
+        }
+    }
+}
+
+/// @return Milliseconds since the Unix epoch
+fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Changes of a single player, which can be undone and redone
+#[derive(Default)]
+struct History {
+    done: Vec<GameChange>,
+    undone: Vec<GameChange>,
+}
+
+/// State of a board which is played on. Dots and fields are identified by
+/// their index. Every mutation is a change in the event log, the fields
+/// always reflect all changes of it.
+pub struct Game {
+    /// Identifies the puzzle, see Board::id
+    pub puzzle: u64,
+    pub width: usize,
+    pub height: usize,
+    pub dots: Vec<Dot>,
+    /// Fields in rows from top to bottom
+    pub fields: Vec<Field>,
+    events: EventLog,
+    solution: Galaxies,
+    positions: Vec<DotPos>,
+    histories: HashMap<Id, History>,
+}
+
+impl Game {
+    pub fn new(board: Board) -> Self {
+        let dots = board
+            .dots
+            .iter()
+            .zip(&board.colors)
+            .enumerate()
+            .map(|(id, (position, &color))| Dot {
+                id: id as Id,
+                color,
+                position: Position(position.0 as Offset, position.1 as Offset),
+            })
+            .collect();
+
+        let mut fields = Vec::with_capacity(board.width * board.height);
+        for y in 0..board.height {
+            for x in 0..board.width {
+                fields.push(Field {
+                    assigned_dot: None,
+                    id: fields.len() as Id,
+                    position: Position(x as Offset, y as Offset),
+                });
+            }
+        }
+
+        Game {
+            puzzle: board.id(),
+            width: board.width,
+            height: board.height,
+            dots,
+            events: EventLog::new(fields.len()),
+            fields,
+            solution: board.galaxies,
+            positions: board.dots,
+            histories: HashMap::new(),
+        }
+    }
+
+    pub fn field_at(&self, position: Position) -> Option<&Field> {
+        let (x, y) = (usize::from(position.0), usize::from(position.1));
+        if x < self.width && y < self.height {
+            Some(&self.fields[y * self.width + x])
+        } else {
+            None
+        }
+    }
+
+    /// Assigns a field to a dot or clears it, if there is no dot.
+    ///
+    /// @return Change which was applied
+    pub fn assign(
+        &mut self,
+        player: Id,
+        position: Position,
+        dot: Option<Id>,
+    ) -> Result<GameChange, GameError> {
+        if let Some(dot) = dot {
+            if usize::from(dot) >= self.dots.len() {
+                return Err(GameError::UnknownDot(dot));
+            }
+        }
+        let field = self
+            .field_at(position)
+            .ok_or(GameError::UnknownField(position))?;
+
+        let change = GameChange {
+            player,
+            affected_field: field.id,
+            new_association: dot,
+            old_association: field.assigned_dot,
+        };
+        self.record(&change);
+
+        let history = self.histories.entry(player).or_default();
+        history.done.push(change.clone());
+        history.undone.clear();
+        Ok(change)
+    }
+
+    /// Reverts the last change of the player. Other players may have changed
+    /// the field since, so changes which aren't visible anymore are skipped.
+    ///
+    /// @return Change which reverts the move
+    pub fn undo(&mut self, player: Id) -> Result<GameChange, GameError> {
+        let history = self.histories.entry(player).or_default();
+        let change = loop {
+            let change = history.done.pop().ok_or(GameError::NothingToUndo)?;
+            let field = &self.fields[usize::from(change.affected_field)];
+            if field.assigned_dot == change.new_association {
+                break change;
+            }
+        };
+        let revert = GameChange {
+            player,
+            affected_field: change.affected_field,
+            new_association: change.old_association,
+            old_association: change.new_association,
+        };
+        history.undone.push(change);
+        self.record(&revert);
+        Ok(revert)
+    }
+
+    /// Applies the last undone change of the player again, as long as the
+    /// field wasn't changed in the meantime.
+    ///
+    /// @return Change which was applied
+    pub fn redo(&mut self, player: Id) -> Result<GameChange, GameError> {
+        let history = self.histories.entry(player).or_default();
+        let change = loop {
+            let change = history.undone.pop().ok_or(GameError::NothingToRedo)?;
+            let field = &self.fields[usize::from(change.affected_field)];
+            if field.assigned_dot == change.old_association {
+                break change;
+            }
+        };
+        history.done.push(change.clone());
+        self.record(&change);
+        Ok(change)
+    }
+
+    fn record(&mut self, change: &GameChange) {
+        self.apply(TimedChange {
+            time: timestamp(),
+            change: change.clone(),
+        });
+    }
+
+    /// Appends the change to the event log and updates the fields
+    pub fn apply(&mut self, timed: TimedChange) {
+        let change = &timed.change;
+        self.fields[usize::from(change.affected_field)].assigned_dot = change.new_association;
+        self.events.push(timed);
+    }
+
+    /// All changes in the order they were applied
+    pub fn changes(&self) -> &[TimedChange] {
+        self.events.changes()
+    }
+
+    /// @return Dot of every field after the given amount of changes
+    pub fn state_at(&self, changes: usize) -> Snapshot {
+        self.events.state_at(changes)
+    }
+
+    /// @return Percentage of fields which are assigned like in the solution
+    pub fn progress(&self) -> u8 {
+        let correct = self
+            .fields
+            .iter()
+            .filter(|field| {
+                let (x, y) = (usize::from(field.position.0), usize::from(field.position.1));
+                field.assigned_dot.map(usize::from) == Some(self.solution[x][y])
+            })
+            .count();
+        (correct * 100 / self.fields.len()) as u8
+    }
+
+    /// Derives from the event log how much every player contributed. A field
+    /// counts for the player who made its last change.
+    ///
+    /// @return Contributions ordered by player
+    pub fn contributions(&self) -> Vec<Contribution> {
+        let mut contributions = BTreeMap::new();
+        let mut last_players = vec![None; self.fields.len()];
+        for TimedChange { change, .. } in self.changes() {
+            contributions
+                .entry(change.player)
+                .or_insert(Contribution {
+                    player: change.player,
+                    moves: 0,
+                    fields: 0,
+                })
+                .moves += 1;
+            last_players[usize::from(change.affected_field)] =
+                change.new_association.map(|_| change.player);
+        }
+        for player in last_players.into_iter().flatten() {
+            if let Some(contribution) = contributions.get_mut(&player) {
+                contribution.fields += 1;
+            }
+        }
+        contributions.into_values().collect()
+    }
+
+    pub fn puzzle(&self) -> Puzzle<'_> {
+        Puzzle {
+            width: self.width,
+            height: self.height,
+            dots: &self.positions,
+        }
+    }
+
+    /// Current state of the game in the form the solver uses
+    pub fn assignment(&self) -> Assignment {
+        let mut assignment = self.puzzle().empty_assignment();
+        for field in &self.fields {
+            let (x, y) = (usize::from(field.position.0), usize::from(field.position.1));
+            assignment[x][y] = field.assigned_dot.map(usize::from);
+        }
+        assignment
+    }
+
+    pub fn solution(&self) -> &Galaxies {
+        &self.solution
+    }
+
+    /// Derives the solve of every player who made moves, which only makes
+    /// sense once the game is solved. The last change solved it.
+    pub fn solves(&self) -> Vec<Solve> {
+        let solved = self.changes().last().map_or(0, |timed| timed.time);
+        let mut solves: BTreeMap<Id, Solve> = BTreeMap::new();
+        for timed in self.changes() {
+            solves
+                .entry(timed.change.player)
+                .or_insert(Solve {
+                    player: timed.change.player,
+                    puzzle: self.puzzle,
+                    time: solved,
+                    duration: solved.saturating_sub(timed.time),
+                    moves: 0,
+                    fields: 0,
+                    board_fields: self.fields.len(),
+                })
+                .moves += 1;
+        }
+        for contribution in self.contributions() {
+            if let Some(solve) = solves.get_mut(&contribution.player) {
+                solve.fields = contribution.fields;
+            }
+        }
+        solves.into_values().collect()
+    }
+
+    /// Checks if the current state is a valid solution. This doesn't have to
+    /// be the solution the board was generated with.
+    pub fn is_solved(&self) -> bool {
+        solver::validate(&self.puzzle(), &self.assignment()).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gamegen::{self, Difficulty, GenerationConfig};
+
+    fn game() -> Game {
+        Game::new(gamegen::generate(&GenerationConfig {
+            width: 5,
+            height: 5,
+            difficulty: Difficulty::Normal,
+            seed: 1,
+        }))
+    }
+
+    #[test]
+    fn undo_reverts_own_changes_only() {
+        let mut game = game();
+        game.assign(0, Position(0, 0), Some(0)).unwrap();
+        game.assign(1, Position(1, 0), Some(1)).unwrap();
+
+        let change = game.undo(0).unwrap();
+        assert_eq!(change.affected_field, 0);
+        assert_eq!(change.new_association, None);
+        assert_eq!(game.fields[0].assigned_dot, None);
+        assert_eq!(game.fields[1].assigned_dot, Some(1));
+        assert_eq!(game.undo(0), Err(GameError::NothingToUndo));
+
+        game.redo(0).unwrap();
+        assert_eq!(game.fields[0].assigned_dot, Some(0));
+        assert_eq!(game.redo(0), Err(GameError::NothingToRedo));
+    }
+
+    #[test]
+    fn undo_skips_overwritten_changes() {
+        let mut game = game();
+        game.assign(0, Position(0, 0), Some(0)).unwrap();
+        game.assign(0, Position(1, 0), Some(0)).unwrap();
+        game.assign(1, Position(1, 0), Some(1)).unwrap();
+
+        // The second field belongs to the other player now
+        assert_eq!(game.undo(0).unwrap().affected_field, 0);
+        assert_eq!(game.fields[1].assigned_dot, Some(1));
+
+        // A new change discards the undone ones
+        game.assign(0, Position(2, 0), Some(0)).unwrap();
+        assert_eq!(game.redo(0), Err(GameError::NothingToRedo));
+    }
+
+    #[test]
+    fn read_models_follow_changes() {
+        let mut game = game();
+        let field = |x: usize, y: usize| Position(x as Offset, y as Offset);
+        let solution = game.solution().clone();
+        let dot = |x: usize, y: usize| Some(solution[x][y] as Id);
+
+        game.assign(0, field(0, 0), dot(0, 0)).unwrap();
+        game.assign(1, field(1, 0), dot(1, 0)).unwrap();
+        game.assign(1, field(0, 0), None).unwrap();
+        assert_eq!(game.progress(), 4);
+        game.undo(1).unwrap();
+        assert_eq!(game.progress(), 8);
+        assert_eq!(game.state_at(1)[0], dot(0, 0));
+        assert_eq!(game.state_at(1)[1], None);
+        assert_eq!(
+            game.contributions(),
+            vec![
+                Contribution {
+                    player: 0,
+                    moves: 1,
+                    fields: 0
+                },
+                Contribution {
+                    player: 1,
+                    moves: 3,
+                    fields: 2
+                }
+            ]
+        );
+
+        for x in 0..5 {
+            for y in 0..5 {
+                game.assign(0, field(x, y), dot(x, y)).unwrap();
+            }
+        }
+        assert_eq!(game.progress(), 100);
+        assert!(game.is_solved());
+    }
+
+    #[test]
+    fn helpers_solve_only_their_fields() {
+        let mut game = game();
+        let solution = game.solution().clone();
+        game.assign(1, Position(0, 0), Some(solution[0][0] as Id))
+            .unwrap();
+        for (x, column) in solution.iter().enumerate() {
+            for (y, &dot) in column.iter().enumerate().filter(|&(y, _)| (x, y) != (0, 0)) {
+                let field = Position(x as Offset, y as Offset);
+                game.assign(0, field, Some(dot as Id)).unwrap();
+            }
+        }
+        assert!(game.is_solved());
+
+        let solves = game.solves();
+        let shares: Vec<(Id, usize, usize, usize)> = solves
+            .iter()
+            .map(|solve| (solve.player, solve.moves, solve.fields, solve.board_fields))
+            .collect();
+        assert_eq!(shares, vec![(0, 24, 24, 25), (1, 1, 1, 25)]);
+        assert!(solves[1].check().is_err());
+    }
+}
@@ -1,13 +1,21 @@
-use rand::Rng;
+use crate::logger;
+use crate::metrics;
+use log::warn;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use std::cmp;
 use std::convert::TryFrom;
-use std::convert::TryInto;
+use std::time::Instant;
 
 mod print;
 mod t;
 
+pub use t::{Bitmap, Board, Difficulty, DotColor, DotPos, Galaxies, GenerationConfig};
+
+/// Seeds tried by generate_reliably before it gives up
+const MAX_ATTEMPTS: u64 = 3;
+
 fn add_border(space: &t::DotSpace, filler: t::Dot) -> t::DotSpace {
-    let x_size = space.len();
     let y_size = space[0].len();
 
     let mut new_space: t::DotSpace = Vec::new();
@@ -19,12 +27,10 @@ fn add_border(space: &t::DotSpace, filler: t::Dot) -> t::DotSpace {
 
     // Create space
     new_space.push(occupied_line.clone());
-    for x in 0..x_size {
+    for column in space {
         let mut line: t::DotSpaceColumn = Vec::new();
         line.push(filler);
-        for y in 0..y_size {
-            line.push(space[x][y]);
-        }
+        line.extend_from_slice(column);
         line.push(filler);
         new_space.push(line);
     }
@@ -33,7 +39,7 @@ fn add_border(space: &t::DotSpace, filler: t::Dot) -> t::DotSpace {
     new_space
 }
 
-fn mark_as_occupied(space: &mut t::DotSpace, dot: &t::DotPos, filler: t::Dot) -> () {
+fn mark_as_occupied(space: &mut t::DotSpace, dot: &t::DotPos, filler: t::Dot) {
     let x_size = space.len();
     let y_size = space[0].len();
 
@@ -53,22 +59,38 @@ fn mark_as_occupied(space: &mut t::DotSpace, dot: &t::DotPos, filler: t::Dot) ->
             let x = usize::try_from(x);
             let y = usize::try_from(y);
 
-            match (x, y) {
-                (Ok(x), Ok(y)) => {
-                    if x < x_size && y < y_size && space[x][y] == 0 {
-                        space[x][y] = filler;
-                    }
+            if let (Ok(x), Ok(y)) = (x, y) {
+                if x < x_size && y < y_size && space[x][y] == 0 {
+                    space[x][y] = filler;
                 }
-                _ => (),
             }
         }
     }
 }
 
+/// Checks if all fields covered by the spots have the same tone in the bitmap.
+/// Without a bitmap every combination of spots fits.
+fn fits_bitmap(bitmap: Option<&t::Bitmap>, spots: &[t::DotPos]) -> bool {
+    match bitmap {
+        Some(bitmap) => {
+            let mut tones = spots
+                .iter()
+                .flat_map(t::DotPos::covered_fields)
+                .map(|(x, y)| bitmap[x][y]);
+            match tones.next() {
+                Some(first) => tones.all(|tone| tone == first),
+                None => true,
+            }
+        }
+        None => true,
+    }
+}
+
 fn generate_next_dots_by_pattern(
     space: &mut t::DotSpace,
     pattern_list: &Vec<t::Pattern>,
-) -> Option<Vec<t::DotPos>> {
+    bitmap: Option<&t::Bitmap>,
+) -> Result<Option<Vec<t::DotPos>>, &'static str> {
     // Prepare patterns and space by adding occupied border
     let tmp_space = add_border(space, 1);
 
@@ -79,61 +101,61 @@ fn generate_next_dots_by_pattern(
         let y_size = tmp_space[x].len();
         for y in 0..y_size {
             // Iterate through all patterns
-            for pattern in pattern_list {
+            'pattern: for pattern in pattern_list {
                 let mut new_dot_list = Vec::new();
 
                 // Check if pattern matches
-                let px_size = pattern.len();
-                let py_size = pattern[0].len();
-                for px in 0..px_size {
-                    for py in 0..py_size {
+                for (px, pattern_column) in pattern.iter().enumerate() {
+                    for (py, &cell) in pattern_column.iter().enumerate() {
                         let x2 = x + px;
                         let y2 = y + py;
 
                         if x2 < x_size
                             && y2 < y_size
-                            && (pattern[px][py] == 0
-                                || tmp_space[x2][y2] == 1 && pattern[px][py] == 1
-                                || tmp_space[x2][y2] == 0 && pattern[px][py] > 1)
+                            && (cell == 0
+                                || tmp_space[x2][y2] == 1 && cell == 1
+                                || tmp_space[x2][y2] == 0 && cell > 1)
                         {
-                            if pattern[px][py] == 3 {
+                            if cell == 3 {
                                 let pos = t::DotPos(x2 - 1, y2 - 1);
                                 new_dot_list.push(pos);
                             }
                         } else {
-                            continue 'outer;
+                            continue 'pattern;
                         }
                     }
                 }
 
                 // Found a matching pattern! Occupy space
                 {
-                    let mut occupied_space = false;
-                    for px in 0..px_size {
-                        for py in 0..py_size {
+                    let mut galaxy_fields = Vec::new();
+                    for (px, pattern_column) in pattern.iter().enumerate() {
+                        for (py, &cell) in pattern_column.iter().enumerate() {
                             let x2 = i32::try_from(x + px).unwrap() - 1;
                             let y2 = i32::try_from(y + py).unwrap() - 1;
                             let x2 = usize::try_from(x2);
                             let y2 = usize::try_from(y2);
-                            match (x2, y2) {
-                                (Ok(x2), Ok(y2)) => {
-                                    if x2 % 2 == 0
-                                        && y2 % 2 == 0
-                                        && x2 < x_size - 2
-                                        && y2 < y_size - 2
-                                        && pattern[px][py] > 1
-                                    {
-                                        let pos = t::DotPos(x2, y2);
-                                        mark_as_occupied(space, &pos, 1);
-                                        occupied_space = true;
-                                    }
+                            if let (Ok(x2), Ok(y2)) = (x2, y2) {
+                                if x2 % 2 == 0
+                                    && y2 % 2 == 0
+                                    && x2 < x_size - 2
+                                    && y2 < y_size - 2
+                                    && cell > 1
+                                {
+                                    galaxy_fields.push(t::DotPos(x2, y2));
                                 }
-                                _ => (),
                             }
                         }
                     }
-                    if !occupied_space {
-                        panic!("Did not occupy space!");
+                    if galaxy_fields.is_empty() {
+                        return Err("A pattern did not occupy space");
+                    }
+                    // The galaxy must not cross the lines of the picture
+                    if !fits_bitmap(bitmap, &galaxy_fields) {
+                        continue 'pattern;
+                    }
+                    for pos in &galaxy_fields {
+                        mark_as_occupied(space, pos, 1);
                     }
                 }
 
@@ -142,7 +164,7 @@ fn generate_next_dots_by_pattern(
             }
         }
     }
-    result
+    Ok(result)
 }
 
 /// Calculates for a field in DotSpace a weight for the choosing algorithm
@@ -214,7 +236,7 @@ fn get_random_dot_candidates(space: &t::DotSpace, min_distance_to_filled: u16) -
             let neighbor_score = 1 - penalty;
 
             if space[x][y] == 0 && neighbor_score > 0 {
-                let dot_pos = t::DotPos(x.try_into().unwrap(), y.try_into().unwrap());
+                let dot_pos = t::DotPos(x, y);
                 candidates.push(dot_pos);
             }
         }
@@ -223,30 +245,35 @@ fn get_random_dot_candidates(space: &t::DotSpace, min_distance_to_filled: u16) -
 }
 
 /// Generates a random dot in the empty spots. Tries to use a weight function to
-/// prefer spaces which allow bigger galaxies.
+/// prefer spaces which allow bigger galaxies. Dots which would cross the lines
+/// of the picture are avoided.
+///
+/// @return New dot, an error if no spot is left for it
 fn generate_random_dot_in_empty_spot(
     space: &t::DotSpace,
-    rng: &mut rand::prelude::ThreadRng,
-) -> t::DotPos {
+    bitmap: Option<&t::Bitmap>,
+    rng: &mut StdRng,
+) -> Result<t::DotPos, &'static str> {
     let mut candidates = Vec::new();
     let search_radius = 3;
     for i in (0..=search_radius).rev() {
         candidates = get_random_dot_candidates(space, i);
+        candidates.retain(|c| fits_bitmap(bitmap, &[*c]));
 
         print::dot_space_candidates(space, &candidates);
 
-        if candidates.len() > 0 {
+        if !candidates.is_empty() {
             break;
         }
     }
-    if candidates.len() == 0 {
-        panic!("Found no candidates!");
+    if candidates.is_empty() {
+        return Err("Found no candidates for a dot");
     }
 
     // Choose one candidate randomly
     let new_dot_index = rng.gen_range(0..candidates.len());
 
-    candidates.swap_remove(new_dot_index)
+    Ok(candidates.swap_remove(new_dot_index))
 }
 
 /// Takes a DotSpace with 2s where the current galaxy is generated and the
@@ -256,7 +283,8 @@ fn generate_random_dot_in_empty_spot(
 fn add_field_to_galaxy(
     space: &mut t::DotSpace,
     dot: &t::DotPos,
-    rng: &mut rand::prelude::ThreadRng,
+    bitmap: Option<&t::Bitmap>,
+    rng: &mut StdRng,
 ) -> bool {
     // Determine possible candidates
     let mut candidates = Vec::new();
@@ -277,21 +305,20 @@ fn add_field_to_galaxy(
                 let y2 = i32::try_from(2 * dot.1).unwrap() - i32::try_from(y).unwrap();
                 let x2 = usize::try_from(x2);
                 let y2 = usize::try_from(y2);
-                match (x2, y2) {
-                    (Ok(x2), Ok(y2)) => {
-                        if x2 < x_size && y2 < y_size && space[x2][y2] == 0 {
-                            let x = x.try_into().unwrap();
-                            let y = y.try_into().unwrap();
-                            let penalty1 =
-                                4 * calculate_neighbor_weight(space, x, y, x_size, y_size, 2) + 1;
-                            let penalty2 =
-                                4 * calculate_neighbor_weight(space, x2, y2, x_size, y_size, 2) + 1;
-                            for _ in 0..cmp::max(penalty1, penalty2) {
-                                candidates.push(t::DotPos(x, y));
-                            }
+                if let (Ok(x2), Ok(y2)) = (x2, y2) {
+                    if x2 < x_size
+                        && y2 < y_size
+                        && space[x2][y2] == 0
+                        && fits_bitmap(bitmap, &[*dot, t::DotPos(x, y), t::DotPos(x2, y2)])
+                    {
+                        let penalty1 =
+                            4 * calculate_neighbor_weight(space, x, y, x_size, y_size, 2) + 1;
+                        let penalty2 =
+                            4 * calculate_neighbor_weight(space, x2, y2, x_size, y_size, 2) + 1;
+                        for _ in 0..cmp::max(penalty1, penalty2) {
+                            candidates.push(t::DotPos(x, y));
                         }
                     }
-                    _ => (),
                 }
             }
         }
@@ -300,8 +327,8 @@ fn add_field_to_galaxy(
     print::dot_space_candidates(space, &candidates);
 
     // Choose one candidate randomly
-    if candidates.len() > 0 {
-        let new_field_index = rng.gen_range(0..(candidates.len() - 1));
+    if !candidates.is_empty() {
+        let new_field_index = rng.gen_range(0..candidates.len());
 
         let winner = candidates.swap_remove(new_field_index);
 
@@ -312,17 +339,14 @@ fn add_field_to_galaxy(
         let y2 = i16::try_from(2 * dot.1).unwrap() - i16::try_from(winner.1).unwrap();
         let x2 = usize::try_from(x2);
         let y2 = usize::try_from(y2);
-        match (x2, y2) {
-            (Ok(x2), Ok(y2)) => {
-                if x2 < x_size && y2 < space[x2].len() {
-                    mark_as_occupied(space, &t::DotPos(x2, y2), 2);
-                }
+        if let (Ok(x2), Ok(y2)) = (x2, y2) {
+            if x2 < x_size && y2 < space[x2].len() {
+                mark_as_occupied(space, &t::DotPos(x2, y2), 2);
             }
-            _ => (),
         }
     }
 
-    candidates.len() > 0
+    !candidates.is_empty()
 }
 
 /// Takes a space and a dot and places the dot in the space.
@@ -333,17 +357,21 @@ fn add_field_to_galaxy(
 fn generate_galaxy_from_dot(
     space: &mut t::DotSpace,
     dot: &t::DotPos,
-    rng: &mut rand::prelude::ThreadRng,
-) -> () {
+    difficulty: t::Difficulty,
+    bitmap: Option<&t::Bitmap>,
+    rng: &mut StdRng,
+) {
     mark_as_occupied(space, dot, 2);
 
     // Add fields to galaxy/
     let mut field = 0;
-    let mid_of_bell = i32::try_from(space.len() * space[0].len()).unwrap() / 40 - 1;
+    let mid_of_bell = i32::try_from(space.len() * space[0].len()).unwrap()
+        / difficulty.spots_per_galaxy_field()
+        - 1;
     let steepness = 10;
 
     loop {
-        let result = add_field_to_galaxy(space, dot, rng);
+        let result = add_field_to_galaxy(space, dot, bitmap, rng);
         field += 1;
         // There are no fields to add anymore... :(
         if !result {
@@ -362,12 +390,10 @@ fn generate_galaxy_from_dot(
     }
 
     // Normalize entries in space
-    let x_size = space.len();
-    for x in 0..x_size {
-        let y_size = space[x].len();
-        for y in 0..y_size {
-            if space[x][y] == 2 {
-                space[x][y] = 1;
+    for column in space.iter_mut() {
+        for spot in column.iter_mut() {
+            if *spot == 2 {
+                *spot = 1;
             }
         }
     }
@@ -376,18 +402,20 @@ fn generate_galaxy_from_dot(
 fn generate_next_dots(
     space: &mut t::DotSpace,
     pattern_list: &Vec<t::Pattern>,
-    rng: &mut rand::prelude::ThreadRng,
-) -> Vec<t::DotPos> {
+    difficulty: t::Difficulty,
+    bitmap: Option<&t::Bitmap>,
+    rng: &mut StdRng,
+) -> Result<Vec<t::DotPos>, &'static str> {
     // First try pattern matching
-    let next_dots = generate_next_dots_by_pattern(space, pattern_list);
+    let next_dots = generate_next_dots_by_pattern(space, pattern_list, bitmap)?;
     match next_dots {
-        Some(dots) => dots,
+        Some(dots) => Ok(dots),
         None => {
             // If that doesn't work, generate random dot
-            let new_dot = generate_random_dot_in_empty_spot(&space, rng);
-            generate_galaxy_from_dot(space, &new_dot, rng);
+            let new_dot = generate_random_dot_in_empty_spot(space, bitmap, rng)?;
+            generate_galaxy_from_dot(space, &new_dot, difficulty, bitmap, rng);
 
-            vec![new_dot]
+            Ok(vec![new_dot])
         }
     }
 }
@@ -405,14 +433,15 @@ fn create_patterns() -> Vec<t::Pattern> {
     // □ ▣ □
     // □ □ □
     {
-        let mut pattern = Vec::new();
-        pattern.push(vec![0, 1, 1, 1, 1, 1, 0]);
-        pattern.push(vec![1, 2, 2, 2, 2, 2, 1]);
-        pattern.push(vec![1, 2, 2, 2, 2, 2, 1]);
-        pattern.push(vec![1, 2, 2, 3, 2, 2, 1]);
-        pattern.push(vec![1, 2, 2, 2, 2, 2, 1]);
-        pattern.push(vec![1, 2, 2, 2, 2, 2, 1]);
-        pattern.push(vec![0, 1, 1, 1, 1, 1, 0]);
+        let pattern = vec![
+            vec![0, 1, 1, 1, 1, 1, 0],
+            vec![1, 2, 2, 2, 2, 2, 1],
+            vec![1, 2, 2, 2, 2, 2, 1],
+            vec![1, 2, 2, 3, 2, 2, 1],
+            vec![1, 2, 2, 2, 2, 2, 1],
+            vec![1, 2, 2, 2, 2, 2, 1],
+            vec![0, 1, 1, 1, 1, 1, 0],
+        ];
         pattern_list.push(pattern);
     }
 
@@ -420,14 +449,15 @@ fn create_patterns() -> Vec<t::Pattern> {
     // □ ▣ □
     //   □ □
     {
-        let mut pattern = Vec::new();
-        pattern.push(vec![0, 1, 1, 1, 1, 0, 0]);
-        pattern.push(vec![1, 2, 2, 2, 1, 0, 0]);
-        pattern.push(vec![1, 2, 2, 2, 1, 1, 0]);
-        pattern.push(vec![1, 2, 2, 3, 2, 2, 1]);
-        pattern.push(vec![1, 1, 1, 2, 2, 2, 1]);
-        pattern.push(vec![0, 0, 1, 2, 2, 2, 1]);
-        pattern.push(vec![0, 0, 1, 1, 1, 1, 0]);
+        let pattern = vec![
+            vec![0, 1, 1, 1, 1, 0, 0],
+            vec![1, 2, 2, 2, 1, 0, 0],
+            vec![1, 2, 2, 2, 1, 1, 0],
+            vec![1, 2, 2, 3, 2, 2, 1],
+            vec![1, 1, 1, 2, 2, 2, 1],
+            vec![0, 0, 1, 2, 2, 2, 1],
+            vec![0, 0, 1, 1, 1, 1, 0],
+        ];
         pattern_list.push(pattern);
     }
 
@@ -435,14 +465,15 @@ fn create_patterns() -> Vec<t::Pattern> {
     // □ ▣ □
     // □ □
     {
-        let mut pattern = Vec::new();
-        pattern.push(vec![0, 0, 0, 1, 1, 1, 0]);
-        pattern.push(vec![0, 0, 1, 2, 2, 2, 1]);
-        pattern.push(vec![0, 1, 1, 2, 2, 2, 1]);
-        pattern.push(vec![1, 2, 2, 3, 2, 2, 1]);
-        pattern.push(vec![1, 2, 2, 2, 1, 1, 0]);
-        pattern.push(vec![1, 2, 2, 2, 1, 0, 0]);
-        pattern.push(vec![0, 1, 1, 1, 0, 0, 0]);
+        let pattern = vec![
+            vec![0, 0, 0, 1, 1, 1, 0],
+            vec![0, 0, 1, 2, 2, 2, 1],
+            vec![0, 1, 1, 2, 2, 2, 1],
+            vec![1, 2, 2, 3, 2, 2, 1],
+            vec![1, 2, 2, 2, 1, 1, 0],
+            vec![1, 2, 2, 2, 1, 0, 0],
+            vec![0, 1, 1, 1, 0, 0, 0],
+        ];
         pattern_list.push(pattern);
     }
 
@@ -450,14 +481,15 @@ fn create_patterns() -> Vec<t::Pattern> {
     //   ▣
     // □ □ □
     {
-        let mut pattern = Vec::new();
-        pattern.push(vec![0, 1, 1, 1, 1, 1, 0]);
-        pattern.push(vec![1, 2, 2, 2, 2, 2, 1]);
-        pattern.push(vec![0, 1, 1, 2, 1, 1, 0]);
-        pattern.push(vec![0, 0, 1, 3, 1, 0, 0]);
-        pattern.push(vec![0, 1, 1, 2, 1, 1, 0]);
-        pattern.push(vec![1, 2, 2, 2, 2, 2, 1]);
-        pattern.push(vec![0, 1, 1, 1, 1, 1, 0]);
+        let pattern = vec![
+            vec![0, 1, 1, 1, 1, 1, 0],
+            vec![1, 2, 2, 2, 2, 2, 1],
+            vec![0, 1, 1, 2, 1, 1, 0],
+            vec![0, 0, 1, 3, 1, 0, 0],
+            vec![0, 1, 1, 2, 1, 1, 0],
+            vec![1, 2, 2, 2, 2, 2, 1],
+            vec![0, 1, 1, 1, 1, 1, 0],
+        ];
         pattern_list.push(pattern);
     }
 
@@ -465,14 +497,15 @@ fn create_patterns() -> Vec<t::Pattern> {
     // □ ▣ □
     // □   □
     {
-        let mut pattern = Vec::new();
-        pattern.push(vec![0, 1, 0, 0, 0, 1, 0]);
-        pattern.push(vec![1, 2, 1, 0, 1, 2, 1]);
-        pattern.push(vec![1, 2, 1, 1, 1, 2, 1]);
-        pattern.push(vec![1, 2, 2, 3, 2, 2, 1]);
-        pattern.push(vec![1, 2, 1, 1, 1, 2, 1]);
-        pattern.push(vec![1, 2, 1, 0, 1, 2, 1]);
-        pattern.push(vec![0, 1, 0, 0, 0, 1, 0]);
+        let pattern = vec![
+            vec![0, 1, 0, 0, 0, 1, 0],
+            vec![1, 2, 1, 0, 1, 2, 1],
+            vec![1, 2, 1, 1, 1, 2, 1],
+            vec![1, 2, 2, 3, 2, 2, 1],
+            vec![1, 2, 1, 1, 1, 2, 1],
+            vec![1, 2, 1, 0, 1, 2, 1],
+            vec![0, 1, 0, 0, 0, 1, 0],
+        ];
         pattern_list.push(pattern);
     }
 
@@ -480,26 +513,28 @@ fn create_patterns() -> Vec<t::Pattern> {
     // □ □
     // □ □
     {
-        let mut pattern = Vec::new();
-        pattern.push(vec![0, 1, 1, 1, 0]);
-        pattern.push(vec![1, 2, 2, 2, 1]);
-        pattern.push(vec![1, 2, 2, 2, 1]);
-        pattern.push(vec![1, 2, 3, 2, 1]);
-        pattern.push(vec![1, 2, 2, 2, 1]);
-        pattern.push(vec![1, 2, 2, 2, 1]);
-        pattern.push(vec![0, 1, 1, 1, 0]);
+        let pattern = vec![
+            vec![0, 1, 1, 1, 0],
+            vec![1, 2, 2, 2, 1],
+            vec![1, 2, 2, 2, 1],
+            vec![1, 2, 3, 2, 1],
+            vec![1, 2, 2, 2, 1],
+            vec![1, 2, 2, 2, 1],
+            vec![0, 1, 1, 1, 0],
+        ];
         pattern_list.push(pattern);
     }
 
     // □ □ □
     // □ □ □
     {
-        let mut pattern = Vec::new();
-        pattern.push(vec![0, 1, 1, 1, 1, 1, 0]);
-        pattern.push(vec![1, 2, 2, 2, 2, 2, 1]);
-        pattern.push(vec![1, 2, 2, 3, 2, 2, 1]);
-        pattern.push(vec![1, 2, 2, 2, 2, 2, 1]);
-        pattern.push(vec![0, 1, 1, 1, 1, 1, 0]);
+        let pattern = vec![
+            vec![0, 1, 1, 1, 1, 1, 0],
+            vec![1, 2, 2, 2, 2, 2, 1],
+            vec![1, 2, 2, 3, 2, 2, 1],
+            vec![1, 2, 2, 2, 2, 2, 1],
+            vec![0, 1, 1, 1, 1, 1, 0],
+        ];
         pattern_list.push(pattern);
     }
 
@@ -507,14 +542,15 @@ fn create_patterns() -> Vec<t::Pattern> {
     //   ▣
     // □ □
     {
-        let mut pattern = Vec::new();
-        pattern.push(vec![0, 0, 0, 1, 1, 1, 0]);
-        pattern.push(vec![0, 0, 1, 2, 2, 2, 1]);
-        pattern.push(vec![0, 0, 1, 2, 1, 1, 0]);
-        pattern.push(vec![0, 0, 1, 3, 1, 0, 0]);
-        pattern.push(vec![0, 1, 1, 2, 1, 0, 0]);
-        pattern.push(vec![1, 2, 2, 2, 1, 0, 0]);
-        pattern.push(vec![0, 1, 1, 1, 0, 0, 0]);
+        let pattern = vec![
+            vec![0, 0, 0, 1, 1, 1, 0],
+            vec![0, 0, 1, 2, 2, 2, 1],
+            vec![0, 0, 1, 2, 1, 1, 0],
+            vec![0, 0, 1, 3, 1, 0, 0],
+            vec![0, 1, 1, 2, 1, 0, 0],
+            vec![1, 2, 2, 2, 1, 0, 0],
+            vec![0, 1, 1, 1, 0, 0, 0],
+        ];
         pattern_list.push(pattern);
     }
 
@@ -522,14 +558,15 @@ fn create_patterns() -> Vec<t::Pattern> {
     //   ▣
     //   □ □
     {
-        let mut pattern = Vec::new();
-        pattern.push(vec![0, 1, 1, 1, 0, 0, 0]);
-        pattern.push(vec![1, 2, 2, 2, 1, 0, 0]);
-        pattern.push(vec![0, 1, 1, 2, 1, 0, 0]);
-        pattern.push(vec![0, 0, 1, 3, 1, 0, 0]);
-        pattern.push(vec![0, 0, 1, 2, 1, 1, 0]);
-        pattern.push(vec![0, 0, 1, 2, 2, 2, 1]);
-        pattern.push(vec![0, 0, 1, 1, 1, 1, 0]);
+        let pattern = vec![
+            vec![0, 1, 1, 1, 0, 0, 0],
+            vec![1, 2, 2, 2, 1, 0, 0],
+            vec![0, 1, 1, 2, 1, 0, 0],
+            vec![0, 0, 1, 3, 1, 0, 0],
+            vec![0, 0, 1, 2, 1, 1, 0],
+            vec![0, 0, 1, 2, 2, 2, 1],
+            vec![0, 0, 1, 1, 1, 1, 0],
+        ];
         pattern_list.push(pattern);
     }
 
@@ -537,14 +574,15 @@ fn create_patterns() -> Vec<t::Pattern> {
     // □ ▣ □
     //     □
     {
-        let mut pattern = Vec::new();
-        pattern.push(vec![0, 1, 0, 0, 0, 0, 0]);
-        pattern.push(vec![1, 2, 1, 0, 0, 0, 0]);
-        pattern.push(vec![1, 2, 1, 1, 1, 1, 0]);
-        pattern.push(vec![1, 2, 2, 3, 2, 2, 1]);
-        pattern.push(vec![0, 1, 1, 1, 1, 2, 1]);
-        pattern.push(vec![0, 0, 0, 0, 1, 2, 1]);
-        pattern.push(vec![0, 0, 0, 0, 0, 1, 0]);
+        let pattern = vec![
+            vec![0, 1, 0, 0, 0, 0, 0],
+            vec![1, 2, 1, 0, 0, 0, 0],
+            vec![1, 2, 1, 1, 1, 1, 0],
+            vec![1, 2, 2, 3, 2, 2, 1],
+            vec![0, 1, 1, 1, 1, 2, 1],
+            vec![0, 0, 0, 0, 1, 2, 1],
+            vec![0, 0, 0, 0, 0, 1, 0],
+        ];
         pattern_list.push(pattern);
     }
 
@@ -552,38 +590,41 @@ fn create_patterns() -> Vec<t::Pattern> {
     // □ ▣ □
     // □
     {
-        let mut pattern = Vec::new();
-        pattern.push(vec![0, 0, 0, 0, 0, 1, 0]);
-        pattern.push(vec![0, 0, 0, 0, 1, 2, 1]);
-        pattern.push(vec![0, 1, 1, 1, 1, 2, 1]);
-        pattern.push(vec![1, 2, 2, 3, 2, 2, 1]);
-        pattern.push(vec![1, 2, 1, 1, 1, 1, 0]);
-        pattern.push(vec![1, 2, 1, 0, 0, 0, 0]);
-        pattern.push(vec![0, 1, 0, 0, 0, 0, 0]);
+        let pattern = vec![
+            vec![0, 0, 0, 0, 0, 1, 0],
+            vec![0, 0, 0, 0, 1, 2, 1],
+            vec![0, 1, 1, 1, 1, 2, 1],
+            vec![1, 2, 2, 3, 2, 2, 1],
+            vec![1, 2, 1, 1, 1, 1, 0],
+            vec![1, 2, 1, 0, 0, 0, 0],
+            vec![0, 1, 0, 0, 0, 0, 0],
+        ];
         pattern_list.push(pattern);
     }
 
     //   □ □
     // □ □
     {
-        let mut pattern = Vec::new();
-        pattern.push(vec![0, 0, 0, 1, 1, 1, 0]);
-        pattern.push(vec![0, 0, 1, 2, 2, 2, 1]);
-        pattern.push(vec![0, 1, 1, 3, 1, 1, 0]);
-        pattern.push(vec![1, 2, 2, 2, 1, 0, 0]);
-        pattern.push(vec![0, 1, 1, 1, 0, 0, 0]);
+        let pattern = vec![
+            vec![0, 0, 0, 1, 1, 1, 0],
+            vec![0, 0, 1, 2, 2, 2, 1],
+            vec![0, 1, 1, 3, 1, 1, 0],
+            vec![1, 2, 2, 2, 1, 0, 0],
+            vec![0, 1, 1, 1, 0, 0, 0],
+        ];
         pattern_list.push(pattern);
     }
 
     // □ □
     //   □ □
     {
-        let mut pattern = Vec::new();
-        pattern.push(vec![0, 1, 1, 1, 0, 0, 0]);
-        pattern.push(vec![1, 2, 2, 2, 1, 0, 0]);
-        pattern.push(vec![0, 1, 1, 3, 1, 1, 0]);
-        pattern.push(vec![0, 0, 1, 2, 2, 2, 1]);
-        pattern.push(vec![0, 0, 1, 1, 1, 1, 0]);
+        let pattern = vec![
+            vec![0, 1, 1, 1, 0, 0, 0],
+            vec![1, 2, 2, 2, 1, 0, 0],
+            vec![0, 1, 1, 3, 1, 1, 0],
+            vec![0, 0, 1, 2, 2, 2, 1],
+            vec![0, 0, 1, 1, 1, 1, 0],
+        ];
         pattern_list.push(pattern);
     }
 
@@ -591,14 +632,15 @@ fn create_patterns() -> Vec<t::Pattern> {
     // □ □
     //   □
     {
-        let mut pattern = Vec::new();
-        pattern.push(vec![0, 1, 0, 0, 0]);
-        pattern.push(vec![1, 2, 0, 0, 0]);
-        pattern.push(vec![1, 2, 1, 1, 0]);
-        pattern.push(vec![1, 2, 3, 2, 1]);
-        pattern.push(vec![0, 1, 1, 2, 1]);
-        pattern.push(vec![0, 0, 0, 2, 1]);
-        pattern.push(vec![0, 0, 0, 1, 0]);
+        let pattern = vec![
+            vec![0, 1, 0, 0, 0],
+            vec![1, 2, 0, 0, 0],
+            vec![1, 2, 1, 1, 0],
+            vec![1, 2, 3, 2, 1],
+            vec![0, 1, 1, 2, 1],
+            vec![0, 0, 0, 2, 1],
+            vec![0, 0, 0, 1, 0],
+        ];
         pattern_list.push(pattern);
     }
 
@@ -606,35 +648,38 @@ fn create_patterns() -> Vec<t::Pattern> {
     // □ □
     // □
     {
-        let mut pattern = Vec::new();
-        pattern.push(vec![0, 0, 0, 0, 0, 1, 0]);
-        pattern.push(vec![0, 0, 0, 0, 1, 2, 1]);
-        pattern.push(vec![0, 1, 1, 1, 1, 2, 1]);
-        pattern.push(vec![1, 2, 2, 3, 2, 2, 1]);
-        pattern.push(vec![1, 2, 1, 1, 1, 1, 0]);
-        pattern.push(vec![1, 2, 1, 0, 0, 0, 0]);
-        pattern.push(vec![0, 1, 0, 0, 0, 0, 0]);
+        let pattern = vec![
+            vec![0, 0, 0, 0, 0, 1, 0],
+            vec![0, 0, 0, 0, 1, 2, 1],
+            vec![0, 1, 1, 1, 1, 2, 1],
+            vec![1, 2, 2, 3, 2, 2, 1],
+            vec![1, 2, 1, 1, 1, 1, 0],
+            vec![1, 2, 1, 0, 0, 0, 0],
+            vec![0, 1, 0, 0, 0, 0, 0],
+        ];
         pattern_list.push(pattern);
     }
 
     // □ □
     // □ □
     {
-        let mut pattern = Vec::new();
-        pattern.push(vec![0, 1, 1, 1, 0]);
-        pattern.push(vec![1, 2, 2, 2, 1]);
-        pattern.push(vec![1, 2, 3, 2, 1]);
-        pattern.push(vec![1, 2, 2, 2, 1]);
-        pattern.push(vec![0, 1, 1, 1, 0]);
+        let pattern = vec![
+            vec![0, 1, 1, 1, 0],
+            vec![1, 2, 2, 2, 1],
+            vec![1, 2, 3, 2, 1],
+            vec![1, 2, 2, 2, 1],
+            vec![0, 1, 1, 1, 0],
+        ];
         pattern_list.push(pattern);
     }
 
     // □ ▣ □
     {
-        let mut pattern = Vec::new();
-        pattern.push(vec![0, 1, 1, 1, 1, 1, 0]);
-        pattern.push(vec![1, 2, 2, 3, 2, 2, 1]);
-        pattern.push(vec![0, 1, 1, 1, 1, 1, 0]);
+        let pattern = vec![
+            vec![0, 1, 1, 1, 1, 1, 0],
+            vec![1, 2, 2, 3, 2, 2, 1],
+            vec![0, 1, 1, 1, 1, 1, 0],
+        ];
         pattern_list.push(pattern);
     }
 
@@ -642,44 +687,44 @@ fn create_patterns() -> Vec<t::Pattern> {
     // ▣
     // □
     {
-        let mut pattern = Vec::new();
-        pattern.push(vec![0, 1, 0]);
-        pattern.push(vec![1, 2, 1]);
-        pattern.push(vec![1, 2, 1]);
-        pattern.push(vec![1, 3, 1]);
-        pattern.push(vec![1, 2, 1]);
-        pattern.push(vec![1, 2, 1]);
-        pattern.push(vec![0, 1, 0]);
+        let pattern = vec![
+            vec![0, 1, 0],
+            vec![1, 2, 1],
+            vec![1, 2, 1],
+            vec![1, 3, 1],
+            vec![1, 2, 1],
+            vec![1, 2, 1],
+            vec![0, 1, 0],
+        ];
         pattern_list.push(pattern);
     }
 
     // □ □
     {
-        let mut pattern = Vec::new();
-        pattern.push(vec![0, 1, 1, 1, 0]);
-        pattern.push(vec![1, 2, 3, 2, 1]);
-        pattern.push(vec![0, 1, 1, 1, 0]);
+        let pattern = vec![
+            vec![0, 1, 1, 1, 0],
+            vec![1, 2, 3, 2, 1],
+            vec![0, 1, 1, 1, 0],
+        ];
         pattern_list.push(pattern);
     }
 
     // □
     // □
     {
-        let mut pattern = Vec::new();
-        pattern.push(vec![0, 1, 0]);
-        pattern.push(vec![1, 2, 1]);
-        pattern.push(vec![1, 3, 1]);
-        pattern.push(vec![1, 2, 1]);
-        pattern.push(vec![0, 1, 0]);
+        let pattern = vec![
+            vec![0, 1, 0],
+            vec![1, 2, 1],
+            vec![1, 3, 1],
+            vec![1, 2, 1],
+            vec![0, 1, 0],
+        ];
         pattern_list.push(pattern);
     }
 
     // ▣
     {
-        let mut pattern = Vec::new();
-        pattern.push(vec![0, 1, 0]);
-        pattern.push(vec![1, 3, 1]);
-        pattern.push(vec![0, 1, 0]);
+        let pattern = vec![vec![0, 1, 0], vec![1, 3, 1], vec![0, 1, 0]];
         pattern_list.push(pattern);
     }
 
@@ -690,105 +735,360 @@ fn create_patterns() -> Vec<t::Pattern> {
 ///
 /// @return number of empty spots
 fn count_empty_spots(space: &t::DotSpace) -> u32 {
-    let x_size = space.len();
     let mut empty_spaces_total = 0;
 
     // Determine which y values have empty places for the dots to go to
     // Save y index and amount of empty spaces
-    for x in 0..x_size {
-        let y_size = space[x].len();
+    for column in space {
         // Simply counts the empty spaces
-        for y in 0..y_size {
-            if space[x][y] == 0 {
+        for &spot in column {
+            if spot == 0 {
                 empty_spaces_total += 1;
             }
         }
     }
 
-    return empty_spaces_total;
+    empty_spaces_total
 }
 
-/// Entrypoint into dot generation.
-/// Takes the size of the field and generates dots in it which are then returned.
+/// Assigns all occupied fields which don't belong to a galaxy yet to the galaxy
+/// of the dot with the given index.
+fn claim_fields(space: &t::DotSpace, galaxies: &mut [Vec<Option<usize>>], dot_index: usize) {
+    for (x, column) in galaxies.iter_mut().enumerate() {
+        for (y, galaxy) in column.iter_mut().enumerate() {
+            if galaxy.is_none() && space[2 * x][2 * y] != 0 {
+                *galaxy = Some(dot_index);
+            }
+        }
+    }
+}
+
+/// Colors every dot after the tone most fields of its galaxy have in the
+/// bitmap. Without a bitmap all dots are white.
+fn color_dots(
+    dot_count: usize,
+    galaxies: &t::Galaxies,
+    bitmap: Option<&t::Bitmap>,
+) -> Vec<t::DotColor> {
+    let bitmap = match bitmap {
+        Some(bitmap) => bitmap,
+        None => return vec![t::DotColor::White; dot_count],
+    };
+
+    // Balance of black and white fields for every galaxy
+    let mut balance = vec![0; dot_count];
+    for (x, column) in galaxies.iter().enumerate() {
+        for (y, &galaxy) in column.iter().enumerate() {
+            balance[galaxy] += if bitmap[x][y] { 1 } else { -1 };
+        }
+    }
+    balance
+        .into_iter()
+        .map(|balance| {
+            if balance > 0 {
+                t::DotColor::Black
+            } else {
+                t::DotColor::White
+            }
+        })
+        .collect()
+}
+
+/// Generates a complete board as described by the config. Generates dots in
+/// a field of the given size and keeps track of the galaxy every field belongs
+/// to, which is the solution of the board. If there is a bitmap, galaxies try
+/// to stay inside areas of the same tone and are colored after it.
 ///
-/// @return List of generated dots
-pub fn generate_dots(x_size: usize, y_size: usize) -> Vec<t::DotPos> {
+/// @return Generated board, an error if the generator got stuck
+fn generate_with_bitmap(
+    config: &t::GenerationConfig,
+    bitmap: Option<&t::Bitmap>,
+) -> Result<t::Board, &'static str> {
+    let x_size = config.width;
+    let y_size = config.height;
+
     // Generate empty space
     let mut space: t::DotSpace = Vec::new();
     for _ in 0..x_size * 2 - 1 {
-        let mut column: t::DotSpaceColumn = Vec::new();
-        for _ in 0..y_size * 2 - 1 {
-            column.push(0);
-        }
+        let column: t::DotSpaceColumn = vec![0; y_size * 2 - 1];
         space.push(column);
     }
+    let mut galaxies = vec![vec![None; y_size]; x_size];
 
     // Generate dots in space
     let pattern_list = create_patterns();
     let mut new_dot_list = Vec::new();
-    let mut rng = rand::thread_rng();
+    let mut rng = StdRng::seed_from_u64(config.seed);
     loop {
-        let next_dots = generate_next_dots(&mut space, &pattern_list, &mut rng);
+        let next_dots = generate_next_dots(
+            &mut space,
+            &pattern_list,
+            config.difficulty,
+            bitmap,
+            &mut rng,
+        )?;
         for next_dot in next_dots {
+            // Every step creates exactly one galaxy, so all newly occupied
+            // fields belong to it
+            claim_fields(&space, &mut galaxies, new_dot_list.len());
             new_dot_list.push(next_dot);
         }
         let empty_spaces = count_empty_spots(&space);
         // Debugging statements
         print::dot_space(&space);
 
-        if empty_spaces <= 0 {
+        if empty_spaces == 0 {
             break;
         }
     }
 
-    return new_dot_list;
+    let galaxies = galaxies
+        .into_iter()
+        .map(|column| {
+            column
+                .into_iter()
+                .map(|galaxy| galaxy.ok_or("A field has no galaxy"))
+                .collect()
+        })
+        .collect::<Result<_, _>>()?;
+
+    let colors = color_dots(new_dot_list.len(), &galaxies, bitmap);
+
+    Ok(t::Board {
+        width: x_size,
+        height: y_size,
+        dots: new_dot_list,
+        colors,
+        galaxies,
+    })
+}
+
+/// Generates a complete board as described by the config. The same config
+/// always results in the same board. All dots are white.
+///
+/// @return Generated board, an error if the generator fails for the config
+pub fn try_generate(config: &t::GenerationConfig) -> Result<t::Board, &'static str> {
+    generate_with_bitmap(config, None)
+}
+
+/// Generates a board like try_generate in tests, whose configs are known to
+/// work.
+///
+/// @return Generated board
+#[cfg(test)]
+pub fn generate(config: &t::GenerationConfig) -> t::Board {
+    try_generate(config).expect("Generating the board failed")
+}
+
+/// Generates a board like try_generate, but tries the following seeds if the
+/// generator fails for the seed. Latencies, retries and failures are
+/// recorded in the metrics.
+///
+/// @return Generated board with the config it was generated with, None if
+/// every attempt failed
+pub fn generate_reliably(config: &t::GenerationConfig) -> Option<(t::Board, t::GenerationConfig)> {
+    for attempt in 0..MAX_ATTEMPTS {
+        let config = t::GenerationConfig {
+            seed: config.seed.wrapping_add(attempt),
+            ..*config
+        };
+        if attempt > 0 {
+            metrics::generation_retried();
+        }
+        let _context = logger::context(vec![
+            ("seed", config.seed.into()),
+            ("width", config.width.into()),
+            ("height", config.height.into()),
+        ]);
+        let start = Instant::now();
+        match try_generate(&config) {
+            Ok(board) => {
+                metrics::generated(config.width, config.height, start.elapsed());
+                return Some((board, config));
+            }
+            Err(reason) => warn!("Generating a board with {:?} failed: {}", config, reason),
+        }
+    }
+    metrics::generation_failed();
+    None
+}
+
+/// Generates a board for a pictorial puzzle. The galaxies with black dots
+/// approximate the black areas of the bitmap, which has to have the size of
+/// the board.
+///
+/// @return Generated board, an error if the generator fails for the config
+pub fn generate_picture(
+    config: &t::GenerationConfig,
+    bitmap: &t::Bitmap,
+) -> Result<t::Board, &'static str> {
+    generate_with_bitmap(config, Some(bitmap))
+}
+
+/// Generates a random board of the given size with default difficulty.
+///
+/// @return Generated board, an error if the generator got stuck
+pub fn generate_board(x_size: usize, y_size: usize) -> Result<t::Board, &'static str> {
+    try_generate(&t::GenerationConfig {
+        width: x_size,
+        height: y_size,
+        difficulty: t::Difficulty::default(),
+        seed: rand::random(),
+    })
+}
+
+/// Entrypoint into dot generation.
+/// Takes the size of the field and generates dots in it which are then returned.
+///
+/// @return List of generated dots, an error if the generator got stuck
+pub fn generate_dots(x_size: usize, y_size: usize) -> Result<Vec<t::DotPos>, &'static str> {
+    generate_board(x_size, y_size).map(|board| board.dots)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::solver::Puzzle;
+    use proptest::prelude::*;
     use t::*;
 
-    fn generic_test(width: usize, height: usize) {
-        for _ in 0..10 {
-            let board = generate_dots(width, height);
-
-            // Amount of generated dots
-            assert!(board.len() > 0);
-            assert!(board.len() <= width * height);
-
-            // Test that generated dots make sense
-            let mut seen_dots = Vec::new();
-            for dot in board {
-                // Dots have to be unique
-                assert!(!seen_dots.contains(&dot));
-                // Dots have to be inside the board
-                assert!(dot.0 < width * 2);
-                assert!(dot.1 < height * 2);
-                // Dots can't have direct neighbors
-                if dot.0 > 0 {
-                    assert!(!seen_dots.contains(&DotPos(dot.0 - 1, dot.1)))
-                }
-                if dot.1 > 0 {
-                    assert!(!seen_dots.contains(&DotPos(dot.0, dot.1 - 1)))
-                }
-                if dot.0 < width * 2 {
-                    assert!(!seen_dots.contains(&DotPos(dot.0 + 1, dot.1)))
-                }
-                if dot.0 < height * 2 {
-                    assert!(!seen_dots.contains(&DotPos(dot.0, dot.1 + 1)))
+    fn difficulty() -> impl Strategy<Value = Difficulty> {
+        prop_oneof![
+            Just(Difficulty::Easy),
+            Just(Difficulty::Normal),
+            Just(Difficulty::Hard)
+        ]
+    }
+
+    /// Checks that the dots are distinct spots of the board and that their
+    /// galaxies tile the board, are symmetric around them and connected
+    fn check_board(board: &Board) -> Result<(), TestCaseError> {
+        let (width, height) = (board.width, board.height);
+        prop_assert!(!board.dots.is_empty());
+        prop_assert_eq!(board.colors.len(), board.dots.len());
+        prop_assert_eq!(board.galaxies.len(), width);
+
+        // Dots don't share fields, which also rules out neighbors
+        let mut owners = vec![vec![None; height]; width];
+        for (index, dot) in board.dots.iter().enumerate() {
+            prop_assert!(dot.0 < 2 * width - 1 && dot.1 < 2 * height - 1);
+            for (x, y) in dot.covered_fields() {
+                prop_assert_eq!(owners[x][y], None, "Dots share field {:?}", (x, y));
+                owners[x][y] = Some(index);
+                prop_assert_eq!(board.galaxies[x][y], index);
+            }
+        }
+
+        let mut sizes = vec![0; board.dots.len()];
+        for (x, column) in board.galaxies.iter().enumerate() {
+            prop_assert_eq!(column.len(), height);
+            for (y, &galaxy) in column.iter().enumerate() {
+                prop_assert!(galaxy < board.dots.len());
+                sizes[galaxy] += 1;
+                // The field mirrored at the dot in dot grid coordinates
+                let dot = board.dots[galaxy];
+                let mirrored = (dot.0.checked_sub(x), dot.1.checked_sub(y));
+                let symmetric = match mirrored {
+                    (Some(mx), Some(my)) if mx < width && my < height => {
+                        board.galaxies[mx][my] == galaxy
+                    }
+                    _ => false,
+                };
+                prop_assert!(symmetric, "Field {:?} has no mirror", (x, y));
+            }
+        }
+
+        for (index, dot) in board.dots.iter().enumerate() {
+            let mut reached = vec![vec![false; height]; width];
+            let mut stack = dot.covered_fields();
+            let mut count = 0;
+            while let Some((x, y)) = stack.pop() {
+                if reached[x][y] || board.galaxies[x][y] != index {
+                    continue;
                 }
-                // TODO: neighbor checks for on grid dots
-                seen_dots.push(dot);
+                reached[x][y] = true;
+                count += 1;
+                stack.extend(
+                    [
+                        (x.wrapping_sub(1), y),
+                        (x + 1, y),
+                        (x, y.wrapping_sub(1)),
+                        (x, y + 1),
+                    ]
+                    .iter()
+                    .filter(|&&(x, y)| x < width && y < height),
+                );
             }
+            prop_assert_eq!(count, sizes[index], "Galaxy {} isn't connected", index);
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn galaxies_tile_the_board(
+            width in 1usize..=20,
+            height in 1usize..=20,
+            difficulty in difficulty(),
+            seed: u64,
+        ) {
+            let config = GenerationConfig { width, height, difficulty, seed };
+            let board = generate(&config);
+            prop_assert_eq!((board.width, board.height), (width, height));
+            check_board(&board)?;
+            prop_assert_eq!(generate(&config), board);
+        }
+
+        #[test]
+        fn boards_are_solvable(
+            width in 1usize..=7,
+            height in 1usize..=7,
+            difficulty in difficulty(),
+            seed: u64,
+        ) {
+            let board = generate(&GenerationConfig { width, height, difficulty, seed });
+            let puzzle = Puzzle { width, height, dots: &board.dots };
+            let solution = puzzle.solve(&puzzle.empty_assignment());
+            prop_assert!(solution.is_some(), "No solution for {:?}", board.dots);
         }
     }
 
     #[test]
-    fn dynamic() {
-        for w in 1..=12 {
-            for h in 1..=12 {
-                generic_test(w, h);
+    fn patterns_are_tried_after_a_mismatch() {
+        // Only the last pattern, a single field galaxy, fits the board
+        let mut space: DotSpace = vec![vec![0]];
+        let dots = generate_next_dots_by_pattern(&mut space, &create_patterns(), None);
+        assert_eq!(dots, Ok(Some(vec![DotPos(0, 0)])));
+        assert_eq!(space, vec![vec![1]]);
+    }
+
+    #[test]
+    fn full_spaces_fail_instead_of_panicking() {
+        let space: DotSpace = vec![vec![1; 3]; 3];
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!(generate_random_dot_in_empty_spot(&space, None, &mut rng).is_err());
+    }
+
+    #[test]
+    fn picture() {
+        let (width, height) = (12, 8);
+        let bitmap: Bitmap = (0..width).map(|x| vec![x < width / 2; height]).collect();
+        for seed in 0..10 {
+            let config = GenerationConfig {
+                width,
+                height,
+                difficulty: Difficulty::Normal,
+                seed,
+            };
+            let board = generate_picture(&config, &bitmap).unwrap();
+
+            // Galaxies with black dots have to match the black area exactly
+            for (column, galaxies) in bitmap.iter().zip(&board.galaxies) {
+                for (&black, &galaxy) in column.iter().zip(galaxies) {
+                    assert_eq!(board.colors[galaxy] == DotColor::Black, black);
+                }
             }
         }
     }
@@ -1,9 +1,13 @@
 use super::t;
-use log::trace;
+use crate::logger::GRIDS;
+use log::{log_enabled, trace, Level};
 
 /// Helper for debugging purposes.
 /// Prints out a DotSpace and the amount of candidates for a single spot.
 pub fn dot_space_candidates(space: &t::DotSpace, candidates: &Vec<t::DotPos>) {
+    if !log_enabled!(target: GRIDS, Level::Trace) {
+        return;
+    }
     let x_size = space.len();
     let y_size = space[0].len();
 
@@ -22,8 +26,8 @@ pub fn dot_space_candidates(space: &t::DotSpace, candidates: &Vec<t::DotPos>) {
         for j in 0..x_size {
             let mut amount = 0;
             for candidate in candidates {
-                let x = usize::from(candidate.0);
-                let y = usize::from(candidate.1);
+                let x = candidate.0;
+                let y = candidate.1;
                 if x == j && y == i {
                     amount += 1;
                 }
@@ -33,12 +37,15 @@ pub fn dot_space_candidates(space: &t::DotSpace, candidates: &Vec<t::DotPos>) {
         }
         s.push('\n');
     }
-    trace!("{}", &s);
+    trace!(target: GRIDS, "{}", &s);
 }
 
 /// Helper for debugging purposes.
 /// Prints out a DotSpace.
 pub fn dot_space(space: &t::DotSpace) {
+    if !log_enabled!(target: GRIDS, Level::Trace) {
+        return;
+    }
     let x_size = space.len();
     let y_size = space[0].len();
 
@@ -54,10 +61,10 @@ pub fn dot_space(space: &t::DotSpace) {
     for i in 0..y_size {
         let line = format!("{:0>2}", i) + " ";
         s.push_str(&line);
-        for j in 0..x_size {
-            s.push_str(&format!(" {} ", space[j][i]));
+        for column in space {
+            s.push_str(&format!(" {} ", column[i]));
         }
         s.push('\n');
     }
-    trace!("{}", &s);
+    trace!(target: GRIDS, "{}", &s);
 }
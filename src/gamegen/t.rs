@@ -1,6 +1,8 @@
+use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::str::FromStr;
 
-#[derive(Eq)]
+#[derive(Clone, Copy, Eq, Serialize, Deserialize)]
 pub struct DotPos(pub usize, pub usize);
 
 impl PartialEq for DotPos {
@@ -9,6 +11,32 @@ impl PartialEq for DotPos {
     }
 }
 
+impl DotPos {
+    /// Determines the fields which are covered by a spot in dot grid
+    /// coordinates. These are one, two or four fields depending on the
+    /// position of the spot.
+    pub fn covered_fields(&self) -> Vec<(usize, usize)> {
+        let xs = if self.0 % 2 == 1 {
+            vec![self.0 / 2, self.0 / 2 + 1]
+        } else {
+            vec![self.0 / 2]
+        };
+        let ys = if self.1 % 2 == 1 {
+            vec![self.1 / 2, self.1 / 2 + 1]
+        } else {
+            vec![self.1 / 2]
+        };
+
+        let mut fields = Vec::new();
+        for &x in &xs {
+            for &y in &ys {
+                fields.push((x, y));
+            }
+        }
+        fields
+    }
+}
+
 impl fmt::Debug for DotPos {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("DotPos")
@@ -27,3 +55,103 @@ pub type DotSpaceColumn = Vec<Dot>;
 pub type DotSpace = Vec<DotSpaceColumn>;
 
 pub type Pattern = DotSpace;
+
+/// For every field of a board the index of the dot whose galaxy it belongs to.
+/// Uses field coordinates, so the field (x, y) is found at galaxies[x][y].
+pub type Galaxies = Vec<Vec<usize>>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DotColor {
+    White,
+    Black,
+}
+
+/// Picture in field coordinates, true marks black fields
+pub type Bitmap = Vec<Vec<bool>>;
+
+/// A generated board consisting of its size in fields, the dots in dot grid
+/// coordinates and the galaxies which form the solution.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Board {
+    pub width: usize,
+    pub height: usize,
+    pub dots: Vec<DotPos>,
+    /// Color of every dot in the same order as the dots
+    pub colors: Vec<DotColor>,
+    pub galaxies: Galaxies,
+}
+
+impl Board {
+    /// Identifies the puzzle by its size and dots, so equal puzzles get the
+    /// same id. This is a FNV-1a hash.
+    pub fn id(&self) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut values = vec![self.width, self.height];
+        for dot in &self.dots {
+            values.extend([dot.0, dot.1]);
+        }
+        for value in values {
+            for byte in (value as u64).to_le_bytes() {
+                hash ^= u64::from(byte);
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+        hash
+    }
+}
+
+/// Difficulty of a board, which mostly depends on the size of its galaxies
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    /// Determines the preferred size of randomly grown galaxies. The bigger
+    /// the amount of spots per field, the smaller the galaxies.
+    pub fn spots_per_galaxy_field(self) -> i32 {
+        match self {
+            Difficulty::Easy => 80,
+            Difficulty::Normal => 40,
+            Difficulty::Hard => 20,
+        }
+    }
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "easy" => Ok(Difficulty::Easy),
+            "normal" => Ok(Difficulty::Normal),
+            "hard" => Ok(Difficulty::Hard),
+            _ => Err(format!("Unknown difficulty '{}'", s)),
+        }
+    }
+}
+
+/// Everything which determines a generated board
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GenerationConfig {
+    pub width: usize,
+    pub height: usize,
+    pub difficulty: Difficulty,
+    pub seed: u64,
+}
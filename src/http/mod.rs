@@ -0,0 +1,602 @@
+use crate::galaxy::Galaxy;
+use crate::game::Game;
+use crate::gamegen::{self, Board, Difficulty, DotPos};
+use crate::leaderboard::Period;
+use crate::solver::{self, Puzzle};
+use crate::types::Dot;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::io;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::time;
+
+/// Longest request line together with the headers
+const MAX_HEAD: usize = 8 * 1024;
+
+/// Longest body of a request
+const MAX_BODY: usize = 64 * 1024;
+
+const MAX_HEADERS: usize = 32;
+
+/// Clients which take longer to receive a response are dropped
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// HTTP request without the headers the API doesn't need
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+/// What a client sent first on a new connection
+#[derive(Debug)]
+pub enum Incoming {
+    /// Handshake of a WebSocket, which has to be passed on together with
+    /// the bytes which were read already
+    WebSocket(Vec<u8>),
+    Http(Request),
+    /// Request which can't be handled, with the answer for the client
+    Invalid(Response),
+}
+
+/// Answer to a request, which is JSON except for the metrics and plain errors
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    fn json<T: Serialize>(status: u16, body: &T) -> Self {
+        Response {
+            status,
+            content_type: "application/json",
+            body: serde_json::to_string(body).expect("Serializing response failed"),
+        }
+    }
+
+    /// @return Plain text response
+    pub fn text(status: u16, body: String) -> Self {
+        Response {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body,
+        }
+    }
+
+    /// @return Metrics in the Prometheus text format
+    pub fn metrics(body: String) -> Self {
+        Response {
+            status: 200,
+            content_type: "text/plain; version=0.0.4; charset=utf-8",
+            body,
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Response::json(status, &json!({ "error": message }))
+    }
+
+    /// Encodes the response. The connection is closed afterwards, so
+    /// every request needs a connection of its own.
+    ///
+    /// @return Status line, headers and body
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let reason = match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            413 => "Payload Too Large",
+            429 => "Too Many Requests",
+            _ => "Internal Server Error",
+        };
+        let head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.status,
+            reason,
+            self.content_type,
+            self.body.len()
+        );
+        let mut bytes = head.into_bytes();
+        bytes.extend_from_slice(self.body.as_bytes());
+        bytes
+    }
+}
+
+/// Sends the response and closes the connection
+pub async fn send<S>(mut stream: S, response: Response)
+where
+    S: AsyncWrite + Unpin,
+{
+    let sent = async {
+        stream.write_all(&response.to_bytes()).await?;
+        stream.shutdown().await
+    };
+    match time::timeout(WRITE_TIMEOUT, sent).await {
+        Ok(Ok(())) => (),
+        Ok(Err(error)) => debug!("Sending a response failed: {}", error),
+        Err(_) => debug!("Sending a response timed out"),
+    }
+}
+
+/// @return Answer to requests of clients which exceed their limits
+pub fn too_many_requests() -> Response {
+    Response::error(429, "Too many requests")
+}
+
+impl Request {
+    /// Whether answering generates a board, which is limited separately
+    pub fn generates(&self) -> bool {
+        self.path.trim_matches('/') == "puzzle"
+    }
+}
+
+/// Stream which returns the given bytes before those of the inner stream.
+/// Lets the WebSocket handshake read the request again after sniffing it.
+pub struct Prefixed<S> {
+    prefix: Vec<u8>,
+    position: usize,
+    inner: S,
+}
+
+impl<S> Prefixed<S> {
+    pub fn new(prefix: Vec<u8>, inner: S) -> Self {
+        Prefixed {
+            prefix,
+            position: 0,
+            inner,
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Prefixed<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let remaining = self.prefix.len() - self.position;
+        if remaining == 0 {
+            return Pin::new(&mut self.inner).poll_read(cx, buf);
+        }
+        let amount = remaining.min(buf.remaining());
+        let start = self.position;
+        buf.put_slice(&self.prefix[start..start + amount]);
+        self.position += amount;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Prefixed<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Splits a query string into its parameters. Values aren't percent
+/// decoded, because the API only takes numbers and names.
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.find('=') {
+            Some(index) => (pair[..index].to_owned(), pair[index + 1..].to_owned()),
+            None => (pair.to_owned(), String::new()),
+        })
+        .collect()
+}
+
+/// Reads the head of a request and the body unless the client wants to
+/// open a WebSocket
+pub async fn read_request<S>(stream: &mut S) -> io::Result<Incoming>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut buffer = Vec::new();
+    let mut chunk = [0; 1024];
+    loop {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut parsed = httparse::Request::new(&mut headers);
+        let length = match parsed.parse(&buffer) {
+            Ok(httparse::Status::Complete(length)) => length,
+            Ok(httparse::Status::Partial) if buffer.len() < MAX_HEAD => continue,
+            Ok(httparse::Status::Partial) => {
+                return Ok(Incoming::Invalid(Response::error(400, "Head too long")))
+            }
+            Err(error) => {
+                let message = format!("Invalid request: {}", error);
+                return Ok(Incoming::Invalid(Response::error(400, &message)));
+            }
+        };
+
+        let header = |name: &str| {
+            parsed
+                .headers
+                .iter()
+                .find(|header| header.name.eq_ignore_ascii_case(name))
+                .and_then(|header| std::str::from_utf8(header.value).ok())
+        };
+        if header("upgrade").is_some_and(|value| value.eq_ignore_ascii_case("websocket")) {
+            return Ok(Incoming::WebSocket(buffer));
+        }
+        let body_length = match header("content-length").map(usize::from_str) {
+            None => 0,
+            Some(Ok(body_length)) if body_length <= MAX_BODY => body_length,
+            Some(Ok(_)) => return Ok(Incoming::Invalid(Response::error(413, "Body too long"))),
+            Some(Err(_)) => {
+                return Ok(Incoming::Invalid(Response::error(
+                    400,
+                    "Invalid content length",
+                )))
+            }
+        };
+        let target = parsed.path.unwrap_or("/");
+        let (path, query) = match target.find('?') {
+            Some(index) => (&target[..index], parse_query(&target[index + 1..])),
+            None => (target, HashMap::new()),
+        };
+        let method = parsed.method.unwrap_or_default().to_owned();
+        let path = path.to_owned();
+        let mut request = Request {
+            method,
+            path,
+            query,
+            body: buffer.split_off(length),
+        };
+        request.body.truncate(body_length);
+        let missing = body_length - request.body.len();
+        let mut rest = vec![0; missing];
+        stream.read_exact(&mut rest).await?;
+        request.body.extend(rest);
+        return Ok(Incoming::Http(request));
+    }
+}
+
+/// Puzzle without its solution
+#[derive(Serialize)]
+struct PuzzleInfo {
+    /// Identifies the puzzle, see Board::id
+    puzzle: u64,
+    width: usize,
+    height: usize,
+    dots: Vec<Dot>,
+    /// Seed which generates the puzzle again
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+}
+
+impl PuzzleInfo {
+    fn new(board: Board, seed: Option<u64>) -> Self {
+        let game = Game::new(board);
+        PuzzleInfo {
+            puzzle: game.puzzle,
+            width: game.width,
+            height: game.height,
+            dots: game.dots,
+            seed,
+        }
+    }
+}
+
+/// Puzzle of a submitted solution
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Submitted {
+    /// Puzzle of a room or a daily puzzle
+    Stored { puzzle: u64 },
+    /// Dots in dot grid coordinates
+    Inline {
+        width: usize,
+        height: usize,
+        dots: Vec<DotPos>,
+    },
+}
+
+#[derive(Deserialize)]
+struct Submission {
+    #[serde(flatten)]
+    puzzle: Submitted,
+    /// Dot of every field in rows from top to bottom like the fields of
+    /// boards, missing for unassigned fields
+    fields: Vec<Option<usize>>,
+}
+
+/// @return Value of the query parameter if it is there
+fn parameter<T: FromStr>(request: &Request, name: &str) -> Result<Option<T>, Response> {
+    match request.query.get(name) {
+        Some(value) => match value.parse() {
+            Ok(value) => Ok(Some(value)),
+            Err(_) => Err(Response::error(
+                400,
+                &format!("Invalid parameter '{}'", name),
+            )),
+        },
+        None => Ok(None),
+    }
+}
+
+fn required<T: FromStr>(request: &Request, name: &str) -> Result<T, Response> {
+    parameter(request, name)?
+        .ok_or_else(|| Response::error(400, &format!("Missing parameter '{}'", name)))
+}
+
+/// Generates a puzzle, the same parameters always result in the same one
+fn generate(galaxy: &Galaxy, request: &Request) -> Result<Response, Response> {
+    let width = required(request, "w")?;
+    let height = required(request, "h")?;
+    let seed = parameter(request, "seed")?;
+    let difficulty = parameter::<Difficulty>(request, "difficulty")?.unwrap_or_default();
+    let config = galaxy
+        .generation_config(width, height, difficulty, seed)
+        .map_err(|message| Response::error(400, message))?;
+    let (board, config) = gamegen::generate_reliably(&config)
+        .ok_or_else(|| Response::error(500, "Generating the board failed"))?;
+    Ok(Response::json(
+        200,
+        &PuzzleInfo::new(board, Some(config.seed)),
+    ))
+}
+
+fn stored(galaxy: &Galaxy, puzzle: &str) -> Result<Response, Response> {
+    let puzzle = puzzle
+        .parse()
+        .map_err(|_| Response::error(400, "Invalid puzzle id"))?;
+    let board = galaxy
+        .puzzle(puzzle)
+        .ok_or_else(|| Response::error(404, "Unknown puzzle"))?;
+    Ok(Response::json(200, &PuzzleInfo::new(board, None)))
+}
+
+/// Checks a submitted solution with the solver
+fn validate(galaxy: &Galaxy, body: &[u8]) -> Result<Response, Response> {
+    let submission: Submission = serde_json::from_slice(body)
+        .map_err(|error| Response::error(400, &format!("Invalid submission: {}", error)))?;
+    let (width, height, dots) = match submission.puzzle {
+        Submitted::Stored { puzzle } => {
+            let board = galaxy
+                .puzzle(puzzle)
+                .ok_or_else(|| Response::error(404, "Unknown puzzle"))?;
+            (board.width, board.height, board.dots)
+        }
+        Submitted::Inline {
+            width,
+            height,
+            dots,
+        } => {
+            if !galaxy.supports(width, height) {
+                return Err(Response::error(400, "Unsupported board size"));
+            }
+            if dots
+                .iter()
+                .any(|dot| dot.0 >= width * 2 - 1 || dot.1 >= height * 2 - 1)
+            {
+                return Err(Response::error(400, "Dot outside of the board"));
+            }
+            (width, height, dots)
+        }
+    };
+    if submission.fields.len() != width * height {
+        let message = format!("Expected {} fields", width * height);
+        return Err(Response::error(400, &message));
+    }
+    if submission
+        .fields
+        .iter()
+        .flatten()
+        .any(|&dot| dot >= dots.len())
+    {
+        return Err(Response::error(400, "Unknown dot"));
+    }
+
+    let puzzle = Puzzle {
+        width,
+        height,
+        dots: &dots,
+    };
+    let mut assignment = puzzle.empty_assignment();
+    for (index, &dot) in submission.fields.iter().enumerate() {
+        assignment[index % width][index / width] = dot;
+    }
+    let body = match solver::validate(&puzzle, &assignment) {
+        Ok(()) => json!({ "valid": true }),
+        Err(violation) => json!({ "valid": false, "violation": violation.to_string() }),
+    };
+    Ok(Response::json(200, &body))
+}
+
+fn leaderboard(galaxy: &Galaxy, request: &Request) -> Result<Response, Response> {
+    let period = parameter(request, "period")?.unwrap_or(Period::AllTime);
+    let puzzle = parameter(request, "puzzle")?;
+    let ranks = galaxy.ranking(period, puzzle);
+    let body = json!({ "period": period, "puzzle": puzzle, "ranks": ranks });
+    Ok(Response::json(200, &body))
+}
+
+/// Answers a request of the REST API:
+///
+/// - `GET /puzzle?w=&h=&seed=&difficulty=` generates a puzzle
+/// - `GET /puzzle/<id>` returns a puzzle of a room or a daily puzzle
+/// - `POST /validate` checks the solution of a puzzle
+/// - `GET /leaderboard?period=&puzzle=` ranks the players
+///
+/// Generating may take a while, so this blocks.
+pub fn respond(galaxy: &Galaxy, request: &Request) -> Response {
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    let result = match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["puzzle"]) => generate(galaxy, request),
+        ("GET", ["puzzle", puzzle]) => stored(galaxy, puzzle),
+        ("POST", ["validate"]) => validate(galaxy, &request.body),
+        ("GET", ["leaderboard"]) => leaderboard(galaxy, request),
+        (_, ["puzzle"]) | (_, ["puzzle", _]) | (_, ["validate"]) | (_, ["leaderboard"]) => {
+            Err(Response::error(405, "Method not allowed"))
+        }
+        _ => Err(Response::error(404, "Not found")),
+    };
+    result.unwrap_or_else(|response| response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gamegen::GenerationConfig;
+    use crate::network::Network;
+    use serde_json::Value;
+    use std::sync::Arc;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::{TcpListener, TcpStream};
+
+    const CONFIG: GenerationConfig = GenerationConfig {
+        width: 4,
+        height: 3,
+        difficulty: Difficulty::Easy,
+        seed: 7,
+    };
+
+    fn request(method: &str, target: &str, body: &Value) -> Request {
+        let (path, query) = match target.find('?') {
+            Some(index) => (&target[..index], parse_query(&target[index + 1..])),
+            None => (target, HashMap::new()),
+        };
+        Request {
+            method: method.to_owned(),
+            path: path.to_owned(),
+            query,
+            body: serde_json::to_vec(body).unwrap(),
+        }
+    }
+
+    /// @return Status and parsed body
+    fn call(galaxy: &Galaxy, method: &str, target: &str, body: Value) -> (u16, Value) {
+        let response = respond(galaxy, &request(method, target, &body));
+        (
+            response.status,
+            serde_json::from_str(&response.body).unwrap(),
+        )
+    }
+
+    /// @return Dot of every field in rows like the API expects them
+    fn fields(board: &Board) -> Vec<usize> {
+        let mut fields = Vec::new();
+        for y in 0..board.height {
+            for x in 0..board.width {
+                fields.push(board.galaxies[x][y]);
+            }
+        }
+        fields
+    }
+
+    #[test]
+    fn answers_api_requests() {
+        let galaxy = Galaxy::new(&CONFIG).unwrap();
+        let board = gamegen::generate(&CONFIG);
+
+        let (status, generated) = call(
+            &galaxy,
+            "GET",
+            "/puzzle?w=4&h=3&seed=7&difficulty=easy",
+            Value::Null,
+        );
+        assert_eq!(status, 200);
+        assert_eq!(generated["puzzle"], board.id());
+        assert_eq!(generated["seed"], 7);
+        assert_eq!(
+            generated["dots"].as_array().unwrap().len(),
+            board.dots.len()
+        );
+        assert_eq!(call(&galaxy, "GET", "/puzzle?w=4", Value::Null).0, 400);
+        assert_eq!(call(&galaxy, "GET", "/puzzle?w=4&h=99", Value::Null).0, 400);
+
+        let (status, stored) = call(
+            &galaxy,
+            "GET",
+            &format!("/puzzle/{}", board.id()),
+            Value::Null,
+        );
+        assert_eq!((status, &stored["dots"]), (200, &generated["dots"]));
+        assert_eq!(call(&galaxy, "GET", "/puzzle/1", Value::Null).0, 404);
+
+        let solution = fields(&board);
+        let stored = json!({ "puzzle": board.id(), "fields": solution });
+        assert_eq!(
+            call(&galaxy, "POST", "/validate", stored).1,
+            json!({ "valid": true })
+        );
+        let mut wrong = solution.iter().map(|&dot| Some(dot)).collect::<Vec<_>>();
+        wrong[0] = None;
+        let inline = json!({ "width": 4, "height": 3, "dots": board.dots, "fields": wrong });
+        let (status, invalid) = call(&galaxy, "POST", "/validate", inline);
+        assert_eq!((status, &invalid["valid"]), (200, &json!(false)));
+        let outside = json!({ "width": 1, "height": 1, "dots": [[1, 0]], "fields": [0] });
+        assert_eq!(call(&galaxy, "POST", "/validate", outside).0, 400);
+        assert_eq!(call(&galaxy, "GET", "/validate", Value::Null).0, 405);
+
+        let (status, ranking) = call(&galaxy, "GET", "/leaderboard?period=daily", Value::Null);
+        assert_eq!((status, &ranking["ranks"]), (200, &json!([])));
+        assert_eq!(
+            call(&galaxy, "GET", "/leaderboard?period=monthly", Value::Null).0,
+            400
+        );
+        assert_eq!(call(&galaxy, "GET", "/", Value::Null).0, 404);
+
+        let error = Response::text(500, String::new());
+        assert_eq!(error.content_type, "text/plain; charset=utf-8");
+        assert!(Response::metrics(String::new())
+            .content_type
+            .contains("version=0.0.4"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serves_http_next_to_websockets() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(Network::new(Arc::new(Galaxy::new(&CONFIG).unwrap())).serve(listener));
+
+        let board = gamegen::generate(&CONFIG);
+        let body = json!({ "puzzle": board.id(), "fields": fields(&board) }).to_string();
+        let mut stream = TcpStream::connect(&address).await.unwrap();
+        let request = format!(
+            "POST /validate HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n",
+            body.len()
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        // The body arrives separately, so it has to be waited for
+        tokio::task::yield_now().await;
+        stream.write_all(body.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("{\"valid\":true}"));
+
+        let url = format!("ws://{}/", address);
+        let (socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        drop(socket);
+    }
+}
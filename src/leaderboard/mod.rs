@@ -0,0 +1,250 @@
+use crate::types::Id;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Nobody assigns fields faster than this many milliseconds per move
+pub const MIN_MILLIS_PER_MOVE: u64 = 100;
+
+/// Solves of boards with fewer fields don't count, they are over too fast
+pub const MIN_BOARD_FIELDS: usize = 25;
+
+/// Players who own a smaller share of the solution in percent only helped,
+/// so their solves don't count
+pub const MIN_SHARE_PERCENT: usize = 10;
+
+const MILLIS_PER_DAY: u64 = 24 * 60 * 60 * 1000;
+
+/// A player who took part in solving a puzzle
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Solve {
+    pub player: Id,
+    /// Identifies the board, see Board::id
+    pub puzzle: u64,
+    /// Milliseconds since the Unix epoch when the puzzle was solved
+    pub time: u64,
+    /// Milliseconds from the first move of the player until the puzzle was
+    /// solved
+    pub duration: u64,
+    /// Moves the player made
+    pub moves: usize,
+    /// Fields which the player assigned last in the solution
+    pub fields: usize,
+    /// Fields of the board
+    pub board_fields: usize,
+}
+
+impl Solve {
+    /// Rejects solves of small boards, of players who barely helped and
+    /// solves which are faster than assigning the fields of the player allows
+    pub fn check(&self) -> Result<(), String> {
+        if self.board_fields < MIN_BOARD_FIELDS {
+            return Err(format!(
+                "Boards of {} fields are too small",
+                self.board_fields
+            ));
+        }
+        if self.fields * 100 < self.board_fields * MIN_SHARE_PERCENT {
+            return Err(format!(
+                "{} of {} fields are too few",
+                self.fields, self.board_fields
+            ));
+        }
+        // Every field of the player took a move, the first one starts the
+        // clock and takes no time
+        let moves = self.moves.max(self.fields);
+        let minimum = (moves as u64).saturating_sub(1) * MIN_MILLIS_PER_MOVE;
+        if self.duration < minimum {
+            return Err(format!(
+                "{} moves in {}ms are impossible",
+                moves, self.duration
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Period {
+    /// Since midnight in UTC
+    Daily,
+    /// The last seven days including today
+    Weekly,
+    AllTime,
+}
+
+impl FromStr for Period {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "daily" => Ok(Period::Daily),
+            "weekly" => Ok(Period::Weekly),
+            "all_time" => Ok(Period::AllTime),
+            _ => Err(format!("Unknown period '{}'", s)),
+        }
+    }
+}
+
+/// Rank of a player in a leaderboard
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub player: Id,
+    pub solves: usize,
+    /// Best duration for a single puzzle, otherwise the sum of all solves
+    pub duration: u64,
+    pub moves: usize,
+}
+
+/// All solves of the server
+#[derive(Default)]
+pub struct Leaderboard {
+    solves: Vec<Solve>,
+}
+
+impl Leaderboard {
+    /// Records the solve if it is plausible
+    pub fn record(&mut self, solve: Solve) -> Result<(), String> {
+        solve.check()?;
+        self.solves.push(solve);
+        Ok(())
+    }
+
+    pub fn solves(&self) -> &[Solve] {
+        &self.solves
+    }
+
+    /// Ranks the players by their solves in the period. For a single puzzle
+    /// the fastest solve of every player counts, otherwise players with more
+    /// solves and then less time in total are ranked first.
+    ///
+    /// @return Entries from the first to the last rank
+    pub fn ranking(&self, period: Period, puzzle: Option<u64>, now: u64) -> Vec<Entry> {
+        let today = now / MILLIS_PER_DAY * MILLIS_PER_DAY;
+        let since = match period {
+            Period::Daily => today,
+            Period::Weekly => today.saturating_sub(6 * MILLIS_PER_DAY),
+            Period::AllTime => 0,
+        };
+
+        let mut entries: HashMap<Id, Entry> = HashMap::new();
+        let solves = self.solves.iter().filter(|solve| {
+            solve.time >= since && puzzle.is_none_or(|puzzle| puzzle == solve.puzzle)
+        });
+        for solve in solves {
+            let entry = entries.entry(solve.player).or_insert(Entry {
+                player: solve.player,
+                solves: 0,
+                duration: 0,
+                moves: 0,
+            });
+            let best = entry.solves == 0 || solve.duration < entry.duration;
+            entry.solves += 1;
+            if puzzle.is_none() {
+                entry.duration += solve.duration;
+                entry.moves += solve.moves;
+            } else if best {
+                entry.duration = solve.duration;
+                entry.moves = solve.moves;
+            }
+        }
+
+        let mut entries: Vec<Entry> = entries.into_values().collect();
+        entries.sort_by_key(|entry| {
+            let solves = if puzzle.is_none() { entry.solves } else { 0 };
+            (Reverse(solves), entry.duration, entry.moves, entry.player)
+        });
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solve(player: Id, puzzle: u64, day: u64, duration: u64) -> Solve {
+        Solve {
+            player,
+            puzzle,
+            time: day * MILLIS_PER_DAY + 1000,
+            duration,
+            moves: 10,
+            fields: 10,
+            board_fields: 50,
+        }
+    }
+
+    #[test]
+    fn rejects_implausible_solves() {
+        let mut leaderboard = Leaderboard::default();
+        assert!(leaderboard.record(solve(0, 1, 0, 899)).is_err());
+        assert!(leaderboard.record(solve(0, 1, 0, 900)).is_ok());
+        assert_eq!(leaderboard.solves().len(), 1);
+    }
+
+    #[test]
+    fn rejects_helpers_and_small_boards() {
+        let helper = Solve {
+            moves: 1,
+            fields: 1,
+            duration: 0,
+            ..solve(0, 1, 0, 900)
+        };
+        assert!(helper.check().is_err());
+        let tiny = Solve {
+            moves: 1,
+            fields: 1,
+            board_fields: 1,
+            duration: 0,
+            ..solve(0, 1, 0, 900)
+        };
+        assert!(tiny.check().is_err());
+
+        // Fields were assigned by moves even if fewer moves are claimed
+        let fast = Solve {
+            moves: 1,
+            duration: 100,
+            ..solve(0, 1, 0, 900)
+        };
+        assert!(fast.check().is_err());
+        let share = Solve {
+            fields: 5,
+            ..solve(0, 1, 0, 900)
+        };
+        assert!(share.check().is_ok());
+    }
+
+    #[test]
+    fn ranks_by_period_and_puzzle() {
+        let mut leaderboard = Leaderboard::default();
+        for solve in [
+            solve(0, 1, 10, 5000),
+            solve(0, 2, 10, 3000),
+            solve(1, 1, 10, 4000),
+            solve(1, 1, 3, 2000),
+            solve(2, 3, 9, 9000),
+        ] {
+            leaderboard.record(solve).unwrap();
+        }
+        let now = 10 * MILLIS_PER_DAY + 5000;
+        let ranks = |period, puzzle| -> Vec<(Id, usize, u64)> {
+            leaderboard
+                .ranking(period, puzzle, now)
+                .iter()
+                .map(|entry| (entry.player, entry.solves, entry.duration))
+                .collect()
+        };
+
+        assert_eq!(ranks(Period::Daily, None), vec![(0, 2, 8000), (1, 1, 4000)]);
+        assert_eq!(
+            ranks(Period::Weekly, None),
+            vec![(0, 2, 8000), (1, 1, 4000), (2, 1, 9000)]
+        );
+        assert_eq!(
+            ranks(Period::AllTime, Some(1)),
+            vec![(1, 2, 2000), (0, 1, 5000)]
+        );
+    }
+}
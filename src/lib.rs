@@ -0,0 +1,22 @@
+pub mod admin;
+pub mod book;
+pub mod config;
+pub mod daily;
+pub mod export;
+pub mod galaxy;
+pub mod game;
+pub mod gamegen;
+pub mod http;
+pub mod leaderboard;
+pub mod limits;
+pub mod loadtest;
+pub mod logger;
+pub mod metrics;
+pub mod network;
+pub mod protocol;
+pub mod replay;
+pub mod secret;
+pub mod solver;
+pub mod storage;
+pub mod tls;
+pub mod types;
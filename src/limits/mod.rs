@@ -0,0 +1,509 @@
+use crate::galaxy::RoomId;
+use crate::protocol::ClientMessage;
+use crate::types::Id;
+use log::warn;
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// Buckets which are full again are forgotten this often
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Amount of requests which may be made at once and how fast the allowance
+/// recovers afterwards
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rate {
+    pub burst: f64,
+    pub per_second: f64,
+}
+
+/// Reads a duration given in seconds
+fn seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    u64::deserialize(deserializer).map(Duration::from_secs)
+}
+
+/// Limits of every address and player
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitConfig {
+    /// New connections of an address
+    pub connections: Rate,
+    /// Messages and HTTP requests of any kind
+    pub messages: Rate,
+    /// Assigning, undoing and redoing
+    pub moves: Rate,
+    /// Requests which generate a board
+    pub generations: Rate,
+    /// Logins and joining or watching rooms with a password, which hash or
+    /// verify a secret and may register a player
+    pub credentials: Rate,
+    /// Violations of the other limits before an address is banned
+    pub strikes: Rate,
+    /// How long addresses are banned, in seconds in config files
+    #[serde(deserialize_with = "seconds")]
+    pub ban: Duration,
+}
+
+impl Default for LimitConfig {
+    fn default() -> Self {
+        LimitConfig {
+            connections: Rate {
+                burst: 10.0,
+                per_second: 1.0,
+            },
+            messages: Rate {
+                burst: 50.0,
+                per_second: 20.0,
+            },
+            // The leaderboard doesn't accept faster moves anyway, see
+            // MIN_MILLIS_PER_MOVE
+            moves: Rate {
+                burst: 20.0,
+                per_second: 10.0,
+            },
+            generations: Rate {
+                burst: 3.0,
+                per_second: 0.2,
+            },
+            credentials: Rate {
+                burst: 5.0,
+                per_second: 0.1,
+            },
+            strikes: Rate {
+                burst: 20.0,
+                per_second: 0.2,
+            },
+            ban: Duration::from_secs(10 * 60),
+        }
+    }
+}
+
+impl LimitConfig {
+    /// @return Config which never limits anything
+    pub fn unlimited() -> Self {
+        let rate = Rate {
+            burst: f64::MAX,
+            per_second: f64::MAX,
+        };
+        LimitConfig {
+            connections: rate,
+            messages: rate,
+            moves: rate,
+            generations: rate,
+            credentials: rate,
+            strikes: rate,
+            ban: Duration::ZERO,
+        }
+    }
+
+    fn rate(&self, action: Action) -> Rate {
+        match action {
+            Action::Connect => self.connections,
+            Action::Message => self.messages,
+            Action::Move => self.moves,
+            Action::Generate => self.generations,
+            Action::Credentials => self.credentials,
+        }
+    }
+}
+
+/// What a client does, every action has its own limit
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Connect,
+    Message,
+    Move,
+    Generate,
+    Credentials,
+}
+
+impl Action {
+    /// @return Action which a WebSocket message is limited as
+    pub fn of(message: &ClientMessage) -> Self {
+        match message {
+            ClientMessage::Assign { .. } | ClientMessage::Undo | ClientMessage::Redo => {
+                Action::Move
+            }
+            ClientMessage::NewGame { .. } | ClientMessage::CreateRoom { .. } => Action::Generate,
+            ClientMessage::Login { .. }
+            | ClientMessage::Join {
+                password: Some(_), ..
+            }
+            | ClientMessage::Spectate {
+                password: Some(_), ..
+            } => Action::Credentials,
+            _ => Action::Message,
+        }
+    }
+}
+
+/// @return Name or room whose secret the message checks, if there is one
+fn guarded(message: &ClientMessage) -> Option<Key> {
+    match message {
+        ClientMessage::Login { name, .. } => Some(Key::Name(name.trim().to_owned())),
+        ClientMessage::Join {
+            room,
+            password: Some(_),
+        }
+        | ClientMessage::Spectate {
+            room,
+            password: Some(_),
+            ..
+        } => Some(Key::Room(*room)),
+        _ => None,
+    }
+}
+
+/// Outcome of checking a limit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Allowed,
+    /// The request has to be rejected
+    Limited,
+    /// The address is banned, its connections have to be closed
+    Banned,
+}
+
+/// Token bucket, which allows a burst of requests and refills at a fixed
+/// rate
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(rate: Rate, now: Instant) -> Self {
+        Bucket {
+            tokens: rate.burst,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, rate: Rate, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate.per_second).min(rate.burst);
+        self.updated = now;
+    }
+
+    /// @return Whether a token was left
+    fn take(&mut self, rate: Rate, now: Instant) -> bool {
+        self.refill(rate, now);
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+/// Who a bucket belongs to. Names and rooms are limited no matter where
+/// guesses of their secrets come from.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Key {
+    Address(IpAddr),
+    Player(Id),
+    Name(String),
+    Room(RoomId),
+}
+
+/// Rate limits of addresses and players. Addresses which keep exceeding
+/// them are banned for a while.
+#[derive(Debug)]
+pub struct Limits {
+    config: LimitConfig,
+    buckets: HashMap<(Key, Action), Bucket>,
+    strikes: HashMap<IpAddr, Bucket>,
+    bans: HashMap<IpAddr, Instant>,
+    cleaned: Instant,
+}
+
+impl Limits {
+    pub fn new(config: LimitConfig) -> Self {
+        Limits {
+            config,
+            buckets: HashMap::new(),
+            strikes: HashMap::new(),
+            bans: HashMap::new(),
+            cleaned: Instant::now(),
+        }
+    }
+
+    /// @return Whether the key has a token left for the action
+    fn take(&mut self, key: Key, action: Action, now: Instant) -> bool {
+        let rate = self.config.rate(action);
+        self.buckets
+            .entry((key, action))
+            .or_insert_with(|| Bucket::new(rate, now))
+            .take(rate, now)
+    }
+
+    /// @return Whether the address is banned right now
+    pub fn banned(&self, address: IpAddr, now: Instant) -> bool {
+        self.bans.get(&address).is_some_and(|&until| now < until)
+    }
+
+    /// Records a violation of the address, which is banned if it has too
+    /// many of them
+    pub fn strike(&mut self, address: IpAddr, reason: &str, now: Instant) -> Verdict {
+        let rate = self.config.strikes;
+        let strikes = self
+            .strikes
+            .entry(address)
+            .or_insert_with(|| Bucket::new(rate, now));
+        if strikes.take(rate, now) {
+            return Verdict::Limited;
+        }
+        self.strikes.remove(&address);
+        self.bans.insert(address, now + self.config.ban);
+        warn!(
+            "Banned {} for {}s: {}",
+            address,
+            self.config.ban.as_secs(),
+            reason
+        );
+        Verdict::Banned
+    }
+
+    /// Checks whether the address and the player, if there is one, may
+    /// perform the action. Everything except connecting counts as message
+    /// as well.
+    pub fn check(
+        &mut self,
+        address: IpAddr,
+        player: Option<Id>,
+        action: Action,
+        now: Instant,
+    ) -> Verdict {
+        if now.saturating_duration_since(self.cleaned) >= CLEANUP_INTERVAL {
+            self.cleanup(now);
+        }
+        if self.banned(address, now) {
+            return Verdict::Banned;
+        }
+
+        let mut actions = vec![action];
+        if action != Action::Connect && action != Action::Message {
+            actions.push(Action::Message);
+        }
+        for action in actions {
+            let mut allowed = self.take(Key::Address(address), action, now);
+            if let Some(player) = player {
+                allowed &= self.take(Key::Player(player), action, now);
+            }
+            if !allowed {
+                let reason = match player {
+                    Some(player) => format!("Player {} exceeded {:?} limit", player, action),
+                    None => format!("Exceeded {:?} limit", action),
+                };
+                return self.strike(address, &reason, now);
+            }
+        }
+        Verdict::Allowed
+    }
+
+    /// Checks the limits of the message like check. Messages which check a
+    /// secret are limited for its name or room as well.
+    pub fn check_message(
+        &mut self,
+        address: IpAddr,
+        player: Option<Id>,
+        message: &ClientMessage,
+        now: Instant,
+    ) -> Verdict {
+        let verdict = self.check(address, player, Action::of(message), now);
+        match guarded(message) {
+            Some(key) if verdict == Verdict::Allowed => {
+                if self.take(key.clone(), Action::Credentials, now) {
+                    return Verdict::Allowed;
+                }
+                let reason = format!("Exceeded {:?} limit of {:?}", Action::Credentials, key);
+                self.strike(address, &reason, now)
+            }
+            _ => verdict,
+        }
+    }
+
+    /// Forgets expired bans and buckets which are full again, because they
+    /// don't differ from new ones
+    fn cleanup(&mut self, now: Instant) {
+        let config = self.config;
+        self.buckets.retain(|&(_, action), bucket| {
+            let rate = config.rate(action);
+            bucket.refill(rate, now);
+            bucket.tokens < rate.burst
+        });
+        self.strikes.retain(|_, bucket| {
+            bucket.refill(config.strikes, now);
+            bucket.tokens < config.strikes.burst
+        });
+        self.bans.retain(|_, &mut until| now < until);
+        self.cleaned = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::galaxy::Galaxy;
+    use crate::network::Network;
+    use crate::protocol::ServerMessage;
+    use crate::secret::Secret;
+    use futures_util::{SinkExt, StreamExt};
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::Message;
+
+    fn config() -> LimitConfig {
+        let rate = |burst, per_second| Rate { burst, per_second };
+        LimitConfig {
+            connections: rate(2.0, 1.0),
+            messages: rate(4.0, 1.0),
+            moves: rate(2.0, 1.0),
+            generations: rate(1.0, 0.5),
+            credentials: rate(2.0, 0.1),
+            strikes: rate(2.0, 0.1),
+            ban: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn limits_refill_and_ban_repeated_violations() {
+        let mut limits = Limits::new(config());
+        let address: IpAddr = [10, 0, 0, 1].into();
+        let other: IpAddr = [10, 0, 0, 2].into();
+        let start = Instant::now();
+        let check = |limits: &mut Limits, address, player, action, seconds| {
+            limits.check(
+                address,
+                player,
+                action,
+                start + Duration::from_secs(seconds),
+            )
+        };
+
+        assert_eq!(
+            check(&mut limits, address, Some(1), Action::Move, 0),
+            Verdict::Allowed
+        );
+        assert_eq!(
+            check(&mut limits, address, Some(1), Action::Move, 0),
+            Verdict::Allowed
+        );
+        assert_eq!(
+            check(&mut limits, address, Some(1), Action::Move, 0),
+            Verdict::Limited
+        );
+        // The player is limited from other addresses as well
+        assert_eq!(
+            check(&mut limits, other, Some(1), Action::Move, 0),
+            Verdict::Limited
+        );
+        assert_eq!(
+            check(&mut limits, other, Some(2), Action::Move, 0),
+            Verdict::Allowed
+        );
+        // Moves count as messages too
+        assert_eq!(
+            check(&mut limits, address, None, Action::Message, 1),
+            Verdict::Allowed
+        );
+        assert_eq!(
+            check(&mut limits, address, Some(1), Action::Move, 1),
+            Verdict::Allowed
+        );
+
+        assert_eq!(
+            check(&mut limits, address, None, Action::Generate, 1),
+            Verdict::Allowed
+        );
+        assert_eq!(
+            check(&mut limits, address, None, Action::Generate, 1),
+            Verdict::Limited
+        );
+        assert_eq!(
+            check(&mut limits, address, None, Action::Generate, 1),
+            Verdict::Banned
+        );
+        assert!(limits.banned(address, start + Duration::from_secs(60)));
+        assert!(!limits.banned(other, start + Duration::from_secs(60)));
+        assert_eq!(
+            check(&mut limits, address, None, Action::Connect, 62),
+            Verdict::Allowed
+        );
+    }
+
+    #[test]
+    fn secrets_are_limited_per_name() {
+        let mut limits = Limits::new(config());
+        let address: IpAddr = [10, 0, 0, 1].into();
+        let other: IpAddr = [10, 0, 0, 2].into();
+        let now = Instant::now();
+        let login = |name: &str| ClientMessage::Login {
+            name: name.to_owned(),
+            passphrase: Secret::default(),
+        };
+
+        assert_eq!(
+            limits.check_message(address, None, &login("admin"), now),
+            Verdict::Allowed
+        );
+        assert_eq!(
+            limits.check_message(address, None, &login("admin"), now),
+            Verdict::Allowed
+        );
+        assert_eq!(
+            limits.check_message(address, None, &login("someone"), now),
+            Verdict::Limited
+        );
+        // Guesses from other addresses count for the name as well
+        assert_eq!(
+            limits.check_message(other, None, &login(" admin "), now),
+            Verdict::Limited
+        );
+        assert_eq!(
+            limits.check_message(other, None, &login("someone"), now),
+            Verdict::Allowed
+        );
+        assert_eq!(
+            limits.check_message(other, None, &ClientMessage::Rooms, now),
+            Verdict::Allowed
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn network_enforces_limits() {
+        let galaxy = Galaxy::for_tests();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/", listener.local_addr().unwrap());
+        let network = Network::new(Arc::new(galaxy)).limits(config());
+        tokio::spawn(network.serve(listener));
+
+        let (mut socket, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        let rooms = serde_json::to_string(&ClientMessage::Rooms).unwrap();
+        for _ in 0..5 {
+            socket.send(Message::Text(rooms.clone())).await.unwrap();
+        }
+        let mut answers = Vec::new();
+        for _ in 0..5 {
+            let text = socket.next().await.unwrap().unwrap().into_text().unwrap();
+            answers.push(serde_json::from_str::<ServerMessage>(&text).unwrap());
+        }
+        let limited = answers
+            .iter()
+            .filter(|answer| matches!(answer, ServerMessage::Error { .. }))
+            .count();
+        assert_eq!(limited, 1);
+
+        // Oversized messages end the connection and count as violation, so
+        // the next one bans the address
+        let long = "x".repeat(64 * 1024);
+        let _ = socket.send(Message::Text(long)).await;
+        while let Some(Ok(_)) = socket.next().await {}
+        let (mut socket, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        socket.send(Message::Text(rooms)).await.unwrap();
+        assert!(!matches!(socket.next().await, Some(Ok(Message::Text(_)))));
+    }
+}
@@ -0,0 +1,329 @@
+use crate::galaxy::{Mode, RoomId, Settings, MAIN_ROOM};
+use crate::gamegen::Difficulty;
+use crate::protocol::{ClientMessage, ServerMessage};
+use crate::secret::Secret;
+use crate::types::{Id, Offset, Position};
+use futures_util::{SinkExt, StreamExt};
+use rand::Rng;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::sync::Barrier;
+use tokio::time;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+/// Clients give up when the server doesn't answer within this time
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Simulated clients and what they do
+#[derive(Clone, Debug)]
+pub struct LoadTestConfig {
+    /// Address of the server like 127.0.0.1:9001
+    pub address: String,
+    pub clients: usize,
+    /// Rooms the clients are spread over, they stay in the main room if 0
+    pub rooms: usize,
+    /// Width and height of the boards of the rooms
+    pub size: usize,
+    /// Moves of every client
+    pub moves: usize,
+    /// Time between two moves of a client
+    pub interval: Duration,
+}
+
+/// Outcome of a load test
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Clients which made all their moves
+    pub completed: usize,
+    /// Reasons of the clients which failed
+    pub failures: Vec<String>,
+    /// Time from every move until the server answered it
+    pub latencies: Vec<Duration>,
+    pub duration: Duration,
+}
+
+impl Report {
+    /// @return Latency which the given fraction of moves didn't exceed
+    pub fn percentile(&self, fraction: f64) -> Option<Duration> {
+        let mut latencies = self.latencies.clone();
+        latencies.sort();
+        let index = ((latencies.len() as f64 * fraction).ceil() as usize).max(1) - 1;
+        latencies.get(index).copied()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} clients completed, {} failed in {:.1}s",
+            self.completed,
+            self.failures.len(),
+            self.duration.as_secs_f64()
+        )?;
+        let seconds = self.duration.as_secs_f64().max(f64::EPSILON);
+        writeln!(
+            f,
+            "{} moves answered, {:.1} moves/s",
+            self.latencies.len(),
+            self.latencies.len() as f64 / seconds
+        )?;
+        for &(name, fraction) in &[("p50", 0.5), ("p90", 0.9), ("p99", 0.99), ("max", 1.0)] {
+            if let Some(latency) = self.percentile(fraction) {
+                writeln!(
+                    f,
+                    "{} latency: {:.1}ms",
+                    name,
+                    latency.as_secs_f64() * 1000.0
+                )?;
+            }
+        }
+        let mut failures = self.failures.clone();
+        failures.sort();
+        failures.dedup();
+        for failure in failures.iter().take(5) {
+            writeln!(f, "Failure: {}", failure)?;
+        }
+        Ok(())
+    }
+}
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Connection of a simulated client
+struct Bot {
+    socket: Socket,
+}
+
+impl Bot {
+    async fn send(&mut self, message: &ClientMessage) -> Result<(), String> {
+        let text = serde_json::to_string(message).expect("Serializing message failed");
+        self.socket
+            .send(Message::Text(text))
+            .await
+            .map_err(|error| error.to_string())
+    }
+
+    /// Waits for the first message the filter accepts and skips all others
+    async fn expect<T>(
+        &mut self,
+        mut filter: impl FnMut(ServerMessage) -> Option<T>,
+    ) -> Result<T, String> {
+        let receive = async {
+            while let Some(message) = self.socket.next().await {
+                let text = match message.map_err(|error| error.to_string())? {
+                    Message::Text(text) => text,
+                    Message::Close(_) => break,
+                    _ => continue,
+                };
+                let message = serde_json::from_str(&text).map_err(|error| error.to_string())?;
+                if let Some(found) = filter(message) {
+                    return Ok(found);
+                }
+            }
+            Err("Connection closed".to_owned())
+        };
+        time::timeout(RESPONSE_TIMEOUT, receive)
+            .await
+            .map_err(|_| "Server didn't answer".to_owned())?
+    }
+}
+
+/// Logs in and puts the leaders of the groups into new rooms, which the
+/// others join after the barrier
+///
+/// @return Bot and its player
+async fn prepare(config: &LoadTestConfig, index: usize) -> Result<(Bot, Id), String> {
+    let url = format!("ws://{}/", config.address);
+    let (socket, _) = tokio_tungstenite::connect_async(url)
+        .await
+        .map_err(|error| error.to_string())?;
+    let mut bot = Bot { socket };
+    bot.send(&ClientMessage::Login {
+        name: format!("load-{}", index),
+        passphrase: Secret("load".to_owned()),
+    })
+    .await?;
+    let player = bot
+        .expect(|message| match message {
+            ServerMessage::Welcome { player } => Some(player),
+            _ => None,
+        })
+        .await?;
+
+    if index < config.rooms {
+        bot.send(&ClientMessage::CreateRoom {
+            name: format!("load-{}", index),
+            width: config.size,
+            height: config.size,
+            difficulty: Difficulty::Easy,
+            seed: None,
+            mode: Mode::Coop,
+            settings: Settings::default(),
+            password: None,
+            capacity: None,
+        })
+        .await?;
+        bot.expect(|message| match message {
+            ServerMessage::Joined { room, .. } if room != MAIN_ROOM => Some(Ok(())),
+            ServerMessage::Error { message } => Some(Err(message)),
+            _ => None,
+        })
+        .await??;
+    }
+    Ok((bot, player))
+}
+
+/// Joins the room of the group unless the bot leads it
+async fn join(config: &LoadTestConfig, index: usize, bot: &mut Bot) -> Result<(), String> {
+    if config.rooms == 0 || index < config.rooms {
+        return Ok(());
+    }
+    bot.send(&ClientMessage::Rooms).await?;
+    let name = format!("load-{}", index % config.rooms);
+    // Rooms of earlier runs may be open still, so the newest counts
+    let room: Option<RoomId> = bot
+        .expect(|message| match message {
+            ServerMessage::Rooms { rooms } => Some(
+                rooms
+                    .iter()
+                    .filter(|info| info.name == name)
+                    .map(|info| info.room)
+                    .max(),
+            ),
+            _ => None,
+        })
+        .await?;
+    let room = room.ok_or_else(|| format!("Room {} is missing", name))?;
+    bot.send(&ClientMessage::Join {
+        room,
+        password: None,
+    })
+    .await?;
+    bot.expect(|message| match message {
+        ServerMessage::Joined { room: joined, .. } if joined == room => Some(()),
+        _ => None,
+    })
+    .await
+}
+
+/// Assigns random fields and measures how long the answers take
+///
+/// @return Latencies of all moves
+async fn play(config: &LoadTestConfig, bot: &mut Bot, player: Id) -> Result<Vec<Duration>, String> {
+    let (width, height, dots) = bot
+        .expect(|message| match message {
+            ServerMessage::Board {
+                width,
+                height,
+                dots,
+                ..
+            } => Some((width, height, dots.len())),
+            _ => None,
+        })
+        .await?;
+
+    let mut latencies = Vec::with_capacity(config.moves);
+    for _ in 0..config.moves {
+        time::sleep(config.interval).await;
+        let (field, dot) = {
+            let mut rng = rand::thread_rng();
+            let field = Position(
+                rng.gen_range(0..width) as Offset,
+                rng.gen_range(0..height) as Offset,
+            );
+            let dot = rng.gen_bool(0.8).then(|| rng.gen_range(0..dots) as Id);
+            (field, dot)
+        };
+        let start = Instant::now();
+        bot.send(&ClientMessage::Assign { field, dot }).await?;
+        bot.expect(|message| match message {
+            ServerMessage::Change { change, .. } if change.player == player => Some(()),
+            ServerMessage::Error { .. } => Some(()),
+            _ => None,
+        })
+        .await?;
+        latencies.push(start.elapsed());
+    }
+    Ok(latencies)
+}
+
+async fn simulate(
+    config: Arc<LoadTestConfig>,
+    index: usize,
+    barrier: Arc<Barrier>,
+) -> Result<Vec<Duration>, String> {
+    let prepared = prepare(&config, index).await;
+    // Every client has to reach the barrier, otherwise the others wait forever
+    barrier.wait().await;
+    let (mut bot, player) = prepared?;
+    join(&config, index, &mut bot).await?;
+    let latencies = play(&config, &mut bot, player).await?;
+    let _ = bot.socket.close(None).await;
+    Ok(latencies)
+}
+
+/// Simulates the clients of the config against a running server
+pub async fn run(config: LoadTestConfig) -> Report {
+    let config = Arc::new(config);
+    let barrier = Arc::new(Barrier::new(config.clients));
+    let start = Instant::now();
+    let clients: Vec<_> = (0..config.clients)
+        .map(|index| tokio::spawn(simulate(Arc::clone(&config), index, Arc::clone(&barrier))))
+        .collect();
+
+    let mut report = Report::default();
+    for client in clients {
+        match client.await {
+            Ok(Ok(latencies)) => {
+                report.completed += 1;
+                report.latencies.extend(latencies);
+            }
+            Ok(Err(reason)) => report.failures.push(reason),
+            Err(error) => report.failures.push(error.to_string()),
+        }
+    }
+    report.duration = start.elapsed();
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::galaxy::Galaxy;
+    use crate::gamegen::GenerationConfig;
+    use crate::limits::LimitConfig;
+    use crate::network::Network;
+    use tokio::net::TcpListener;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn clients_play_in_rooms() {
+        let galaxy = Galaxy::new(&GenerationConfig {
+            width: 4,
+            height: 4,
+            difficulty: Difficulty::Easy,
+            seed: 1,
+        })
+        .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let network = Network::new(Arc::new(galaxy)).limits(LimitConfig::unlimited());
+        tokio::spawn(network.serve(listener));
+
+        let report = run(LoadTestConfig {
+            address,
+            clients: 12,
+            rooms: 3,
+            size: 4,
+            moves: 3,
+            interval: Duration::from_millis(5),
+        })
+        .await;
+        assert_eq!(report.failures, Vec::<String>::new());
+        assert_eq!(report.completed, 12);
+        assert_eq!(report.latencies.len(), 36);
+    }
+}
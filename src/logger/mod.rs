@@ -0,0 +1,211 @@
+use clap::ValueEnum;
+use log::kv::{self, VisitSource};
+use log::{LevelFilter, Log, Metadata, Record};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::cell::RefCell;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Target of the trace records with whole grids, which are only logged if
+/// enabled explicitly because they are huge
+pub const GRIDS: &str = "galaxy::grids";
+
+/// The WebSocket crates trace every frame, which slows the server down
+const IGNORED: [&str; 2] = ["tungstenite", "tokio_tungstenite"];
+
+thread_local! {
+    /// Fields which are added to every record of the thread
+    static CONTEXT: RefCell<Vec<(&'static str, Value)>> = const { RefCell::new(Vec::new()) };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    /// Human readable lines with the fields at the end
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/// Adds fields to the records of the thread while it is alive
+#[must_use]
+pub struct Context {
+    length: usize,
+}
+
+impl Drop for Context {
+    fn drop(&mut self) {
+        CONTEXT.with(|context| context.borrow_mut().truncate(self.length));
+    }
+}
+
+/// Adds the fields to every record the thread logs until the returned
+/// context is dropped, for example the room a message is handled for
+pub fn context(fields: Vec<(&'static str, Value)>) -> Context {
+    CONTEXT.with(|context| {
+        let mut context = context.borrow_mut();
+        let length = context.len();
+        context.extend(fields);
+        Context { length }
+    })
+}
+
+/// Collects the key-values of a record
+struct Fields<'a>(&'a mut Vec<(String, Value)>);
+
+impl<'kvs> VisitSource<'kvs> for Fields<'_> {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        let value = if let Some(value) = value.to_u64() {
+            Value::from(value)
+        } else if let Some(value) = value.to_i64() {
+            Value::from(value)
+        } else if let Some(value) = value.to_f64() {
+            Value::from(value)
+        } else if let Some(value) = value.to_bool() {
+            Value::from(value)
+        } else {
+            Value::from(value.to_string())
+        };
+        self.0.push((key.to_string(), value));
+        Ok(())
+    }
+}
+
+/// @return Line for the record without the line break
+fn format(format: Format, record: &Record, millis: u128) -> String {
+    let mut fields: Vec<(String, Value)> = CONTEXT.with(|context| {
+        let context = context.borrow();
+        let fields = context.iter();
+        fields
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect()
+    });
+    let _ = record.key_values().visit(&mut Fields(&mut fields));
+
+    match format {
+        Format::Text => {
+            let seconds = millis / 1000;
+            let mut line = format!(
+                "{:02}:{:02}:{:02}.{:03} [{:>5}] {}: {}",
+                seconds / 3600 % 24,
+                seconds / 60 % 60,
+                seconds % 60,
+                millis % 1000,
+                record.level(),
+                record.target(),
+                record.args()
+            );
+            for (key, value) in fields {
+                match value {
+                    Value::String(value) => write!(line, " {}={:?}", key, value),
+                    value => write!(line, " {}={}", key, value),
+                }
+                .unwrap();
+            }
+            line
+        }
+        Format::Json => {
+            let mut object = Map::new();
+            object.insert("time".to_owned(), Value::from(millis as u64));
+            object.insert("level".to_owned(), Value::from(record.level().as_str()));
+            object.insert("target".to_owned(), Value::from(record.target()));
+            object.insert("message".to_owned(), Value::from(record.args().to_string()));
+            object.extend(fields);
+            Value::Object(object).to_string()
+        }
+    }
+}
+
+/// Writes the records to stderr, so they don't mix with output of commands
+pub struct Logger {
+    format: Format,
+    level: LevelFilter,
+    grids: bool,
+}
+
+impl Logger {
+    /// Installs the logger for the whole process
+    pub fn init(format: Format, level: LevelFilter, grids: bool) {
+        let logger = Logger {
+            format,
+            level,
+            grids,
+        };
+        let max_level = if grids { LevelFilter::Trace } else { level };
+        log::set_boxed_logger(Box::new(logger)).expect("Logger was initialized already");
+        log::set_max_level(max_level);
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let target = metadata.target();
+        if target == GRIDS {
+            return self.grids;
+        }
+        let ignored = IGNORED
+            .iter()
+            .any(|ignored| target == *ignored || target.starts_with(&format!("{}::", ignored)));
+        !ignored && metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_millis());
+        let line = format(self.format, record, millis);
+        let _ = writeln!(io::stderr().lock(), "{}", line);
+    }
+
+    fn flush(&self) {
+        let _ = io::stderr().flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+
+    #[test]
+    fn formats_records_with_context() {
+        let fields = [("seed", 42u64)];
+        let arguments = format_args!("Generated board");
+        let record = Record::builder()
+            .args(arguments)
+            .level(Level::Info)
+            .target("galaxy")
+            .key_values(&fields)
+            .build();
+
+        let room = context(vec![("room", Value::from(3)), ("name", Value::from("a b"))]);
+        let json: Value = serde_json::from_str(&format(Format::Json, &record, 1_500)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "time": 1500,
+                "level": "INFO",
+                "target": "galaxy",
+                "message": "Generated board",
+                "room": 3,
+                "name": "a b",
+                "seed": 42,
+            })
+        );
+        assert_eq!(
+            format(Format::Text, &record, 3_723_004),
+            "01:02:03.004 [ INFO] galaxy: Generated board room=3 name=\"a b\" seed=42"
+        );
+
+        drop(room);
+        assert_eq!(
+            format(Format::Text, &record, 0),
+            "00:00:00.000 [ INFO] galaxy: Generated board seed=42"
+        );
+    }
+}
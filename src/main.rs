@@ -1,23 +1,580 @@
 extern crate log;
-extern crate simplelog;
 
-use simplelog::*;
+use clap::builder::RangedU64ValueParser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use galaxy_server_rust::{
+    admin, book, config, export, galaxy, gamegen, limits, loadtest, logger, metrics, network,
+    protocol, replay, tls, types,
+};
+use gamegen::{Difficulty, GenerationConfig};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
-mod gamegen;
-mod types;
+#[derive(Parser)]
+#[command(about = "Server for the galaxies puzzle")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    log: LogArgs,
+}
+
+/// Flags of the logger, which override the config file of the server
+#[derive(Args)]
+struct LogArgs {
+    /// Format of the log [default: text]
+    #[arg(long, global = true, value_enum)]
+    log_format: Option<logger::Format>,
+    /// [default: info]
+    #[arg(long, global = true)]
+    log_level: Option<log::LevelFilter>,
+    /// Logs the grids of the generator, which are huge, at trace level
+    #[arg(long, global = true)]
+    log_grids: bool,
+}
+
+impl LogArgs {
+    fn init(&self, config: config::LogConfig) {
+        logger::Logger::init(
+            self.log_format.unwrap_or(config.format),
+            self.log_level.unwrap_or(config.level),
+            self.log_grids || config.grids,
+        );
+    }
+}
+
+/// Parses widths and heights of boards the generator supports
+fn board_size() -> RangedU64ValueParser<usize> {
+    RangedU64ValueParser::new().range(1..=galaxy::MAX_BOARD_SIZE as u64)
+}
+
+/// Parses the sizes of fields of exported images
+fn field_size() -> RangedU64ValueParser<u32> {
+    RangedU64ValueParser::new().range(1..=u64::from(export::MAX_FIELD_SIZE))
+}
+
+/// Flags of the server, which override its config file
+#[derive(Args)]
+struct ServeArgs {
+    /// TOML file with the settings, which environment variables like
+    /// GALAXY__LIMITS__MOVES__BURST override
+    #[arg(long, env = "GALAXY_CONFIG")]
+    config: Option<PathBuf>,
+    /// [default: 0.0.0.0:9001]
+    #[arg(long)]
+    address: Option<String>,
+    /// Width of the first board [default: 10]
+    #[arg(long, value_parser = board_size())]
+    width: Option<usize>,
+    /// Height of the first board [default: 10]
+    #[arg(long, value_parser = board_size())]
+    height: Option<usize>,
+    /// [default: normal]
+    #[arg(long)]
+    difficulty: Option<Difficulty>,
+    /// Log file which keeps players and the current game across restarts
+    #[arg(long)]
+    storage: Option<PathBuf>,
+    /// PEM file with the certificate chain, which enables TLS. It is
+    /// reloaded when it changes.
+    #[arg(long, requires = "tls_key")]
+    tls_certificate: Option<PathBuf>,
+    /// PEM file with the private key of the certificate
+    #[arg(long, requires = "tls_certificate")]
+    tls_key: Option<PathBuf>,
+    /// Disables the rate limits and bans, for example for load tests
+    #[arg(long)]
+    unlimited: bool,
+    /// Address of the admin channel, which is disabled if missing. It
+    /// isn't encrypted, so it should only be reachable locally.
+    #[arg(long)]
+    admin_address: Option<String>,
+    /// Token admins have to send with every command
+    #[arg(long, env = "GALAXY_ADMIN_TOKEN", hide_env_values = true)]
+    admin_token: Option<String>,
+    /// Address of the Prometheus metrics at /metrics, which are
+    /// disabled if missing. It should only be reachable locally.
+    #[arg(long)]
+    metrics_address: Option<String>,
+    /// Seconds clients get to disconnect after SIGINT or SIGTERM, before
+    /// the server exits anyway [default: 10]
+    #[arg(long)]
+    shutdown_deadline: Option<u64>,
+}
+
+impl ServeArgs {
+    /// Loads the config file and applies the environment and the flags
+    fn configure(&self) -> Result<config::Config, String> {
+        let variables = std::env::vars();
+        let mut config = config::Config::load(self.config.as_deref(), variables)?;
+        let generation = &mut config.generation;
+        generation.width = self.width.unwrap_or(generation.width);
+        generation.height = self.height.unwrap_or(generation.height);
+        generation.difficulty = self.difficulty.unwrap_or(generation.difficulty);
+        if let Some(address) = &self.address {
+            config.address = address.clone();
+        }
+        if let Some(storage) = &self.storage {
+            config.storage = Some(storage.clone());
+        }
+        config.shutdown_deadline = self.shutdown_deadline.unwrap_or(config.shutdown_deadline);
+        if let (Some(certificate), Some(key)) = (&self.tls_certificate, &self.tls_key) {
+            config.tls = Some(tls::TlsConfig {
+                certificate: certificate.clone(),
+                key: key.clone(),
+            });
+        }
+        if self.admin_address.is_some() || self.admin_token.is_some() {
+            let admin = config.admin.take();
+            let (address, token) = match admin {
+                Some(admin) => (Some(admin.address), Some(admin.token)),
+                None => (None, None),
+            };
+            let address = self.admin_address.clone().or(address);
+            let token = self.admin_token.clone().or(token);
+            config.admin = match (address, token) {
+                (Some(address), Some(token)) => Some(config::AdminConfig { address, token }),
+                (Some(_), None) => return Err("admin.token: missing".to_owned()),
+                // A token alone doesn't enable the channel
+                (None, _) => None,
+            };
+        }
+        if let Some(address) = &self.metrics_address {
+            config.metrics = Some(config::MetricsConfig {
+                address: address.clone(),
+            });
+        }
+        if self.unlimited {
+            config.limits = limits::LimitConfig::unlimited();
+        }
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Runs the WebSocket server together with the HTTP API
+    Serve(ServeArgs),
+    /// Sends a command to the admin channel of a running server
+    Admin {
+        #[arg(long, default_value = "127.0.0.1:9002")]
+        address: String,
+        #[arg(long, env = "GALAXY_ADMIN_TOKEN", hide_env_values = true)]
+        token: String,
+        #[command(subcommand)]
+        command: AdminAction,
+    },
+    /// Generates a board and exports it as image
+    Export {
+        #[arg(long, default_value_t = 10, value_parser = board_size())]
+        width: usize,
+        #[arg(long, default_value_t = 10, value_parser = board_size())]
+        height: usize,
+        #[arg(long, default_value_t = Difficulty::Normal)]
+        difficulty: Difficulty,
+        /// Seed of the board, a random one is used if missing
+        #[arg(long)]
+        seed: Option<u64>,
+        /// PNG image which the black galaxies of a pictorial puzzle approximate
+        #[arg(long)]
+        image: Option<PathBuf>,
+        /// Also draw the galaxies of the solution
+        #[arg(long)]
+        solution: bool,
+        #[arg(long, value_enum, default_value_t = ImageFormat::Svg)]
+        format: ImageFormat,
+        /// Length of the side of a single field in pixels
+        #[arg(long, default_value_t = 40, value_parser = field_size())]
+        field_size: u32,
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Generates a batch of puzzles and writes them with their solutions to a
+    /// printable PDF
+    Book {
+        /// Amount of puzzles
+        #[arg(short = 'n', long, default_value_t = 10)]
+        count: usize,
+        #[arg(long, default_value_t = 10, value_parser = board_size())]
+        width: usize,
+        #[arg(long, default_value_t = 10, value_parser = board_size())]
+        height: usize,
+        #[arg(long, default_value_t = Difficulty::Normal)]
+        difficulty: Difficulty,
+        /// Seed of the first puzzle, a random one is used if missing
+        #[arg(long)]
+        seed: Option<u64>,
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Simulates many clients playing on a running server and reports how
+    /// fast it answers. The server has to run without rate limits.
+    Loadtest {
+        #[arg(long, default_value = "127.0.0.1:9001")]
+        address: String,
+        #[arg(long, default_value_t = 100)]
+        clients: usize,
+        /// Rooms the clients are spread over, 0 keeps them in the main room
+        #[arg(long, default_value_t = 10)]
+        rooms: usize,
+        /// Width and height of the boards of the rooms
+        #[arg(long, default_value_t = 10)]
+        size: usize,
+        /// Moves of every client
+        #[arg(long, default_value_t = 20)]
+        moves: usize,
+        /// Time between two moves of a client in milliseconds
+        #[arg(long, default_value_t = 500)]
+        interval: u64,
+    },
+    /// Shows a replay file, which clients receive from the server, move by
+    /// move
+    Replay {
+        input: PathBuf,
+        #[arg(long, value_enum, default_value_t = ReplayFormat::Text)]
+        format: ReplayFormat,
+        /// Time between two frames of the text animation in milliseconds
+        #[arg(long, default_value_t = 500)]
+        delay: u64,
+        /// Length of the side of a single field in pixels
+        #[arg(long, default_value_t = 40, value_parser = field_size())]
+        field_size: u32,
+        /// Directory for the SVG files, one per move
+        #[arg(short, long, required_if_eq("format", "svg"))]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum AdminAction {
+    /// Lists the open rooms
+    Rooms,
+    /// Lists the players who are online
+    Players,
+    /// Disconnects all clients of the player
+    Kick {
+        player: types::Id,
+    },
+    /// Kicks the player and refuses its logins
+    Ban {
+        player: types::Id,
+        /// Length of the ban, it lasts until the player is unbanned if
+        /// missing
+        #[arg(long)]
+        minutes: Option<u64>,
+    },
+    Unban {
+        player: types::Id,
+    },
+    /// Closes a room, its clients stay connected
+    Close {
+        room: galaxy::RoomId,
+    },
+    /// Sends a message to all clients
+    Announce {
+        message: String,
+    },
+    /// Writes the current state to the log
+    Save,
+}
+
+impl From<AdminAction> for protocol::AdminCommand {
+    fn from(action: AdminAction) -> Self {
+        use protocol::AdminCommand::*;
+        match action {
+            AdminAction::Rooms => Rooms,
+            AdminAction::Players => Players,
+            AdminAction::Kick { player } => Kick { player },
+            AdminAction::Ban { player, minutes } => Ban { player, minutes },
+            AdminAction::Unban { player } => Unban { player },
+            AdminAction::Close { room } => CloseRoom { room },
+            AdminAction::Announce { message } => Announce { message },
+            AdminAction::Save => Save,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ReplayFormat {
+    /// Animation in the terminal
+    Text,
+    /// Sequence of SVG files
+    Svg,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ImageFormat {
+    Svg,
+    Png,
+}
+
+/// Reads the replay from the file and checks it
+///
+/// @return Replay, an error if the file doesn't contain a valid replay
+fn read_replay(path: &Path) -> Result<replay::Replay, String> {
+    let content =
+        fs::read_to_string(path).map_err(|error| format!("Reading replay failed: {}", error))?;
+    let replay: replay::Replay = serde_json::from_str(&content)
+        .map_err(|error| format!("Parsing replay failed: {}", error))?;
+    replay
+        .check()
+        .map_err(|error| format!("Invalid replay: {}", error))?;
+    Ok(replay)
+}
+
+/// Runs the server until it is asked to terminate
+fn serve(config: config::Config) {
+    let generation = config.generation;
+    let first = GenerationConfig {
+        width: generation.width,
+        height: generation.height,
+        difficulty: generation.difficulty,
+        seed: generation.seed.unwrap_or_else(rand::random),
+    };
+    let galaxy = match &config.storage {
+        Some(path) => galaxy::Galaxy::open(&first, path),
+        None => galaxy::Galaxy::new(&first),
+    };
+    let galaxy = match galaxy {
+        Ok(galaxy) => galaxy,
+        Err(error) => {
+            eprintln!("Starting the server failed: {}", error);
+            std::process::exit(1);
+        }
+    };
+    let galaxy = Arc::new(galaxy.max_board_size(generation.max_size));
+    galaxy.schedule_daily();
+    let mut network = network::Network::new(Arc::clone(&galaxy)).limits(config.limits);
+    if let Some(tls) = config.tls.clone() {
+        // The files were checked with the config, but may have changed since
+        let certificates = match tls::Certificates::load(tls) {
+            Ok(certificates) => Arc::new(certificates),
+            Err(error) => {
+                eprintln!("Loading the certificate failed: {}", error);
+                std::process::exit(1);
+            }
+        };
+        Arc::clone(&certificates).watch();
+        network = network.tls(certificates.acceptor());
+    }
+    let runtime = tokio::runtime::Runtime::new().expect("Starting runtime failed");
+    runtime
+        .block_on(async {
+            if let Some(admin) = config.admin {
+                let listener = tokio::net::TcpListener::bind(&admin.address).await?;
+                log::info!("Admin channel listening on {}", admin.address);
+                tokio::spawn(admin::serve(Arc::clone(&galaxy), listener, admin.token));
+            }
+            if let Some(metrics) = config.metrics {
+                let listener = tokio::net::TcpListener::bind(&metrics.address).await?;
+                log::info!("Metrics listening on {}", metrics.address);
+                tokio::spawn(metrics::serve(galaxy, listener));
+            }
+            let deadline = Duration::from_secs(config.shutdown_deadline);
+            network.listen(&config.address, deadline).await
+        })
+        .expect("Running server failed");
+    // Boards which are still generated aren't needed anymore
+    runtime.shutdown_timeout(Duration::from_secs(1));
+}
 
 fn main() {
-    TermLogger::init(
-        LevelFilter::Trace,
-        Config::default(),
-        TerminalMode::Mixed,
-        ColorChoice::Auto,
-    )
-    .unwrap();
-
-    let dots = gamegen::generate_dots(10, 10);
-    println!("Dots:");
-    for dot in dots {
-        println!("x: {}, y: {}", dot.0, dot.1);
+    let cli = Cli::parse();
+
+    let config = match &cli.command {
+        Some(Command::Serve(args)) => match args.configure() {
+            Ok(config) => Some(config),
+            Err(error) => {
+                eprintln!("Invalid config: {}", error);
+                std::process::exit(2);
+            }
+        },
+        _ => None,
+    };
+    let log = config
+        .as_ref()
+        .map_or_else(Default::default, |config| config.log);
+    cli.log.init(log);
+
+    match cli.command {
+        Some(Command::Serve(_)) => serve(config.expect("Config of the server is missing")),
+        Some(Command::Export {
+            width,
+            height,
+            difficulty,
+            seed,
+            image,
+            solution,
+            format,
+            field_size,
+            output,
+        }) => {
+            let config = GenerationConfig {
+                width,
+                height,
+                difficulty,
+                seed: seed.unwrap_or_else(rand::random),
+            };
+            let generated = match image {
+                Some(image) => {
+                    let data = fs::read(&image).expect("Reading image failed");
+                    let bitmap =
+                        export::bitmap_from_png(&data, width, height).expect("Decoding PNG failed");
+                    gamegen::generate_picture(&config, &bitmap)
+                }
+                None => gamegen::try_generate(&config),
+            };
+            let board = match generated {
+                Ok(board) => board,
+                Err(reason) => {
+                    eprintln!("Generating the board failed: {}", reason);
+                    std::process::exit(1);
+                }
+            };
+            let picture = if solution {
+                export::Picture::solution(&board)
+            } else {
+                export::Picture::puzzle(&board)
+            };
+            let style = export::Style {
+                field_size,
+                ..export::Style::default()
+            };
+            let content = match format {
+                ImageFormat::Svg => export::svg(&picture, &style).map(String::into_bytes),
+                ImageFormat::Png => export::png(&picture, &style).map_err(|error| match error {
+                    png::EncodingError::LimitsExceeded => "The image is too large",
+                    _ => "Encoding PNG failed",
+                }),
+            };
+            let content = match content {
+                Ok(content) => content,
+                Err(reason) => {
+                    eprintln!("Exporting the board failed: {}", reason);
+                    std::process::exit(1);
+                }
+            };
+            fs::write(&output, content).expect("Writing image failed");
+        }
+        Some(Command::Book {
+            count,
+            width,
+            height,
+            difficulty,
+            seed,
+            output,
+        }) => {
+            let generated = book::generate(&book::BookConfig {
+                count,
+                width,
+                height,
+                difficulty,
+                seed: seed.unwrap_or_else(rand::random),
+            });
+            let content = match generated {
+                Ok(content) => content,
+                Err(error) => {
+                    eprintln!("{}", error);
+                    std::process::exit(1);
+                }
+            };
+            fs::write(&output, content).expect("Writing book failed");
+        }
+        Some(Command::Loadtest {
+            address,
+            clients,
+            rooms,
+            size,
+            moves,
+            interval,
+        }) => {
+            let config = loadtest::LoadTestConfig {
+                address,
+                clients,
+                rooms,
+                size,
+                moves,
+                interval: Duration::from_millis(interval),
+            };
+            let runtime = tokio::runtime::Runtime::new().expect("Starting runtime failed");
+            print!("{}", runtime.block_on(loadtest::run(config)));
+        }
+        Some(Command::Admin {
+            address,
+            token,
+            command,
+        }) => {
+            let runtime = tokio::runtime::Runtime::new().expect("Starting runtime failed");
+            let request = admin::request(&address, &token, command.into());
+            let response = runtime
+                .block_on(request)
+                .expect("Contacting the server failed");
+            print!("{}", response);
+            if let protocol::AdminResponse::Error { .. } = response {
+                std::process::exit(1);
+            }
+        }
+        Some(Command::Replay {
+            input,
+            format,
+            delay,
+            field_size,
+            output,
+        }) => {
+            let replay = match read_replay(&input) {
+                Ok(replay) => replay,
+                Err(error) => {
+                    eprintln!("{}", error);
+                    std::process::exit(1);
+                }
+            };
+            match (format, output) {
+                (ReplayFormat::Svg, Some(output)) => {
+                    fs::create_dir_all(&output).expect("Creating output directory failed");
+                    let style = export::Style {
+                        field_size,
+                        ..export::Style::default()
+                    };
+                    for moves in 0..=replay.moves.len() {
+                        let path = output.join(format!("move-{:04}.svg", moves));
+                        let frame = match replay.svg(moves, &style) {
+                            Ok(frame) => frame,
+                            Err(reason) => {
+                                eprintln!("Drawing move {} failed: {}", moves, reason);
+                                std::process::exit(1);
+                            }
+                        };
+                        fs::write(path, frame).expect("Writing frame failed");
+                    }
+                }
+                _ => {
+                    for moves in 0..=replay.moves.len() {
+                        if delay > 0 {
+                            // Clear the terminal to animate the frames
+                            print!("\x1b[2J\x1b[H");
+                        }
+                        println!("{}", replay.text(moves));
+                        thread::sleep(Duration::from_millis(delay));
+                    }
+                }
+            }
+        }
+        None => {
+            let dots = match gamegen::generate_dots(10, 10) {
+                Ok(dots) => dots,
+                Err(reason) => {
+                    eprintln!("Generating the dots failed: {}", reason);
+                    std::process::exit(1);
+                }
+            };
+            println!("Dots:");
+            for dot in dots {
+                println!("x: {}, y: {}", dot.0, dot.1);
+            }
+        }
     }
 }
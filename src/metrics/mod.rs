@@ -0,0 +1,240 @@
+use crate::galaxy::Galaxy;
+use crate::http::{self, Incoming, Response};
+use log::{debug, warn};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::{task, time};
+
+/// Upper bounds of the buckets of generation latencies in seconds
+const GENERATION_BUCKETS: [f64; 10] = [0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0];
+
+/// Scrapes which take longer are dropped
+const SCRAPE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Latencies of generating boards of one size
+#[derive(Clone, Debug, Default)]
+struct Histogram {
+    /// Observations in every bucket, not cumulative
+    buckets: [u64; GENERATION_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if let Some(bucket) = GENERATION_BUCKETS.iter().position(|&bound| value <= bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+/// Counters of the whole process, which are exported in the Prometheus text
+/// format. Gauges are read from the galaxy when the metrics are scraped.
+struct Metrics {
+    moves: AtomicU64,
+    solves: AtomicU64,
+    rejected_solves: AtomicU64,
+    generation_retries: AtomicU64,
+    generation_failures: AtomicU64,
+    /// Generation latencies by width and height
+    generations: Mutex<BTreeMap<(usize, usize), Histogram>>,
+}
+
+static METRICS: Metrics = Metrics {
+    moves: AtomicU64::new(0),
+    solves: AtomicU64::new(0),
+    rejected_solves: AtomicU64::new(0),
+    generation_retries: AtomicU64::new(0),
+    generation_failures: AtomicU64::new(0),
+    generations: Mutex::new(BTreeMap::new()),
+};
+
+/// Counts a change a player made
+pub fn moved() {
+    METRICS.moves.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Counts a solve of a player, which the leaderboard accepted or rejected
+pub fn solved(accepted: bool) {
+    let counter = if accepted {
+        &METRICS.solves
+    } else {
+        &METRICS.rejected_solves
+    };
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Records how long generating a board of the size took
+pub fn generated(width: usize, height: usize, duration: Duration) {
+    let mut generations = METRICS.generations.lock().unwrap();
+    let histogram = generations.entry((width, height)).or_default();
+    histogram.observe(duration.as_secs_f64());
+}
+
+/// Counts an attempt to generate a board again after the generator failed
+pub fn generation_retried() {
+    METRICS.generation_retries.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Counts a board which couldn't be generated at all
+pub fn generation_failed() {
+    METRICS.generation_failures.fetch_add(1, Ordering::Relaxed);
+}
+
+fn write_metric(output: &mut String, name: &str, kind: &str, help: &str, value: u64) {
+    writeln!(output, "# HELP {} {}", name, help).unwrap();
+    writeln!(output, "# TYPE {} {}", name, kind).unwrap();
+    writeln!(output, "{} {}", name, value).unwrap();
+}
+
+/// @return All metrics in the Prometheus text format
+pub fn render(galaxy: &Galaxy) -> String {
+    let census = galaxy.census();
+    let counter = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+    let mut output = String::new();
+    let gauges = [
+        (
+            "galaxy_connections",
+            "Open WebSocket connections",
+            census.clients,
+        ),
+        (
+            "galaxy_players",
+            "Players who are logged in",
+            census.players,
+        ),
+        ("galaxy_rooms", "Open rooms", census.rooms),
+    ];
+    for &(name, help, value) in &gauges {
+        write_metric(&mut output, name, "gauge", help, value as u64);
+    }
+    let counters = [
+        ("galaxy_moves_total", "Changes players made", &METRICS.moves),
+        (
+            "galaxy_solves_total",
+            "Solves on the leaderboard",
+            &METRICS.solves,
+        ),
+        (
+            "galaxy_rejected_solves_total",
+            "Solves the leaderboard rejected as implausible",
+            &METRICS.rejected_solves,
+        ),
+        (
+            "galaxy_generation_retries_total",
+            "Generations which were retried with another seed",
+            &METRICS.generation_retries,
+        ),
+        (
+            "galaxy_generation_failures_total",
+            "Boards which couldn't be generated",
+            &METRICS.generation_failures,
+        ),
+    ];
+    for &(name, help, value) in &counters {
+        write_metric(&mut output, name, "counter", help, counter(value));
+    }
+
+    let name = "galaxy_generation_seconds";
+    writeln!(output, "# HELP {} Time to generate a board by size", name).unwrap();
+    writeln!(output, "# TYPE {} histogram", name).unwrap();
+    let generations = METRICS.generations.lock().unwrap();
+    for (&(width, height), histogram) in generations.iter() {
+        let size = format!("{}x{}", width, height);
+        let mut cumulative = 0;
+        for (bound, count) in GENERATION_BUCKETS.iter().zip(&histogram.buckets) {
+            cumulative += count;
+            writeln!(
+                output,
+                "{}_bucket{{size=\"{}\",le=\"{}\"}} {}",
+                name, size, bound, cumulative
+            )
+            .unwrap();
+        }
+        writeln!(
+            output,
+            "{}_bucket{{size=\"{}\",le=\"+Inf\"}} {}",
+            name, size, histogram.count
+        )
+        .unwrap();
+        writeln!(
+            output,
+            "{}_sum{{size=\"{}\"}} {}",
+            name, size, histogram.sum
+        )
+        .unwrap();
+        writeln!(
+            output,
+            "{}_count{{size=\"{}\"}} {}",
+            name, size, histogram.count
+        )
+        .unwrap();
+    }
+    output
+}
+
+/// Answers scrapes of `/metrics` on the listener, which should only be
+/// reachable locally. Runs until the runtime shuts down.
+pub async fn serve(galaxy: Arc<Galaxy>, listener: TcpListener) {
+    loop {
+        let mut stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(error) => {
+                warn!("Accepting a scrape failed: {}", error);
+                time::sleep(SCRAPE_TIMEOUT / 100).await;
+                continue;
+            }
+        };
+        let galaxy = Arc::clone(&galaxy);
+        task::spawn(async move {
+            let request = time::timeout(SCRAPE_TIMEOUT, http::read_request(&mut stream)).await;
+            let response = match request {
+                Ok(Ok(Incoming::Http(request))) if request.path == "/metrics" => {
+                    let body = render(&galaxy);
+                    Response::metrics(body)
+                }
+                Ok(Ok(Incoming::Invalid(response))) => response,
+                Ok(Ok(_)) => Response::text(404, "Not found\n".to_owned()),
+                Ok(Err(error)) => return debug!("Reading a scrape failed: {}", error),
+                Err(_) => return debug!("Reading a scrape timed out"),
+            };
+            http::send(stream, response).await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gamegen::{self, Difficulty, GenerationConfig};
+
+    #[test]
+    fn renders_counters_and_histograms() {
+        let config = GenerationConfig {
+            width: 3,
+            height: 2,
+            difficulty: Difficulty::Easy,
+            seed: 4,
+        };
+        let galaxy = Galaxy::new(&config).unwrap();
+        gamegen::generate_reliably(&config).unwrap();
+        moved();
+
+        let output = render(&galaxy);
+        assert!(output.contains("# TYPE galaxy_moves_total counter\n"));
+        assert!(output.contains("galaxy_rooms 1\n"));
+        assert!(output.contains("galaxy_connections 0\n"));
+        assert!(output.contains("galaxy_generation_seconds_bucket{size=\"3x2\",le=\"+Inf\"}"));
+        let moves = output
+            .lines()
+            .find_map(|line| line.strip_prefix("galaxy_moves_total "))
+            .unwrap();
+        assert!(moves.parse::<u64>().unwrap() >= 1);
+    }
+}
@@ -0,0 +1,485 @@
+use crate::galaxy::{self, ClientId, Galaxy, Inbox, Outbox, RoomId, OUTBOX_CAPACITY};
+use crate::http::{self, Incoming, Prefixed, Response};
+use crate::limits::{Action, LimitConfig, Limits, Verdict};
+use crate::protocol::{ClientMessage, ServerMessage};
+use crate::types::Id;
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::future::{self, Future};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::task::{self, JoinError};
+use tokio::time;
+use tokio_rustls::TlsAcceptor;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, WebSocketConfig};
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::WebSocketStream;
+
+/// Room tasks end after this time without messages
+const ROOM_IDLE_TIME: Duration = Duration::from_secs(60);
+
+/// Clients which take longer to receive a message are disconnected
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Connections which don't finish the TLS and WebSocket handshakes or
+/// their HTTP request within this time are dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest WebSocket message clients may send, longer ones get them banned
+/// eventually
+const MAX_MESSAGE_SIZE: usize = 16 * 1024;
+
+/// Time to wait before accepting connections again after it failed, for
+/// example because there are too many open files
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Time between checks whether all clients disconnected during a shutdown
+const SHUTDOWN_POLL: Duration = Duration::from_millis(50);
+
+/// Messages of clients to a room task
+type Queue = UnboundedSender<(ClientId, ClientMessage)>;
+
+/// Result of socket operations, the error is boxed because of its size
+type SocketResult = Result<(), Box<tungstenite::Error>>;
+
+/// Manages the WebSocket connections and answers HTTP requests on the same
+/// port. Every connection reads messages and
+/// writes the messages of its outbox concurrently. Messages of clients in a
+/// room are handled one after another by a task of the room, so rooms
+/// progress independently and a client only waits for its own room.
+#[derive(Clone)]
+pub struct Network {
+    galaxy: Arc<Galaxy>,
+    rooms: Arc<Mutex<HashMap<RoomId, Queue>>>,
+    /// Terminates TLS if there is one
+    tls: Option<TlsAcceptor>,
+    limits: Arc<Mutex<Limits>>,
+}
+
+/// Forwards the messages of the inbox to the client until the client lags
+/// behind, gets kicked or the connection fails
+async fn write<S>(
+    sink: &mut SplitSink<WebSocketStream<S>, Message>,
+    mut inbox: Inbox,
+) -> SocketResult
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    while let Some(message) = inbox.recv().await {
+        if inbox.lagging() {
+            // The client missed messages, so it has to reconnect and resync
+            let frame = CloseFrame {
+                code: CloseCode::Again,
+                reason: "Client is too slow".into(),
+            };
+            let _ = time::timeout(WRITE_TIMEOUT, sink.send(Message::Close(Some(frame)))).await;
+            return Ok(());
+        }
+        let last = matches!(
+            message,
+            ServerMessage::Kicked { .. } | ServerMessage::ShuttingDown
+        );
+        let text = serde_json::to_string(&message).expect("Serializing message failed");
+        match time::timeout(WRITE_TIMEOUT, sink.send(Message::Text(text))).await {
+            Ok(result) => result.map_err(Box::new)?,
+            Err(_) => return Err(Box::new(tungstenite::Error::ConnectionClosed)),
+        }
+        if last {
+            let _ = time::timeout(WRITE_TIMEOUT, sink.send(Message::Close(None))).await;
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+/// Handles the message, which waits for the state and may generate boards.
+/// Hints are searched in a task of their own, so the room goes on meanwhile.
+async fn handle(
+    galaxy: Arc<Galaxy>,
+    client: ClientId,
+    message: ClientMessage,
+) -> Result<(), JoinError> {
+    let hint = task::spawn_blocking(move || galaxy.handle(client, message)).await?;
+    if let Some(hint) = hint {
+        task::spawn_blocking(move || hint.run());
+    }
+    Ok(())
+}
+
+/// Handles the messages of a room one after another. The task ends when it
+/// was idle for a while.
+async fn serve_room(
+    galaxy: Arc<Galaxy>,
+    rooms: Arc<Mutex<HashMap<RoomId, Queue>>>,
+    room: RoomId,
+    mut queue: UnboundedReceiver<(ClientId, ClientMessage)>,
+) {
+    loop {
+        match time::timeout(ROOM_IDLE_TIME, queue.recv()).await {
+            Ok(Some((client, message))) => {
+                if let Err(error) = handle(Arc::clone(&galaxy), client, message).await {
+                    warn!("Handling a message in room {} failed: {}", room, error);
+                }
+            }
+            Ok(None) => return,
+            Err(_) => {
+                // Messages are only queued while the lock is held, so none
+                // get lost
+                let mut rooms = rooms.lock().unwrap();
+                if queue.is_empty() {
+                    rooms.remove(&room);
+                    debug!("Task of room {} ended", room);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl Network {
+    pub fn new(galaxy: Arc<Galaxy>) -> Self {
+        Network {
+            galaxy,
+            rooms: Arc::default(),
+            tls: None,
+            limits: Arc::new(Mutex::new(Limits::new(LimitConfig::default()))),
+        }
+    }
+
+    /// Serves clients over TLS with the acceptor
+    pub fn tls(mut self, acceptor: TlsAcceptor) -> Self {
+        self.tls = Some(acceptor);
+        self
+    }
+
+    /// Replaces the default rate limits
+    pub fn limits(mut self, config: LimitConfig) -> Self {
+        self.limits = Arc::new(Mutex::new(Limits::new(config)));
+        self
+    }
+
+    fn check(&self, address: IpAddr, player: Option<Id>, action: Action) -> Verdict {
+        let mut limits = self.limits.lock().unwrap();
+        limits.check(address, player, action, Instant::now())
+    }
+
+    /// Queues the message for the task of the room, which is started if
+    /// there is none
+    fn queue(&self, room: RoomId, client: ClientId, message: ClientMessage) {
+        let mut rooms = self.rooms.lock().unwrap();
+        let queue = rooms.entry(room).or_insert_with(|| {
+            let (queue, receiver) = mpsc::unbounded_channel();
+            let galaxy = Arc::clone(&self.galaxy);
+            task::spawn(serve_room(galaxy, Arc::clone(&self.rooms), room, receiver));
+            queue
+        });
+        // The task only ends with an empty queue while the lock is held
+        let _ = queue.send((client, message));
+    }
+
+    /// Passes the message to the room of the client. Messages of clients
+    /// outside of rooms are handled right away.
+    async fn dispatch(&self, client: ClientId, message: ClientMessage) {
+        match self.galaxy.room_of(client) {
+            Some(room) => self.queue(room, client, message),
+            None => {
+                let galaxy = Arc::clone(&self.galaxy);
+                if let Err(error) = handle(galaxy, client, message).await {
+                    warn!("Handling a message of client {} failed: {}", client, error);
+                }
+            }
+        }
+    }
+
+    /// Reads messages of the client until the connection is closed or the
+    /// address gets banned
+    async fn read<S>(
+        &self,
+        client: ClientId,
+        address: IpAddr,
+        stream: &mut SplitStream<WebSocketStream<S>>,
+        outbox: &Outbox,
+    ) -> SocketResult
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        while let Some(frame) = stream.next().await {
+            let frame = match frame {
+                Err(tungstenite::Error::Capacity(error)) => {
+                    let reason = format!("Client {} sent too much: {}", client, error);
+                    self.limits
+                        .lock()
+                        .unwrap()
+                        .strike(address, &reason, Instant::now());
+                    return Err(Box::new(tungstenite::Error::Capacity(error)));
+                }
+                frame => frame.map_err(Box::new)?,
+            };
+            let parsed = match frame {
+                Message::Text(text) => Some(serde_json::from_str::<ClientMessage>(&text)),
+                Message::Close(_) => return Ok(()),
+                _ => None,
+            };
+
+            // Every frame counts, so pings can't flood the server either
+            let player = self.galaxy.player_of(client);
+            let verdict = match &parsed {
+                Some(Ok(message)) => {
+                    let mut limits = self.limits.lock().unwrap();
+                    limits.check_message(address, player, message, Instant::now())
+                }
+                _ => self.check(address, player, Action::Message),
+            };
+            match verdict {
+                Verdict::Allowed => (),
+                Verdict::Limited => {
+                    outbox.send(ServerMessage::Error {
+                        message: "Too many requests".to_owned(),
+                    });
+                    continue;
+                }
+                Verdict::Banned => return Ok(()),
+            }
+
+            match parsed {
+                Some(Ok(message)) => {
+                    debug!("Client {} sent {:?}", client, message);
+                    self.dispatch(client, message).await;
+                }
+                Some(Err(error)) => outbox.send(ServerMessage::Error {
+                    message: format!("Invalid message: {}", error),
+                }),
+                None => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Answers an HTTP request unless the address exceeds its limits
+    async fn answer_request<S>(&self, stream: S, address: IpAddr, request: http::Request)
+    where
+        S: AsyncWrite + Unpin,
+    {
+        let action = if request.generates() {
+            Action::Generate
+        } else {
+            Action::Message
+        };
+        let response = match self.check(address, None, action) {
+            Verdict::Allowed => {
+                let galaxy = Arc::clone(&self.galaxy);
+                task::spawn_blocking(move || http::respond(&galaxy, &request))
+                    .await
+                    .unwrap_or_else(|error| {
+                        warn!("Answering a request failed: {}", error);
+                        Response::text(500, String::new())
+                    })
+            }
+            Verdict::Limited => http::too_many_requests(),
+            Verdict::Banned => return,
+        };
+        http::send(stream, response).await
+    }
+
+    async fn handle_connection(self, stream: TcpStream, address: SocketAddr) {
+        let tls = match &self.tls {
+            Some(acceptor) => acceptor.clone(),
+            None => return self.handle_socket(stream, address).await,
+        };
+        match time::timeout(HANDSHAKE_TIMEOUT, tls.accept(stream)).await {
+            Ok(Ok(stream)) => self.handle_socket(stream, address).await,
+            Ok(Err(error)) => warn!("TLS handshake with {} failed: {}", address, error),
+            Err(_) => warn!("TLS handshake with {} timed out", address),
+        }
+    }
+
+    /// Serves the connection as WebSocket if the client asks for one and
+    /// answers its HTTP request otherwise
+    async fn handle_socket<S>(self, mut stream: S, address: SocketAddr)
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let head = match time::timeout(HANDSHAKE_TIMEOUT, http::read_request(&mut stream)).await {
+            Ok(Ok(Incoming::WebSocket(head))) => head,
+            Ok(Ok(Incoming::Http(request))) => {
+                debug!("{} {} from {}", request.method, request.path, address);
+                return self.answer_request(stream, address.ip(), request).await;
+            }
+            Ok(Ok(Incoming::Invalid(response))) => return http::send(stream, response).await,
+            Ok(Err(error)) => {
+                debug!("Reading a request from {} failed: {}", address, error);
+                return;
+            }
+            Err(_) => {
+                debug!("Request from {} timed out", address);
+                return;
+            }
+        };
+        let config = WebSocketConfig {
+            max_message_size: Some(MAX_MESSAGE_SIZE),
+            max_frame_size: Some(MAX_MESSAGE_SIZE),
+            ..WebSocketConfig::default()
+        };
+        let stream = Prefixed::new(head, stream);
+        let handshake = tokio_tungstenite::accept_async_with_config(stream, Some(config));
+        let socket = match time::timeout(HANDSHAKE_TIMEOUT, handshake).await {
+            Ok(Ok(socket)) => socket,
+            Ok(Err(error)) => {
+                warn!("Handshake with {} failed: {}", address, error);
+                return;
+            }
+            Err(_) => {
+                warn!("Handshake with {} timed out", address);
+                return;
+            }
+        };
+        let (mut sink, mut stream) = socket.split();
+
+        let (outbox, inbox) = galaxy::outbox(OUTBOX_CAPACITY);
+        let client = self.galaxy.connect(outbox.clone());
+        info!("Client {} connected from {}", client, address);
+
+        let result = tokio::select! {
+            result = self.read(client, address.ip(), &mut stream, &outbox) => result,
+            result = write(&mut sink, inbox) => result,
+        };
+        if let Err(error) = result {
+            debug!("Connection of client {} failed: {}", client, error);
+        }
+
+        self.galaxy.disconnect(client);
+        info!("Client {} disconnected", client);
+    }
+
+    /// Serves every client of the listener in its own task. Runs until the
+    /// runtime shuts down.
+    pub async fn serve(self, listener: TcpListener) {
+        loop {
+            match listener.accept().await {
+                Ok((stream, address)) => match self.check(address.ip(), None, Action::Connect) {
+                    Verdict::Allowed => {
+                        task::spawn(self.clone().handle_connection(stream, address));
+                    }
+                    _ => debug!("Refused connection from {}", address),
+                },
+                Err(error) => {
+                    warn!("Accepting a connection failed: {}", error);
+                    time::sleep(ACCEPT_BACKOFF).await;
+                }
+            }
+        }
+    }
+
+    /// Serves connections until the signal completes. Then the clients are
+    /// told about the shutdown and the state is saved. The connections get
+    /// until the deadline to close.
+    pub async fn serve_until<F>(self, listener: TcpListener, signal: F, deadline: Duration)
+    where
+        F: Future<Output = ()>,
+    {
+        let galaxy = Arc::clone(&self.galaxy);
+        tokio::select! {
+            () = self.serve(listener) => (),
+            () = signal => (),
+        }
+        info!("Shutting down");
+
+        let closed = async {
+            let saving = Arc::clone(&galaxy);
+            match task::spawn_blocking(move || saving.shut_down()).await {
+                Ok(Ok(())) => info!("Saved the state"),
+                Ok(Err(error)) => warn!("Saving the state failed: {}", error),
+                Err(error) => warn!("Saving the state failed: {}", error),
+            }
+            while galaxy.census().clients > 0 {
+                time::sleep(SHUTDOWN_POLL).await;
+            }
+        };
+        if time::timeout(deadline, closed).await.is_err() {
+            let clients = galaxy.census().clients;
+            warn!("{} clients were still connected at the deadline", clients);
+        }
+    }
+
+    /// Accepts WebSocket connections on the address until the server is
+    /// asked to terminate, see serve_until
+    pub async fn listen(self, address: &str, deadline: Duration) -> io::Result<()> {
+        let listener = TcpListener::bind(address).await?;
+        info!("Listening on {}", address);
+        self.serve_until(listener, terminated(), deadline).await;
+        Ok(())
+    }
+}
+
+/// Completes when the process receives SIGINT or SIGTERM
+async fn terminated() {
+    let interrupted = async {
+        if let Err(error) = tokio::signal::ctrl_c().await {
+            warn!("Waiting for SIGINT failed: {}", error);
+            future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminated = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(error) => {
+                warn!("Waiting for SIGTERM failed: {}", error);
+                future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminated = future::pending::<()>();
+    tokio::select! {
+        () = interrupted => (),
+        () = terminated => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::oneshot;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn shutdown_notifies_clients_and_stops_accepting() {
+        let galaxy = Galaxy::for_tests();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let url = format!("ws://{}/", address);
+        let (stop, signal) = oneshot::channel();
+        let signal = async {
+            signal.await.unwrap();
+        };
+        let network = Network::new(Arc::new(galaxy));
+        let server = tokio::spawn(network.serve_until(listener, signal, Duration::from_secs(5)));
+
+        let (mut socket, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        let rooms = serde_json::to_string(&ClientMessage::Rooms).unwrap();
+        socket.send(Message::Text(rooms)).await.unwrap();
+        assert!(matches!(socket.next().await, Some(Ok(Message::Text(_)))));
+
+        stop.send(()).unwrap();
+        let text = socket.next().await.unwrap().unwrap().into_text().unwrap();
+        let message: ServerMessage = serde_json::from_str(&text).unwrap();
+        assert_eq!(message, ServerMessage::ShuttingDown);
+        while let Some(Ok(_)) = socket.next().await {}
+        time::timeout(Duration::from_secs(5), server)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(TcpStream::connect(address).await.is_err());
+    }
+}
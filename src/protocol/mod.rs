@@ -0,0 +1,329 @@
+use crate::galaxy::{Mode, RoomId, Settings};
+use crate::gamegen::Difficulty;
+use crate::leaderboard::Period;
+use crate::replay::Replay;
+use crate::secret::Secret;
+use crate::types::{Contribution, Dot, Field, GameChange, Id, Position};
+use serde::{Deserialize, Serialize};
+
+/// Messages clients send to the server as JSON text
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Registers a new player or logs in an existing one with the same name
+    Login { name: String, passphrase: Secret },
+    /// Asks for the open rooms
+    Rooms,
+    /// Opens a room with a new board and joins it
+    CreateRoom {
+        name: String,
+        width: usize,
+        height: usize,
+        #[serde(default)]
+        difficulty: Difficulty,
+        seed: Option<u64>,
+        #[serde(default)]
+        mode: Mode,
+        #[serde(default)]
+        settings: Settings,
+        password: Option<Secret>,
+        /// Most players at the same time, unlimited if missing
+        capacity: Option<usize>,
+    },
+    /// Leaves the current room and plays in the given one
+    Join {
+        room: RoomId,
+        password: Option<Secret>,
+    },
+    /// Leaves the current room without joining another one
+    Leave,
+    /// Watches the game of the room without playing, in race mode the game
+    /// of the player
+    Spectate {
+        #[serde(default)]
+        room: RoomId,
+        player: Option<Id>,
+        password: Option<Secret>,
+    },
+    /// Replaces the game of the room with a newly generated board
+    NewGame {
+        width: usize,
+        height: usize,
+        #[serde(default)]
+        difficulty: Difficulty,
+        seed: Option<u64>,
+        #[serde(default)]
+        mode: Mode,
+        #[serde(default)]
+        settings: Settings,
+    },
+    /// Assigns a field to a dot or clears it without dot
+    Assign { field: Position, dot: Option<Id> },
+    /// Asks for the next logical step
+    Hint,
+    /// Reverts the last own change
+    Undo,
+    /// Applies the last undone change again
+    Redo,
+    /// Asks how far the game is and who contributed
+    Progress,
+    /// Asks for the state after the given amount of changes, the current
+    /// state if missing
+    Snapshot { changes: Option<usize> },
+    /// Asks for all timestamped moves of the current game
+    Replay,
+    /// Asks for the changes a reconnecting client missed since the last
+    /// sequence number it saw of the game of the puzzle
+    Resync {
+        puzzle: u64,
+        game: u64,
+        sequence: usize,
+    },
+    /// Replaces the current game with today's puzzle of the difficulty
+    Daily {
+        #[serde(default)]
+        difficulty: Difficulty,
+    },
+    /// Asks for the ranking of the period, which is limited to a single
+    /// puzzle if there is one
+    Leaderboard { period: Period, puzzle: Option<u64> },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HintReason {
+    UnderDot,
+    Mirror,
+    OnlyCandidate,
+    Mistake,
+    Reveal,
+}
+
+/// Entry of the room listing
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomInfo {
+    pub room: RoomId,
+    pub name: String,
+    pub mode: Mode,
+    pub width: usize,
+    pub height: usize,
+    pub players: usize,
+    pub capacity: Option<usize>,
+    /// Whether joining needs a password
+    pub locked: bool,
+    pub spectators: bool,
+}
+
+/// Entry of a leaderboard, durations are in milliseconds
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rank {
+    pub player: Id,
+    pub name: String,
+    pub solves: usize,
+    pub duration: u64,
+    pub moves: usize,
+}
+
+/// Messages the server sends to clients as JSON text
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Welcome {
+        player: Id,
+    },
+    Rooms {
+        rooms: Vec<RoomInfo>,
+    },
+    /// The client entered the room, the board follows
+    Joined {
+        room: RoomId,
+        name: String,
+    },
+    Left {
+        room: RoomId,
+    },
+    /// Complete state of the current game
+    Board {
+        /// Identifies the puzzle for leaderboards
+        puzzle: u64,
+        /// Number of the game in the room, which tells a restarted puzzle
+        /// apart
+        game: u64,
+        width: usize,
+        height: usize,
+        dots: Vec<Dot>,
+        fields: Vec<Field>,
+        /// Number of changes the fields include
+        sequence: usize,
+    },
+    Change {
+        /// Number of changes including this one, which increases with every
+        /// change of the game
+        sequence: usize,
+        #[serde(flatten)]
+        change: GameChange,
+    },
+    /// Next step for the player, the dot is missing if the field has to be
+    /// cleared
+    Hint {
+        reason: HintReason,
+        field: Position,
+        dot: Option<Id>,
+        involved: Vec<Position>,
+        text: String,
+    },
+    Progress {
+        percent: u8,
+        contributions: Vec<Contribution>,
+    },
+    /// Dot of every field by its id after the given amount of changes
+    Snapshot {
+        changes: usize,
+        fields: Vec<Option<Id>>,
+    },
+    Replay(Replay),
+    Leaderboard {
+        period: Period,
+        puzzle: Option<u64>,
+        ranks: Vec<Rank>,
+    },
+    /// Progress of a player in race mode
+    RaceProgress {
+        player: Id,
+        percent: u8,
+    },
+    /// The player found the first solution of the race
+    RaceWon {
+        player: Id,
+        name: String,
+    },
+    Solved,
+    /// Message of an admin to everybody
+    Announcement {
+        message: String,
+    },
+    /// An admin disconnected the client
+    Kicked {
+        reason: String,
+    },
+    /// The server stops, the connection is closed afterwards
+    ShuttingDown,
+    Error {
+        message: String,
+    },
+}
+
+/// Commands of the admin console
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AdminCommand {
+    Rooms,
+    /// Lists the players who are online
+    Players,
+    /// Disconnects all clients of the player
+    Kick {
+        player: Id,
+    },
+    /// Kicks the player and refuses its logins for the minutes, until it is
+    /// unbanned if there are none
+    Ban {
+        player: Id,
+        minutes: Option<u64>,
+    },
+    Unban {
+        player: Id,
+    },
+    /// Closes a room, its clients stay connected outside of rooms
+    CloseRoom {
+        room: RoomId,
+    },
+    /// Sends a message to every client
+    Announce {
+        message: String,
+    },
+    /// Compacts the log, so it contains the current state
+    Save,
+}
+
+/// Command on the admin channel, which is only executed with the token of
+/// the server
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AdminRequest {
+    pub token: String,
+    #[serde(flatten)]
+    pub command: AdminCommand,
+}
+
+/// Player who is online
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerInfo {
+    pub player: Id,
+    pub name: String,
+    pub connections: usize,
+    /// Rooms the connections of the player are in
+    pub rooms: Vec<RoomId>,
+}
+
+/// Answers on the admin channel
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AdminResponse {
+    Rooms { rooms: Vec<RoomInfo> },
+    Players { players: Vec<PlayerInfo> },
+    Done { message: String },
+    Error { message: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_messages_from_json() {
+        let message: ClientMessage =
+            serde_json::from_str(r#"{"type": "assign", "field": [3, 4], "dot": 7}"#).unwrap();
+        assert_eq!(
+            message,
+            ClientMessage::Assign {
+                field: Position(3, 4),
+                dot: Some(7)
+            }
+        );
+
+        let message: ClientMessage =
+            serde_json::from_str(r#"{"type": "new_game", "width": 5, "height": 6}"#).unwrap();
+        assert_eq!(
+            message,
+            ClientMessage::NewGame {
+                width: 5,
+                height: 6,
+                difficulty: Difficulty::Normal,
+                seed: None,
+                mode: Mode::Coop,
+                settings: Settings::default()
+            }
+        );
+    }
+
+    #[test]
+    fn server_messages_to_json() {
+        let message = ServerMessage::Change {
+            sequence: 4,
+            change: GameChange {
+                player: 1,
+                affected_field: 2,
+                new_association: Some(3),
+                old_association: None,
+            },
+        };
+        let json = serde_json::to_string(&message).unwrap();
+        assert_eq!(
+            json,
+            r#"{"type":"change","sequence":4,"player":1,"affected_field":2,"new_association":3,"old_association":null}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<ServerMessage>(&json).unwrap(),
+            message
+        );
+    }
+}
@@ -0,0 +1,241 @@
+use crate::export::{self, Picture, Style};
+use crate::galaxy::MAX_BOARD_SIZE;
+use crate::game::{self, Game, Snapshot};
+use crate::gamegen::{DotColor, DotPos};
+use crate::solver::Assignment;
+use crate::types::{Dot, TimedChange};
+use serde::{Deserialize, Serialize};
+
+/// Symbols of the galaxies in text frames, which repeat for many dots
+const SYMBOLS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// Recording of a game, which can be stepped through move by move. It
+/// contains no solution, so it can be shared during a game.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub width: usize,
+    pub height: usize,
+    pub dots: Vec<Dot>,
+    pub moves: Vec<TimedChange>,
+}
+
+impl Replay {
+    pub fn new(game: &Game) -> Self {
+        Replay {
+            width: game.width,
+            height: game.height,
+            dots: game.dots.clone(),
+            moves: game.changes().to_vec(),
+        }
+    }
+
+    /// Checks that dots and moves lie on the board, as replays are read from
+    /// files
+    pub fn check(&self) -> Result<(), String> {
+        if self.width == 0 || self.height == 0 {
+            return Err("The board is empty".to_owned());
+        }
+        if self.width > MAX_BOARD_SIZE || self.height > MAX_BOARD_SIZE {
+            return Err(format!("The board is larger than {0}x{0}", MAX_BOARD_SIZE));
+        }
+        for (index, dot) in self.dots.iter().enumerate() {
+            if usize::from(dot.id) != index {
+                return Err(format!("Dot {} is out of order", dot.id));
+            }
+            let (x, y) = (usize::from(dot.position.0), usize::from(dot.position.1));
+            if x >= 2 * self.width - 1 || y >= 2 * self.height - 1 {
+                return Err(format!("Dot {} lies outside of the board", dot.id));
+            }
+        }
+        for timed in &self.moves {
+            if usize::from(timed.change.affected_field) >= self.width * self.height {
+                return Err(format!(
+                    "Field {} of a move doesn't exist",
+                    timed.change.affected_field
+                ));
+            }
+            let dots = [timed.change.new_association, timed.change.old_association];
+            if let Some(dot) = dots
+                .iter()
+                .flatten()
+                .find(|&&dot| usize::from(dot) >= self.dots.len())
+            {
+                return Err(format!("Dot {} of a move doesn't exist", dot));
+            }
+        }
+        Ok(())
+    }
+
+    /// @return Dot of every field by its id after the given amount of moves
+    pub fn state_at(&self, moves: usize) -> Snapshot {
+        let mut state = vec![None; self.width * self.height];
+        for timed in self.moves.iter().take(moves) {
+            game::fold(&mut state, timed);
+        }
+        state
+    }
+
+    /// Converts the state after the given amount of moves into field
+    /// coordinates
+    fn assignment_at(&self, moves: usize) -> Assignment {
+        let mut assignment = vec![vec![None; self.height]; self.width];
+        for (id, dot) in self.state_at(moves).into_iter().enumerate() {
+            assignment[id % self.width][id / self.width] = dot.map(usize::from);
+        }
+        assignment
+    }
+
+    /// Describes the last move of a frame
+    fn caption(&self, moves: usize) -> String {
+        match moves.checked_sub(1).and_then(|index| self.moves.get(index)) {
+            Some(timed) => {
+                let start = self.moves[0].time;
+                format!(
+                    "Move {}/{} after {:.1}s by player {}",
+                    moves,
+                    self.moves.len(),
+                    timed.time.saturating_sub(start) as f64 / 1000.0,
+                    timed.change.player
+                )
+            }
+            None => format!("Start, {} moves", self.moves.len()),
+        }
+    }
+
+    /// Draws the board after the given amount of moves in dot grid
+    /// coordinates. Dots are stars, fields show the symbol of their galaxy
+    /// and borders are drawn between galaxies.
+    ///
+    /// @return Text with a caption line
+    pub fn text(&self, moves: usize) -> String {
+        let assignment = self.assignment_at(moves);
+        let (width, height) = (2 * self.width - 1, 2 * self.height - 1);
+        let mut grid = vec![vec![b' '; width]; height];
+
+        let galaxy = |x: usize, y: usize| assignment[x][y];
+        let differs = |a: Option<usize>, b: Option<usize>| a.is_some() && b.is_some() && a != b;
+        for (y, row) in grid.iter_mut().enumerate() {
+            for (x, cell) in row.iter_mut().enumerate() {
+                *cell = match (x % 2, y % 2) {
+                    (0, 0) => galaxy(x / 2, y / 2).map_or(b'.', |dot| SYMBOLS[dot % SYMBOLS.len()]),
+                    (1, 0) if differs(galaxy(x / 2, y / 2), galaxy(x / 2 + 1, y / 2)) => b'|',
+                    (0, 1) if differs(galaxy(x / 2, y / 2), galaxy(x / 2, y / 2 + 1)) => b'-',
+                    _ => b' ',
+                };
+            }
+        }
+        for dot in &self.dots {
+            grid[usize::from(dot.position.1)][usize::from(dot.position.0)] = b'*';
+        }
+
+        let mut text = self.caption(moves);
+        text.push('\n');
+        for row in grid {
+            text.push_str(String::from_utf8_lossy(&row).trim_end());
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Draws the board after the given amount of moves as SVG document
+    ///
+    /// @return SVG document, an error if the image is too large
+    pub fn svg(&self, moves: usize, style: &Style) -> Result<String, &'static str> {
+        let positions: Vec<DotPos> = self
+            .dots
+            .iter()
+            .map(|dot| DotPos(usize::from(dot.position.0), usize::from(dot.position.1)))
+            .collect();
+        let colors: Vec<DotColor> = self.dots.iter().map(|dot| dot.color).collect();
+        let assignment = self.assignment_at(moves);
+        export::svg(
+            &Picture {
+                width: self.width,
+                height: self.height,
+                dots: &positions,
+                colors: Some(&colors),
+                galaxies: None,
+                assignment: Some(&assignment),
+            },
+            style,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{GameChange, Position};
+
+    fn replay() -> Replay {
+        let change = |time, affected_field, new_association| TimedChange {
+            time,
+            change: GameChange {
+                player: 0,
+                affected_field,
+                new_association,
+                old_association: None,
+            },
+        };
+        Replay {
+            width: 2,
+            height: 2,
+            dots: vec![
+                Dot {
+                    id: 0,
+                    color: DotColor::White,
+                    position: Position(1, 0),
+                },
+                Dot {
+                    id: 1,
+                    color: DotColor::Black,
+                    position: Position(1, 2),
+                },
+            ],
+            moves: vec![
+                change(1000, 0, Some(0)),
+                change(1500, 1, Some(0)),
+                change(4000, 2, Some(1)),
+            ],
+        }
+    }
+
+    #[test]
+    fn text_frames() {
+        let replay = replay();
+        assert_eq!(replay.text(0), "Start, 3 moves\n.*.\n\n.*.\n");
+        assert_eq!(
+            replay.text(3),
+            "Move 3/3 after 3.0s by player 0\n0*0\n-\n1*.\n"
+        );
+    }
+
+    #[test]
+    fn invalid_replays_are_refused() {
+        let mut oversized = replay();
+        oversized.width = usize::MAX;
+        assert!(oversized.check().is_err());
+
+        let mut unknown_dot = replay();
+        unknown_dot.moves[1].change.old_association = Some(2);
+        assert_eq!(
+            unknown_dot.check(),
+            Err("Dot 2 of a move doesn't exist".to_owned())
+        );
+
+        let mut reordered = replay();
+        reordered.dots.swap(0, 1);
+        assert!(reordered.check().is_err());
+        assert_eq!(replay().check(), Ok(()));
+    }
+
+    #[test]
+    fn replay_from_server_message() {
+        let replay = replay();
+        let json =
+            serde_json::to_string(&crate::protocol::ServerMessage::Replay(replay.clone())).unwrap();
+        assert_eq!(serde_json::from_str::<Replay>(&json).unwrap(), replay);
+        assert_eq!(replay.check(), Ok(()));
+        assert!(replay.svg(2, &Style::default()).unwrap().contains("<rect"));
+    }
+}
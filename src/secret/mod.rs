@@ -0,0 +1,69 @@
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Passphrase or password as sent by a client, which is never printed
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(pub String);
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("Secret(..)")
+    }
+}
+
+/// Salted Argon2 hash of a passphrase or password in the PHC string format.
+/// Hashing takes a while on purpose, so it shouldn't happen while holding a
+/// lock.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SecretHash(String);
+
+impl SecretHash {
+    pub fn new(secret: &Secret) -> Self {
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::default()
+            .hash_password(secret.0.as_bytes(), &salt)
+            .expect("Hashing with default parameters can't fail");
+        SecretHash(hash.to_string())
+    }
+
+    /// Compares the secret with the hashed one in constant time
+    /// @return Whether the secret is the hashed one
+    pub fn matches(&self, secret: &Secret) -> bool {
+        match PasswordHash::new(&self.0) {
+            Ok(hash) => Argon2::default()
+                .verify_password(secret.0.as_bytes(), &hash)
+                .is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+
+/// Hashes are never printed, not even in logs of failed writes
+impl fmt::Debug for SecretHash {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("SecretHash(..)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_are_salted_and_verified() {
+        let secret = Secret("hunter2".to_owned());
+        let first = SecretHash::new(&secret);
+        let second = SecretHash::new(&secret);
+        assert_ne!(first, second);
+        assert!(first.matches(&secret));
+        assert!(!first.matches(&Secret("hunter3".to_owned())));
+        assert!(!serde_json::to_string(&first).unwrap().contains("hunter2"));
+        assert_eq!(format!("{:?}", secret), "Secret(..)");
+        assert_eq!(format!("{:?}", first), "SecretHash(..)");
+    }
+}
@@ -0,0 +1,145 @@
+use super::{Assignment, Exhausted, Puzzle, Reason};
+use crate::gamegen::Galaxies;
+
+/// Partial assignments the solver may try for a single hint, which keeps
+/// hints on big boards fast
+const SEARCH_STEPS: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HintKind {
+    /// The field follows logically from the current state
+    Deduction(Reason),
+    /// The field belongs to a wrong galaxy and has to be cleared
+    Mistake,
+    /// No simple deduction is left, so the field is revealed
+    Reveal,
+}
+
+/// A single step towards the solution, which is shown to a player
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hint {
+    pub kind: HintKind,
+    pub field: (usize, usize),
+    /// Dot the field belongs to, none if it has to be cleared
+    pub dot: Option<usize>,
+    /// Further fields which lead to the hint
+    pub involved: Vec<(usize, usize)>,
+}
+
+/// Formats a dot grid position in field coordinates, which are halves for
+/// dots on edges
+fn dot_coordinates(puzzle: &Puzzle, dot: usize) -> String {
+    let format = |offset: usize| {
+        if offset % 2 == 1 {
+            format!("{}.5", offset / 2)
+        } else {
+            format!("{}", offset / 2)
+        }
+    };
+    let position = &puzzle.dots[dot];
+    format!("({}, {})", format(position.0), format(position.1))
+}
+
+impl Hint {
+    /// Explains the hint in a sentence for the player
+    pub fn describe(&self, puzzle: &Puzzle) -> String {
+        let (x, y) = self.field;
+        let dot = match self.dot {
+            Some(dot) => dot_coordinates(puzzle, dot),
+            None => String::new(),
+        };
+        match self.kind {
+            HintKind::Deduction(Reason::UnderDot) => {
+                format!(
+                    "Field ({}, {}) belongs to the dot at {}, because the dot lies on it",
+                    x, y, dot
+                )
+            }
+            HintKind::Deduction(Reason::Mirror) => {
+                let (mx, my) = self.involved[0];
+                format!(
+                    "Field ({}, {}) must belong to the dot at {}, because its mirrored field ({}, {}) does",
+                    x, y, dot, mx, my
+                )
+            }
+            HintKind::Deduction(Reason::OnlyCandidate) => format!(
+                "Field ({}, {}) must belong to the dot at {}, because no other dot can reach it",
+                x, y, dot
+            ),
+            HintKind::Mistake => format!("Field ({}, {}) belongs to the wrong galaxy", x, y),
+            HintKind::Reveal => format!("Field ({}, {}) belongs to the dot at {}", x, y, dot),
+        }
+    }
+}
+
+/// @return Hint at the first assigned field which differs from the solution
+fn mistake(puzzle: &Puzzle, assignment: &Assignment, solution: &Galaxies) -> Option<Hint> {
+    for x in 0..puzzle.width {
+        for y in 0..puzzle.height {
+            if let Some(dot) = assignment[x][y] {
+                if dot != solution[x][y] {
+                    return Some(Hint {
+                        kind: HintKind::Mistake,
+                        field: (x, y),
+                        dot: None,
+                        involved: Vec::new(),
+                    });
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Finds the next step for a player. Mistakes are pointed out first, then
+/// logical deductions are preferred over revealing a field of the solution.
+///
+/// @return Hint, none if the puzzle is solved already, or Exhausted if the
+/// solver gave up on an assignment which differs from the solution
+pub fn hint(
+    puzzle: &Puzzle,
+    assignment: &Assignment,
+    solution: &Galaxies,
+) -> Result<Option<Hint>, Exhausted> {
+    // The solution completes every assignment which follows it, only other
+    // assignments need the solver
+    let follows = (0..puzzle.width).all(|x| {
+        (0..puzzle.height).all(|y| assignment[x][y].is_none_or(|dot| dot == solution[x][y]))
+    });
+    let completion = if follows {
+        None
+    } else {
+        match puzzle.solve_within(assignment, SEARCH_STEPS)? {
+            Some(completion) => Some(completion),
+            // The current state can't be completed, so there is a mistake
+            // compared to the solution
+            None => return Ok(mistake(puzzle, assignment, solution)),
+        }
+    };
+    let completion = completion.as_ref().unwrap_or(solution);
+
+    if let Ok(Some(deduction)) = puzzle.next_deduction(assignment) {
+        return Ok(Some(Hint {
+            kind: HintKind::Deduction(deduction.reason),
+            field: deduction.field,
+            dot: Some(deduction.dot),
+            involved: deduction.involved,
+        }));
+    }
+
+    // Reveal a free field, because there is no simple deduction
+    for x in 0..puzzle.width {
+        for y in 0..puzzle.height {
+            if assignment[x][y].is_none() {
+                return Ok(Some(Hint {
+                    kind: HintKind::Reveal,
+                    field: (x, y),
+                    dot: Some(completion[x][y]),
+                    involved: Vec::new(),
+                }));
+            }
+        }
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,398 @@
+use crate::gamegen::{DotPos, Galaxies};
+use std::collections::VecDeque;
+
+pub mod hint;
+mod validate;
+
+pub use validate::validate;
+
+/// Partial solution of a puzzle. For every field the index of the dot whose
+/// galaxy it belongs to, if it is known. Uses field coordinates like Galaxies.
+pub type Assignment = Vec<Vec<Option<usize>>>;
+
+/// Dots which could still be the center of the galaxy of a field, for every
+/// field in field coordinates
+type Candidates = Vec<Vec<Vec<usize>>>;
+
+/// The dots of a puzzle and the size of its board
+pub struct Puzzle<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub dots: &'a [DotPos],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reason {
+    /// The dot lies on the field
+    UnderDot,
+    /// The mirrored field already belongs to the dot
+    Mirror,
+    /// No other dot can reach the field with a symmetric galaxy
+    OnlyCandidate,
+}
+
+/// A field which has to belong to a dot and why
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Deduction {
+    pub field: (usize, usize),
+    pub dot: usize,
+    pub reason: Reason,
+    /// Further fields which lead to the deduction
+    pub involved: Vec<(usize, usize)>,
+}
+
+/// The assignment can't be completed to a solution, because no dot is left
+/// for this field
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Contradiction {
+    pub field: (usize, usize),
+}
+
+/// The search for a solution took more steps than it was allowed to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Exhausted;
+
+impl<'a> Puzzle<'a> {
+    /// Mirrors a field at a dot.
+    ///
+    /// @return Mirrored field, if it is on the board
+    pub fn mirror(&self, dot: usize, field: (usize, usize)) -> Option<(usize, usize)> {
+        let dot = &self.dots[dot];
+        if field.0 > dot.0 || field.1 > dot.1 {
+            return None;
+        }
+        let mirrored = (dot.0 - field.0, dot.1 - field.1);
+        if mirrored.0 < self.width && mirrored.1 < self.height {
+            Some(mirrored)
+        } else {
+            None
+        }
+    }
+
+    /// Creates an assignment in which no field belongs to a dot yet
+    pub fn empty_assignment(&self) -> Assignment {
+        vec![vec![None; self.height]; self.width]
+    }
+
+    fn neighbors(&self, field: (usize, usize)) -> Vec<(usize, usize)> {
+        let (x, y) = field;
+        let mut neighbors = Vec::new();
+        if x > 0 {
+            neighbors.push((x - 1, y));
+        }
+        if y > 0 {
+            neighbors.push((x, y - 1));
+        }
+        if x + 1 < self.width {
+            neighbors.push((x + 1, y));
+        }
+        if y + 1 < self.height {
+            neighbors.push((x, y + 1));
+        }
+        neighbors
+    }
+
+    /// Determines for every field which dots could still own it. A dot is a
+    /// candidate, if the field and its mirror are free or already belong to
+    /// the dot and the field can be reached from the dot over such fields.
+    fn candidates(&self, assignment: &Assignment) -> Candidates {
+        let mut candidates = vec![vec![Vec::new(); self.height]; self.width];
+
+        for dot in 0..self.dots.len() {
+            let possible = |field: (usize, usize)| {
+                let free_for_dot = |f: (usize, usize)| match assignment[f.0][f.1] {
+                    Some(owner) => owner == dot,
+                    None => true,
+                };
+                free_for_dot(field) && self.mirror(dot, field).map(free_for_dot).unwrap_or(false)
+            };
+
+            // Flood fill from the fields under the dot
+            let mut reached = vec![vec![false; self.height]; self.width];
+            let mut queue: VecDeque<(usize, usize)> = self.dots[dot]
+                .covered_fields()
+                .into_iter()
+                .filter(|&field| possible(field))
+                .collect();
+            for &(x, y) in &queue {
+                reached[x][y] = true;
+            }
+            while let Some(field) = queue.pop_front() {
+                candidates[field.0][field.1].push(dot);
+                for (x, y) in self.neighbors(field) {
+                    if !reached[x][y] && possible((x, y)) {
+                        reached[x][y] = true;
+                        queue.push_back((x, y));
+                    }
+                }
+            }
+        }
+
+        candidates
+    }
+
+    /// Finds every field, which is free but can only belong to one dot.
+    fn deductions(
+        &self,
+        assignment: &Assignment,
+        candidates: &Candidates,
+    ) -> Result<Vec<Deduction>, Contradiction> {
+        let mut deductions = Vec::new();
+
+        // Fields under a dot
+        for (dot, position) in self.dots.iter().enumerate() {
+            let covered = position.covered_fields();
+            for &field in &covered {
+                if assignment[field.0][field.1].is_none() {
+                    deductions.push(Deduction {
+                        field,
+                        dot,
+                        reason: Reason::UnderDot,
+                        involved: covered.iter().copied().filter(|&f| f != field).collect(),
+                    });
+                }
+            }
+        }
+
+        for x in 0..self.width {
+            for y in 0..self.height {
+                let field_candidates = &candidates[x][y];
+                if field_candidates.is_empty() {
+                    return Err(Contradiction { field: (x, y) });
+                }
+
+                match assignment[x][y] {
+                    // Mirrors of assigned fields
+                    Some(dot) => {
+                        let mirrored = self.mirror(dot, (x, y));
+                        if let Some(mirrored) = mirrored {
+                            if assignment[mirrored.0][mirrored.1].is_none() {
+                                deductions.push(Deduction {
+                                    field: mirrored,
+                                    dot,
+                                    reason: Reason::Mirror,
+                                    involved: vec![(x, y)],
+                                });
+                            }
+                        }
+                    }
+                    // Fields only one dot can reach
+                    None => {
+                        if field_candidates.len() == 1 {
+                            deductions.push(Deduction {
+                                field: (x, y),
+                                dot: field_candidates[0],
+                                reason: Reason::OnlyCandidate,
+                                involved: Vec::new(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(deductions)
+    }
+
+    /// Determines the next logical step from the assignment.
+    ///
+    /// @return Deduction, none if there is no simple one left
+    pub fn next_deduction(
+        &self,
+        assignment: &Assignment,
+    ) -> Result<Option<Deduction>, Contradiction> {
+        let candidates = self.candidates(assignment);
+        let deductions = self.deductions(assignment, &candidates)?;
+        Ok(deductions.into_iter().next())
+    }
+
+    /// Applies deductions until there are none left.
+    fn propagate(&self, assignment: &mut Assignment) -> Result<Candidates, Contradiction> {
+        loop {
+            let candidates = self.candidates(assignment);
+            let deductions = self.deductions(assignment, &candidates)?;
+            if deductions.is_empty() {
+                return Ok(candidates);
+            }
+            for deduction in deductions {
+                let (x, y) = deduction.field;
+                match assignment[x][y] {
+                    Some(dot) if dot != deduction.dot => {
+                        return Err(Contradiction {
+                            field: deduction.field,
+                        })
+                    }
+                    _ => assignment[x][y] = Some(deduction.dot),
+                }
+            }
+        }
+    }
+
+    /// Completes the assignment to a solution. Uses deductions as long as
+    /// possible and tries out the candidates of a field afterwards.
+    ///
+    /// @return First found solution, none if there is no solution
+    pub fn solve(&self, assignment: &Assignment) -> Option<Galaxies> {
+        self.solve_within(assignment, usize::MAX).unwrap_or(None)
+    }
+
+    /// Completes the assignment like solve, but gives up after trying the
+    /// given amount of partial assignments. Big boards can take very long
+    /// otherwise.
+    ///
+    /// @return First found solution, none if there is no solution
+    pub fn solve_within(
+        &self,
+        assignment: &Assignment,
+        steps: usize,
+    ) -> Result<Option<Galaxies>, Exhausted> {
+        let mut steps = steps;
+        self.search(assignment, &mut steps)
+    }
+
+    fn search(
+        &self,
+        assignment: &Assignment,
+        steps: &mut usize,
+    ) -> Result<Option<Galaxies>, Exhausted> {
+        if *steps == 0 {
+            return Err(Exhausted);
+        }
+        *steps -= 1;
+
+        let mut assignment = assignment.clone();
+        let candidates = match self.propagate(&mut assignment) {
+            Ok(candidates) => candidates,
+            Err(_) => return Ok(None),
+        };
+
+        // Branch at the free field with the fewest candidates
+        let mut branch: Option<(usize, usize)> = None;
+        for x in 0..self.width {
+            for y in 0..self.height {
+                if assignment[x][y].is_none() {
+                    let better = match branch {
+                        Some((bx, by)) => candidates[x][y].len() < candidates[bx][by].len(),
+                        None => true,
+                    };
+                    if better {
+                        branch = Some((x, y));
+                    }
+                }
+            }
+        }
+
+        match branch {
+            Some((x, y)) => {
+                for &dot in &candidates[x][y] {
+                    let mut guess = assignment.clone();
+                    guess[x][y] = Some(dot);
+                    if let Some(solution) = self.search(&guess, steps)? {
+                        return Ok(Some(solution));
+                    }
+                }
+                Ok(None)
+            }
+            None => {
+                if validate(self, &assignment).is_err() {
+                    return Ok(None);
+                }
+                Ok(Some(
+                    assignment
+                        .into_iter()
+                        .map(|column| column.into_iter().map(Option::unwrap).collect())
+                        .collect(),
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gamegen::{self, Difficulty, GenerationConfig};
+
+    #[test]
+    fn deductions_on_small_board() {
+        // Two vertical dominos next to each other
+        // ┌───┬───┐
+        // │   │   │
+        // │ ○ │ ○ │
+        // │   │   │
+        // └───┴───┘
+        let dots = [DotPos(0, 1), DotPos(2, 1)];
+        let puzzle = Puzzle {
+            width: 2,
+            height: 2,
+            dots: &dots,
+        };
+        let mut assignment = puzzle.empty_assignment();
+
+        let deduction = puzzle.next_deduction(&assignment).unwrap().unwrap();
+        assert_eq!(deduction.reason, Reason::UnderDot);
+        assert_eq!(deduction.field, (0, 0));
+        assert_eq!(deduction.dot, 0);
+
+        // Wrong assignment leads to a contradiction
+        assignment[1][0] = Some(0);
+        assert!(puzzle.next_deduction(&assignment).is_err());
+
+        let solution = puzzle.solve(&puzzle.empty_assignment()).unwrap();
+        assert_eq!(solution, vec![vec![0, 0], vec![1, 1]]);
+    }
+
+    #[test]
+    fn solves_generated_boards() {
+        for seed in 0..20 {
+            let board = gamegen::generate(&GenerationConfig {
+                width: 8,
+                height: 8,
+                difficulty: Difficulty::Normal,
+                seed,
+            });
+            let puzzle = Puzzle {
+                width: board.width,
+                height: board.height,
+                dots: &board.dots,
+            };
+
+            let solution = puzzle.solve(&puzzle.empty_assignment()).unwrap();
+            let solution = solution
+                .iter()
+                .map(|column| column.iter().copied().map(Some).collect())
+                .collect();
+            assert_eq!(validate(&puzzle, &solution), Ok(()));
+        }
+    }
+
+    #[test]
+    fn hints_on_big_boards_are_bounded() {
+        let board = gamegen::generate(&GenerationConfig {
+            width: 30,
+            height: 30,
+            difficulty: Difficulty::Hard,
+            seed: 2,
+        });
+        let puzzle = Puzzle {
+            width: board.width,
+            height: board.height,
+            dots: &board.dots,
+        };
+        let mut assignment = puzzle.empty_assignment();
+        assert_eq!(puzzle.solve_within(&assignment, 0), Err(Exhausted));
+
+        // Following the solution needs no search at all
+        let found = hint::hint(&puzzle, &assignment, &board.galaxies).unwrap();
+        let kind = found.unwrap().kind;
+        assert_eq!(kind, hint::HintKind::Deduction(Reason::UnderDot));
+
+        // Other assignments get a hint or give up, but never search long
+        let wrong = (board.galaxies[15][15] + 1) % board.dots.len();
+        assignment[15][15] = Some(wrong);
+        match hint::hint(&puzzle, &assignment, &board.galaxies) {
+            Ok(Some(found)) => assert_eq!(found.kind, hint::HintKind::Mistake),
+            other => assert_eq!(other, Err(Exhausted)),
+        }
+    }
+}
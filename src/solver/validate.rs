@@ -0,0 +1,86 @@
+use super::{Assignment, Puzzle};
+use std::fmt;
+
+/// Reason why an assignment is no valid solution
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Violation {
+    /// The field doesn't belong to any galaxy
+    Unassigned { field: (usize, usize) },
+    /// The field lies under the dot but belongs to another galaxy
+    DotNotInGalaxy { dot: usize },
+    /// The mirrored field doesn't belong to the same galaxy
+    Asymmetric { field: (usize, usize), dot: usize },
+    /// The field is not connected to the rest of its galaxy
+    Disconnected { field: (usize, usize), dot: usize },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Unassigned { field } => {
+                write!(f, "Field {:?} belongs to no galaxy", field)
+            }
+            Violation::DotNotInGalaxy { dot } => {
+                write!(f, "Dot {} lies outside of its galaxy", dot)
+            }
+            Violation::Asymmetric { field, dot } => {
+                write!(f, "Galaxy of dot {} is not symmetric at {:?}", dot, field)
+            }
+            Violation::Disconnected { field, dot } => {
+                write!(f, "Field {:?} is not connected to dot {}", field, dot)
+            }
+        }
+    }
+}
+
+/// Checks if an assignment is a valid solution of the puzzle. Every field has
+/// to belong to a galaxy, which contains its dot, is symmetric around it and
+/// is connected.
+pub fn validate(puzzle: &Puzzle, assignment: &Assignment) -> Result<(), Violation> {
+    // Every field belongs to a galaxy, which is symmetric
+    for x in 0..puzzle.width {
+        for y in 0..puzzle.height {
+            let dot = assignment[x][y].ok_or(Violation::Unassigned { field: (x, y) })?;
+            let symmetric = match puzzle.mirror(dot, (x, y)) {
+                Some((mx, my)) => assignment[mx][my] == Some(dot),
+                None => false,
+            };
+            if !symmetric {
+                return Err(Violation::Asymmetric { field: (x, y), dot });
+            }
+        }
+    }
+
+    // Every galaxy contains its dot and is connected
+    let mut reached = vec![vec![false; puzzle.height]; puzzle.width];
+    for (dot, position) in puzzle.dots.iter().enumerate() {
+        let mut stack = Vec::new();
+        for (x, y) in position.covered_fields() {
+            if assignment[x][y] != Some(dot) {
+                return Err(Violation::DotNotInGalaxy { dot });
+            }
+            if !reached[x][y] {
+                reached[x][y] = true;
+                stack.push((x, y));
+            }
+        }
+        while let Some(field) = stack.pop() {
+            for (x, y) in puzzle.neighbors(field) {
+                if !reached[x][y] && assignment[x][y] == Some(dot) {
+                    reached[x][y] = true;
+                    stack.push((x, y));
+                }
+            }
+        }
+    }
+    for x in 0..puzzle.width {
+        for y in 0..puzzle.height {
+            if !reached[x][y] {
+                let dot = assignment[x][y].unwrap();
+                return Err(Violation::Disconnected { field: (x, y), dot });
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,182 @@
+use crate::galaxy::{Mode, RoomId, Settings};
+use crate::gamegen::Board;
+use crate::leaderboard::Solve;
+use crate::secret::SecretHash;
+use crate::types::{Id, Player, TimedChange};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Single entry of the log. Replaying all records in order restores the
+/// state of the server.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record {
+    Player(Player),
+    /// Creates a room, which gets its game from the next room record
+    Open {
+        room: RoomId,
+        name: String,
+        password: Option<SecretHash>,
+        capacity: Option<usize>,
+    },
+    /// Starts a new game in the room, changes before it are obsolete
+    Room {
+        room: RoomId,
+        mode: Mode,
+        settings: Settings,
+        board: Board,
+    },
+    Change {
+        room: RoomId,
+        #[serde(flatten)]
+        change: TimedChange,
+    },
+    Solve(Solve),
+    /// The room was closed, all its records are obsolete
+    Close {
+        room: RoomId,
+    },
+    /// Logins of the player are refused until the time in milliseconds since
+    /// the Unix epoch, forever if there is none
+    Ban {
+        player: Id,
+        until: Option<u64>,
+    },
+    Unban {
+        player: Id,
+    },
+}
+
+/// Append-only log of records with one JSON object per line
+pub struct Storage {
+    path: PathBuf,
+    file: File,
+}
+
+fn serialize(record: &Record) -> String {
+    let mut line = serde_json::to_string(record).expect("Serializing record failed");
+    line.push('\n');
+    line
+}
+
+impl Storage {
+    /// Opens the log at the path and creates it if it is missing. A record
+    /// which was only written partially during a crash is dropped.
+    ///
+    /// @return Storage and all records of the log
+    pub fn open(path: &Path) -> io::Result<(Storage, Vec<Record>)> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(error) => return Err(error),
+        };
+
+        let mut records = Vec::new();
+        let mut valid_length = 0;
+        let lines: Vec<&str> = content.split_inclusive('\n').collect();
+        for (index, line) in lines.iter().enumerate() {
+            let last = index + 1 == lines.len();
+            // Only the last line can miss its line break
+            let complete = line.ends_with('\n');
+            match serde_json::from_str(line) {
+                Ok(record) if complete => records.push(record),
+                Err(error) if !last => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, error))
+                }
+                _ => {
+                    warn!("Dropping incomplete last record of {:?}", path);
+                    break;
+                }
+            }
+            valid_length += line.len();
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.set_len(valid_length as u64)?;
+        let storage = Storage {
+            path: path.to_owned(),
+            file,
+        };
+        Ok((storage, records))
+    }
+
+    pub fn append(&mut self, record: &Record) -> io::Result<()> {
+        self.file.write_all(serialize(record).as_bytes())
+    }
+
+    /// Replaces the whole log by the records. The new log is written next to
+    /// the old one first, so a crash leaves one of both intact.
+    pub fn rewrite(&mut self, records: &[Record]) -> io::Result<()> {
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        let temporary = PathBuf::from(temporary);
+
+        let content: String = records.iter().map(serialize).collect();
+        let mut file = File::create(&temporary)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temporary, &self.path)?;
+
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secret::Secret;
+    use crate::types::GameChange;
+    use std::process;
+
+    #[test]
+    fn restores_records_and_drops_partial_line() {
+        let path = std::env::temp_dir().join(format!("galaxy-storage-{}.log", process::id()));
+        let _ = fs::remove_file(&path);
+        let player = Record::Player(Player {
+            id: 0,
+            name: "Ann".to_owned(),
+            passphrase_hash: SecretHash::new(&Secret("secret".to_owned())),
+        });
+        let change = Record::Change {
+            room: 2,
+            change: TimedChange {
+                time: 1_600_000_000_000,
+                change: GameChange {
+                    player: 0,
+                    affected_field: 3,
+                    new_association: Some(1),
+                    old_association: None,
+                },
+            },
+        };
+
+        let (mut storage, records) = Storage::open(&path).unwrap();
+        assert!(records.is_empty());
+        storage.append(&player).unwrap();
+        storage.append(&change).unwrap();
+        drop(storage);
+
+        // Simulate a crash in the middle of writing a record
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"type":"change","pla"#).unwrap();
+        drop(file);
+
+        let (mut storage, records) = Storage::open(&path).unwrap();
+        assert_eq!(records, [player.clone(), change.clone()]);
+        storage.append(&change).unwrap();
+        drop(storage);
+
+        let (mut storage, records) = Storage::open(&path).unwrap();
+        assert_eq!(records.len(), 3);
+        storage.rewrite(std::slice::from_ref(&change)).unwrap();
+        drop(storage);
+
+        let (_, records) = Storage::open(&path).unwrap();
+        assert_eq!(records, vec![change]);
+        fs::remove_file(&path).unwrap();
+    }
+}
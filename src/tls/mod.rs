@@ -0,0 +1,259 @@
+use log::{info, warn};
+use serde::Deserialize;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::server::{ClientHello, ResolvesServerCert};
+use tokio_rustls::rustls::sign::CertifiedKey;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+
+/// Time between two checks whether the certificate files changed
+const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Files of the certificate chain and its private key, both PEM encoded
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    pub certificate: PathBuf,
+    pub key: PathBuf,
+}
+
+/// Modification time and length, which identify a version of a file
+type Version = (SystemTime, u64);
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn version(path: &Path) -> io::Result<Version> {
+    let metadata = fs::metadata(path)?;
+    Ok((metadata.modified()?, metadata.len()))
+}
+
+/// Reads the certificate chain and the private key, which has to belong to
+/// the certificate
+///
+/// @return Certificate, an error together with the key of the faulty file
+/// in the config
+fn read(config: &TlsConfig) -> Result<CertifiedKey, (&'static str, io::Error)> {
+    let certificate = |error| ("certificate", error);
+    let key = |error| ("key", error);
+
+    let mut reader = BufReader::new(File::open(&config.certificate).map_err(certificate)?);
+    let certificates = rustls_pemfile::certs(&mut reader)
+        .collect::<io::Result<Vec<_>>>()
+        .map_err(certificate)?;
+    if certificates.is_empty() {
+        let message = format!("No certificate in {:?}", config.certificate);
+        return Err(certificate(invalid(message)));
+    }
+    let mut reader = BufReader::new(File::open(&config.key).map_err(key)?);
+    let private_key = rustls_pemfile::private_key(&mut reader)
+        .map_err(key)?
+        .ok_or_else(|| key(invalid(format!("No private key in {:?}", config.key))))?;
+    let private_key = ring::sign::any_supported_type(&private_key)
+        .map_err(|error| key(invalid(format!("Unsupported private key: {}", error))))?;
+    let certified = CertifiedKey::new(certificates, private_key);
+    certified.keys_match().map_err(|error| {
+        key(invalid(format!(
+            "The key in {:?} doesn't belong to the certificate: {}",
+            config.key, error
+        )))
+    })?;
+    Ok(certified)
+}
+
+fn load(config: &TlsConfig) -> io::Result<CertifiedKey> {
+    read(config).map_err(|(_, error)| error)
+}
+
+impl TlsConfig {
+    /// Checks that the files hold a certificate and its key
+    ///
+    /// @return Error which names the key of the faulty file
+    pub fn check(&self) -> Result<(), String> {
+        read(self)
+            .map(drop)
+            .map_err(|(key, error)| format!("tls.{}: {}", key, error))
+    }
+}
+
+/// Certificate of the server, which is replaced when its files change.
+/// Connections which are established already keep the old one.
+#[derive(Debug)]
+pub struct Certificates {
+    config: TlsConfig,
+    current: RwLock<Arc<CertifiedKey>>,
+    versions: Mutex<(Version, Version)>,
+}
+
+impl Certificates {
+    pub fn load(config: TlsConfig) -> io::Result<Self> {
+        let versions = (version(&config.certificate)?, version(&config.key)?);
+        let current = load(&config)?;
+        Ok(Certificates {
+            config,
+            current: RwLock::new(Arc::new(current)),
+            versions: Mutex::new(versions),
+        })
+    }
+
+    /// Loads the certificate again if one of its files changed. The old one
+    /// stays in use if the new one is invalid.
+    ///
+    /// @return Whether the certificate was replaced
+    pub fn reload(&self) -> io::Result<bool> {
+        let versions = (
+            version(&self.config.certificate)?,
+            version(&self.config.key)?,
+        );
+        let mut loaded = self.versions.lock().unwrap();
+        if *loaded == versions {
+            return Ok(false);
+        }
+        // The versions are updated even if loading fails, because files which
+        // are written at the moment change again anyway
+        *loaded = versions;
+        let certificate = load(&self.config)?;
+        *self.current.write().unwrap() = Arc::new(certificate);
+        Ok(true)
+    }
+
+    /// Checks the files for changes in the background from now on
+    pub fn watch(self: Arc<Self>) {
+        thread::spawn(move || loop {
+            thread::sleep(RELOAD_INTERVAL);
+            match self.reload() {
+                Ok(true) => info!("Reloaded the certificate {:?}", self.config.certificate),
+                Ok(false) => (),
+                Err(error) => warn!("Reloading the certificate failed: {}", error),
+            }
+        });
+    }
+
+    /// @return Acceptor which presents the current certificate to clients
+    pub fn acceptor(self: Arc<Self>) -> TlsAcceptor {
+        let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .expect("The provider supports the default versions")
+            .with_no_client_auth()
+            .with_cert_resolver(self);
+        TlsAcceptor::from(Arc::new(config))
+    }
+}
+
+impl ResolvesServerCert for Certificates {
+    fn resolve(&self, _: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(Arc::clone(&self.current.read().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::galaxy::Galaxy;
+    use crate::network::Network;
+    use crate::protocol::{ClientMessage, ServerMessage};
+    use crate::secret::Secret;
+    use futures_util::{SinkExt, StreamExt};
+    use rcgen::{CertifiedKey as Generated, KeyPair};
+    use std::convert::TryFrom;
+    use std::process;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName};
+    use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+    use tokio_rustls::TlsConnector;
+    use tokio_tungstenite::tungstenite::Message;
+
+    fn generate(name: &str) -> Generated {
+        let key_pair = KeyPair::generate().unwrap();
+        let params = rcgen::CertificateParams::new(vec![name.to_owned()]).unwrap();
+        let cert = params.self_signed(&key_pair).unwrap();
+        Generated { cert, key_pair }
+    }
+
+    fn write(config: &TlsConfig, generated: &Generated, modified: SystemTime) {
+        fs::write(&config.certificate, generated.cert.pem()).unwrap();
+        fs::write(&config.key, generated.key_pair.serialize_pem()).unwrap();
+        for path in [&config.certificate, &config.key].iter() {
+            let file = File::options().write(true).open(path).unwrap();
+            file.set_modified(modified).unwrap();
+        }
+    }
+
+    /// Logs in over TLS with the certificate as only trusted one
+    ///
+    /// @return Certificate the server presented
+    async fn login(address: &str, trusted: &Generated) -> CertificateDer<'static> {
+        let mut roots = RootCertStore::empty();
+        roots.add(trusted.cert.der().clone()).unwrap();
+        let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let stream = TcpStream::connect(address).await.unwrap();
+        let name = ServerName::try_from("localhost".to_owned()).unwrap();
+        let stream = TlsConnector::from(Arc::new(config))
+            .connect(name, stream)
+            .await
+            .unwrap();
+        let presented = stream.get_ref().1.peer_certificates().unwrap()[0].clone();
+
+        let (mut socket, _) = tokio_tungstenite::client_async("wss://localhost/", stream)
+            .await
+            .unwrap();
+        let login = ClientMessage::Login {
+            name: "secure".to_owned(),
+            passphrase: Secret::default(),
+        };
+        let text = serde_json::to_string(&login).unwrap();
+        socket.send(Message::Text(text)).await.unwrap();
+        let answer = socket.next().await.unwrap().unwrap();
+        let answer: ServerMessage = serde_json::from_str(answer.to_text().unwrap()).unwrap();
+        assert!(matches!(answer, ServerMessage::Welcome { .. }));
+        presented
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serves_and_reloads_certificates() {
+        let directory = std::env::temp_dir().join(format!("galaxy-tls-{}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let config = TlsConfig {
+            certificate: directory.join("cert.pem"),
+            key: directory.join("key.pem"),
+        };
+        let first = generate("localhost");
+        write(&config, &first, SystemTime::UNIX_EPOCH);
+        let certificates = Arc::new(Certificates::load(config.clone()).unwrap());
+
+        let galaxy = Galaxy::for_tests();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let network = Network::new(Arc::new(galaxy)).tls(Arc::clone(&certificates).acceptor());
+        tokio::spawn(network.serve(listener));
+        assert_eq!(login(&address, &first).await, *first.cert.der());
+
+        assert!(!certificates.reload().unwrap());
+        let second = generate("localhost");
+        write(&config, &second, SystemTime::now());
+        assert!(certificates.reload().unwrap());
+        assert_eq!(login(&address, &second).await, *second.cert.der());
+
+        let third = generate("localhost");
+        let mismatched = Generated {
+            cert: third.cert,
+            key_pair: first.key_pair,
+        };
+        write(&config, &mismatched, SystemTime::UNIX_EPOCH);
+        assert!(certificates.reload().is_err());
+        assert_eq!(login(&address, &second).await, *second.cert.der());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,45 +1,65 @@
+use crate::gamegen::DotColor;
+use crate::secret::SecretHash;
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
-use std::collections::HashSet;
+use std::fmt;
 
-type Id = u16;
+pub type Id = u16;
 pub type Offset = u16;
 
-#[derive(Eq)]
+/// Position of a field in field coordinates or of a dot in dot grid
+/// coordinates
+#[derive(Clone, Copy, Debug, Eq, Serialize, Deserialize)]
 pub struct Position(pub Offset, pub Offset);
 
-struct Dot<'a> {
-    fields: HashSet<&'a Field<'a>>,
-    position: Position,
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Dot {
+    pub id: Id,
+    pub color: DotColor,
+    /// Position in dot grid coordinates
+    pub position: Position,
 }
 
-struct Field<'a> {
-    assigned_dot: &'a Dot<'a>,
-    id: Id,
-    position: Position,
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Field {
+    pub assigned_dot: Option<Id>,
+    pub id: Id,
+    pub position: Position,
 }
 
-struct Player {
-    id: Id,
-    name: String,
-    passphrase: String,
-    // TODO: websocket
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Player {
+    pub id: Id,
+    pub name: String,
+    pub passphrase_hash: SecretHash,
 }
 
-/// Main class of this project. Manages all ressources
-struct Galaxy<'a> {
-    network: Network<'a>,
+/// A single move of a player, which assigns a field to another dot
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GameChange {
+    pub player: Id,
+    pub affected_field: Id,
+    pub new_association: Option<Id>,
+    pub old_association: Option<Id>,
 }
 
-/// Manages the Websocket connection
-struct Network<'a> {
-    galaxy: &'a Galaxy<'a>,
+/// A change together with the time it was made
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TimedChange {
+    /// Milliseconds since the Unix epoch
+    pub time: u64,
+    #[serde(flatten)]
+    pub change: GameChange,
 }
 
-struct GameChange<'a> {
-    player: &'a Player,
-    affected_field: &'a Field<'a>,
-    new_association: &'a Dot<'a>,
-    old_association: &'a Dot<'a>,
+/// Share of a player in the current state of a game
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Contribution {
+    pub player: Id,
+    /// Changes the player made
+    pub moves: usize,
+    /// Fields which are assigned by the player at the moment
+    pub fields: usize,
 }
 
 // Implement comparision operators for Position
@@ -64,3 +84,9 @@ impl PartialEq for Position {
         self.cmp(other) == Ordering::Equal
     }
 }
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}, {})", self.0, self.1)
+    }
+}